@@ -0,0 +1,145 @@
+fn main() {
+    // v8/qjs/rquickjs/boa/sandboxのうち二つ以上を有効にしたときは、InitOptions::engineでレンダラーごとに選ぶ。wasmを有効にしたときは他を使わない
+    println!("cargo::rustc-check-cfg=cfg(multi_engine)");
+    let engines = ["V8", "QJS", "RQUICKJS", "BOA", "SANDBOX"].iter().filter(|engine| std::env::var_os(format!("CARGO_FEATURE_{engine}")).is_some()).count();
+    if engines > 1 && std::env::var_os("CARGO_FEATURE_WASM").is_none() {
+        println!("cargo::rustc-cfg=multi_engine");
+    }
+    #[cfg(feature = "embedded-snapshot")]
+    embedded_snapshot::build();
+    #[cfg(feature = "sandbox")]
+    sandbox_guest::build();
+}
+
+// ビルド時に同梱のKaTeXとshim.jsからV8のスナップショットを作り、OUT_DIRに置く
+#[cfg(feature = "embedded-snapshot")]
+mod embedded_snapshot {
+    use deno_core_build as deno_core;
+    use std::{env, fs, path::Path};
+
+    include!("src/bundles.rs");
+
+    // KatexVersion::latest()と同じく、有効なうち最も新しい同梱版
+    macro_rules! latest_katex {
+        ($($feature:literal => $variant:ident, $version:literal, $path:literal;)*) => {{
+            #[allow(unused_mut, unused_assignments)]
+            let mut source = "";
+            $(
+                println!(concat!("cargo:rerun-if-changed=src/", $path));
+                #[cfg(feature = $feature)]
+                {
+                    source = include_str!(concat!("src/", $path));
+                }
+            )*
+            source
+        }};
+    }
+
+    pub fn build() {
+        println!("cargo:rerun-if-changed=src/shim.js");
+        println!("cargo:rerun-if-changed=src/svg.js");
+        println!("cargo:rerun-if-changed=src/bundles.rs");
+        println!("cargo:rerun-if-changed=src/contrib/mhchem.min.js");
+        // InitOptions::default()で組み立てるコードと同じ並びにする
+        let mut code = String::new();
+        code.push_str(katex_bundles!(latest_katex));
+        #[cfg(feature = "mhchem")]
+        {
+            code.push('\n');
+            code.push_str(include_str!("src/contrib/mhchem.min.js"));
+        }
+        code.push('\n');
+        code.push_str(include_str!("src/shim.js"));
+        #[cfg(feature = "png")]
+        {
+            code.push('\n');
+            code.push_str(include_str!("src/svg.js"));
+        }
+
+        let mut runtime = deno_core::JsRuntimeForSnapshot::new(deno_core::RuntimeOptions::default());
+        runtime.execute_script("katex", code).expect("failed to evaluate KaTeX for the embedded snapshot");
+        let snapshot = runtime.snapshot();
+        fs::write(Path::new(&env::var("OUT_DIR").unwrap()).join("katex.snapshot"), snapshot).unwrap();
+    }
+}
+
+// sandbox-guest/lib.rsをwasm32-wasip1向けにビルドし、OUT_DIR/katex-gdef-sandbox.wasmに置く。
+// 入れ子のパッケージはcargo packageで落とされるので、マニフェストはここで書き出す
+#[cfg(feature = "sandbox")]
+mod sandbox_guest {
+    use std::{env, fs, path::Path, process::Command};
+
+    const MANIFEST: &str = r#"[package]
+name = "katex-gdef-sandbox-guest"
+version = "0.1.0"
+edition = "2024"
+publish = false
+
+[lib]
+path = "{lib}"
+crate-type = ["cdylib"]
+
+[dependencies]
+boa_engine = { version = "0.20.0", default-features = false, features = ["annex-b"], optional = true }
+rquickjs = { version = "0.14.0", optional = true }
+
+[features]
+boa = ["dep:boa_engine"]
+rquickjs = ["dep:rquickjs"]
+
+[profile.release]
+opt-level = "s"
+panic = "abort"
+
+[workspace]
+"#;
+
+    pub fn build() {
+        println!("cargo:rerun-if-changed=sandbox-guest/lib.rs");
+        println!("cargo:rerun-if-env-changed=KATEX_GDEF_SANDBOX_WASM");
+        println!("cargo:rerun-if-env-changed=KATEX_GDEF_SANDBOX_GUEST");
+        println!("cargo:rerun-if-env-changed=WASI_SDK");
+        let out_dir = env::var("OUT_DIR").unwrap();
+        let wasm_path = Path::new(&out_dir).join("katex-gdef-sandbox.wasm");
+        // KATEX_GDEF_SANDBOX_WASM: 先にビルドしておいたゲスト。wasm32-wasip1のツールチェーンが無い環境で使う
+        let (guest, wasm) = if let Some(prebuilt) = env::var_os("KATEX_GDEF_SANDBOX_WASM") {
+            println!("cargo:rerun-if-changed={}", Path::new(&prebuilt).display());
+            ("prebuilt".to_string(), fs::read(&prebuilt).expect("failed to read KATEX_GDEF_SANDBOX_WASM"))
+        } else {
+            // KATEX_GDEF_SANDBOX_GUEST: "rquickjs"(既定)か"boa"。boaならCコンパイラもwasi-sdkも要らない
+            let guest =
+                env::var("KATEX_GDEF_SANDBOX_GUEST").ok().filter(|guest| !guest.is_empty()).unwrap_or_else(|| "rquickjs".to_string());
+            assert!(guest == "rquickjs" || guest == "boa", "KATEX_GDEF_SANDBOX_GUEST must be rquickjs or boa, not {guest}");
+            let guest_dir = Path::new(&out_dir).join("sandbox-guest");
+            fs::create_dir_all(&guest_dir).unwrap();
+            let lib = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("sandbox-guest/lib.rs");
+            let manifest = MANIFEST.replace("{lib}", &lib.display().to_string().replace('\\', "/"));
+            fs::write(guest_dir.join("Cargo.toml"), manifest).unwrap();
+            // 外側のビルドのフラグやラッパーはホスト向けなので、入れ子のcargoには渡さない。
+            // KaTeXは深く再帰するので、wasm-ldの既定(1MiB)より大きいスタックを持たせる
+            let status = Command::new(env::var("CARGO").unwrap_or_else(|_| "cargo".to_string()))
+                .args(["build", "--release", "--target", "wasm32-wasip1", "--features", &guest, "--manifest-path"])
+                .arg(guest_dir.join("Cargo.toml"))
+                .arg("--target-dir")
+                .arg(guest_dir.join("target"))
+                .env("CARGO_ENCODED_RUSTFLAGS", "-Clink-arg=-zstack-size=8388608")
+                .env_remove("RUSTFLAGS")
+                .env_remove("RUSTC_WRAPPER")
+                .env_remove("RUSTC_WORKSPACE_WRAPPER")
+                .env_remove("CARGO_TARGET_DIR")
+                .env_remove("CARGO_BUILD_TARGET")
+                .status()
+                .expect("failed to run cargo for the sandbox guest");
+            assert!(
+                status.success(),
+                "failed to build the sandbox guest; run `rustup target add wasm32-wasip1`, or set KATEX_GDEF_SANDBOX_WASM"
+            );
+            let built = guest_dir.join("target/wasm32-wasip1/release/katex_gdef_sandbox_guest.wasm");
+            (guest, fs::read(built).unwrap())
+        };
+        // キャッシュの見出しに入れ、別のゲストで作ったメモリのイメージを読まないようにする
+        let hash = wasm.iter().fold(0xcbf29ce484222325u64, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+        println!("cargo:rustc-env=KATEX_GDEF_SANDBOX_GUEST={guest} {hash:016x}");
+        fs::write(wasm_path, wasm).unwrap();
+    }
+}
@@ -0,0 +1,21 @@
+#ifndef KATEX_GDEF_V8_H
+#define KATEX_GDEF_V8_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Returns HTML to be freed with katex_free_string, or NULL on failure.
+ * On failure *error (if error is not NULL) receives a message that must also be freed. */
+char *katex_render(const char *latex, char **error);
+
+/* options is a KaTeX options object as JSON, e.g. {"displayMode":true}, or NULL for the defaults. */
+char *katex_render_opts(const char *latex, const char *options, char **error);
+
+void katex_free_string(char *string);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
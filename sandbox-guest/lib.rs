@@ -0,0 +1,94 @@
+// sandboxのfeatureでbuild.rsがwasm32-wasip1向けにビルドし、wasmtimeの中でKaTeXを動かすゲスト(src/sandbox.rs)。
+// ホストとは線形メモリ上のバイト列だけでやり取りする。ホストはallocで確保した場所にスクリプトを書いてevalを呼び、
+// 結果の文字列(例外ならそのメッセージ)をoutput_ptrとoutput_lenから読む
+use std::cell::RefCell;
+
+#[cfg(all(feature = "rquickjs", not(feature = "boa")))]
+mod engine {
+    use rquickjs::{CatchResultExt, Coerced, Context, Runtime};
+
+    // ランタイムはコンテキストより後に破棄されるよう一緒に持つ
+    pub struct Engine {
+        ctx: Context,
+        _runtime: Runtime,
+    }
+    impl Engine {
+        pub fn new() -> Result<Self, String> {
+            let runtime = Runtime::new().map_err(|error| error.to_string())?;
+            // build.rsが渡すスタック(8MiB)に収まるところで、QuickJSに深すぎる再帰を例外にさせる
+            runtime.set_max_stack_size(6 * 1024 * 1024);
+            let ctx = Context::full(&runtime).map_err(|error| error.to_string())?;
+            Ok(Engine { ctx, _runtime: runtime })
+        }
+        // 文字列でない結果は空にする
+        pub fn eval(&mut self, script: String) -> Result<String, String> {
+            self.ctx.with(|ctx| {
+                let value: rquickjs::Value = ctx.eval(script).catch(&ctx).map_err(|error| error.to_string())?;
+                Ok(if value.is_string() { value.get::<Coerced<String>>().map_err(|error| error.to_string())?.0 } else { String::new() })
+            })
+        }
+    }
+}
+
+#[cfg(feature = "boa")]
+mod engine {
+    use boa_engine::{Context, Source};
+
+    pub struct Engine {
+        ctx: Context,
+    }
+    impl Engine {
+        pub fn new() -> Result<Self, String> {
+            Ok(Engine { ctx: Context::default() })
+        }
+        // 文字列でない結果は空にする。対になっていないサロゲートは\uXXXXとして書き出す
+        pub fn eval(&mut self, script: String) -> Result<String, String> {
+            let value = self.ctx.eval(Source::from_bytes(&script)).map_err(|error| error.to_string())?;
+            Ok(value.as_string().map(|string| string.to_std_string_escaped()).unwrap_or_default())
+        }
+    }
+}
+
+thread_local! {
+    static ENGINE: RefCell<Option<engine::Engine>> = const { RefCell::new(None) };
+    static OUTPUT: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn alloc(len: usize) -> *mut u8 {
+    let mut buffer = Vec::<u8>::with_capacity(len);
+    let ptr = buffer.as_mut_ptr();
+    std::mem::forget(buffer);
+    ptr
+}
+
+// allocで確保してスクリプトを書いた場所を受け取って評価し、手放す。成功なら0、例外なら1を返す
+/// # Safety
+/// `ptr` must be returned by `alloc(len)`, hold `len` written bytes, and not be used again.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn eval(ptr: *mut u8, len: usize) -> i32 {
+    let bytes = unsafe { Vec::from_raw_parts(ptr, len, len) };
+    let result = match String::from_utf8(bytes) {
+        Ok(script) => ENGINE.with_borrow_mut(|engine| match engine {
+            Some(engine) => engine.eval(script),
+            None => engine::Engine::new().and_then(|created| engine.insert(created).eval(script)),
+        }),
+        Err(error) => Err(error.to_string()),
+    };
+    let (status, output) = match result {
+        Ok(output) => (0, output),
+        Err(message) => (1, message),
+    };
+    OUTPUT.set(output.into_bytes());
+    status
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn output_ptr() -> *const u8 {
+    OUTPUT.with_borrow(|output| output.as_ptr())
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn output_len() -> usize {
+    OUTPUT.with_borrow(|output| output.len())
+}
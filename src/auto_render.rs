@@ -0,0 +1,172 @@
+use std::borrow::Cow;
+
+// KaTeXのauto-render拡張(splitAtDelimiters)の移植
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delimiter {
+    pub left: Cow<'static, str>,
+    pub right: Cow<'static, str>,
+    pub display: bool,
+}
+impl Delimiter {
+    pub fn new(left: impl Into<Cow<'static, str>>, right: impl Into<Cow<'static, str>>, display: bool) -> Self {
+        Delimiter { left: left.into(), right: right.into(), display }
+    }
+    // `$$`は`$`より先に調べる必要がある
+    pub fn defaults() -> Vec<Delimiter> {
+        vec![
+            Delimiter::new("$$", "$$", true),
+            Delimiter::new("$", "$", false),
+            Delimiter::new("\\(", "\\)", false),
+            Delimiter::new("\\[", "\\]", true),
+        ]
+    }
+}
+
+// split_mathの設定。code_spansならMarkdownのコードスパン(`...`)の中は数式として扱わない
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DelimiterConfig {
+    pub delimiters: Vec<Delimiter>,
+    pub code_spans: bool,
+}
+impl DelimiterConfig {
+    pub fn new(delimiters: Vec<Delimiter>) -> Self {
+        DelimiterConfig { delimiters, ..Default::default() }
+    }
+    pub fn code_spans(mut self, code_spans: bool) -> Self {
+        self.code_spans = code_spans;
+        self
+    }
+}
+impl Default for DelimiterConfig {
+    fn default() -> Self {
+        DelimiterConfig { delimiters: Delimiter::defaults(), code_spans: true }
+    }
+}
+
+// rawは区切り文字を含む元の文字列。Textの中のエスケープされた`\$`はそのまま残る
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment<'a> {
+    Text(&'a str),
+    Math { latex: &'a str, raw: &'a str, display: bool },
+}
+
+// 文章を地の文と数式に分ける。閉じられていない区切り文字から後ろは地の文になる
+pub fn split_math<'a>(text: &'a str, config: &DelimiterConfig) -> Vec<Segment<'a>> {
+    split(text, &config.delimiters, config.code_spans)
+}
+
+pub(crate) fn split_at_delimiters<'a>(text: &'a str, delimiters: &[Delimiter]) -> Vec<Segment<'a>> {
+    split(text, delimiters, false)
+}
+
+fn split<'a>(mut text: &'a str, delimiters: &[Delimiter], code_spans: bool) -> Vec<Segment<'a>> {
+    let mut segments = Vec::new();
+    while let Some((index, delimiter)) = find_left_delimiter(text, delimiters, code_spans) {
+        let math_start = index + delimiter.left.len();
+        let Some(end) = find_end_of_math(&delimiter.right, text, math_start) else { break };
+        if index > 0 {
+            segments.push(Segment::Text(&text[..index]));
+        }
+        let math_end = end + delimiter.right.len();
+        let raw = &text[index..math_end];
+        // amsmathの環境は\begin{...}〜\end{...}ごとKaTeXに渡す
+        let latex = if delimiter.left.starts_with("\\begin{") { raw } else { &text[math_start..end] };
+        segments.push(Segment::Math { latex, raw, display: delimiter.display });
+        text = &text[math_end..];
+    }
+    if !text.is_empty() {
+        segments.push(Segment::Text(text));
+    }
+    segments
+}
+
+// 奇数個のバックスラッシュが直前にある区切り文字(`\$`など)はエスケープされているとみなす
+// code_spansなら、コードスパンは丸ごと飛ばす
+fn find_left_delimiter<'d>(text: &str, delimiters: &'d [Delimiter], code_spans: bool) -> Option<(usize, &'d Delimiter)> {
+    let mut backslashes = 0;
+    let mut index = 0;
+    while let Some(c) = text[index..].chars().next() {
+        if backslashes % 2 == 0 {
+            let rest = &text[index..];
+            if let Some(delimiter) = delimiters.iter().find(|delimiter| !delimiter.left.is_empty() && rest.starts_with(&*delimiter.left)) {
+                return Some((index, delimiter));
+            }
+            if code_spans && c == '`' {
+                index += code_span_len(rest);
+                backslashes = 0;
+                continue;
+            }
+        }
+        backslashes = if c == '\\' { backslashes + 1 } else { 0 };
+        index += c.len_utf8();
+    }
+    None
+}
+
+// 同じ長さのバッククォートの並びで閉じるまで。閉じられていなければ開きの並びだけ
+fn code_span_len(text: &str) -> usize {
+    let open = text.len() - text.trim_start_matches('`').len();
+    let mut index = open;
+    while let Some(start) = text[index..].find('`').map(|start| start + index) {
+        let close = text[start..].len() - text[start..].trim_start_matches('`').len();
+        if close == open {
+            return start + close;
+        }
+        index = start + close;
+    }
+    open
+}
+
+fn find_end_of_math(right: &str, text: &str, start: usize) -> Option<usize> {
+    let mut brace_level = 0;
+    let mut chars = text[start..].char_indices().map(|(index, c)| (index + start, c));
+    while let Some((index, c)) = chars.next() {
+        if brace_level <= 0 && text[index..].starts_with(right) {
+            return Some(index);
+        }
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '{' => brace_level += 1,
+            '}' => brace_level -= 1,
+            _ => (),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split_default(text: &str) -> Vec<Segment<'_>> {
+        split_math(text, &DelimiterConfig::default())
+    }
+
+    #[test]
+    fn escaped_delimiters_stay_in_text() {
+        assert_eq!(split_default(r"costs \$5 and \$6"), [Segment::Text(r"costs \$5 and \$6")]);
+        assert_eq!(split_default(r"\$5 or $x$"), [Segment::Text(r"\$5 or "), Segment::Math { latex: "x", raw: "$x$", display: false }]);
+        // \\の後の$はエスケープされていない
+        assert_eq!(split_default(r"\\$x$"), [Segment::Text(r"\\"), Segment::Math { latex: "x", raw: "$x$", display: false }]);
+    }
+
+    #[test]
+    fn escapes_and_braces_inside_math() {
+        assert_eq!(split_default(r"$a\$b$"), [Segment::Math { latex: r"a\$b", raw: r"$a\$b$", display: false }]);
+        assert_eq!(split_default(r"$\text{$}$"), [Segment::Math { latex: r"\text{$}", raw: r"$\text{$}$", display: false }]);
+        assert_eq!(split_default(r"\[x\]"), [Segment::Math { latex: "x", raw: r"\[x\]", display: true }]);
+    }
+
+    #[test]
+    fn unclosed_delimiters_and_code_spans() {
+        assert_eq!(split_default(r"$x and \(y"), [Segment::Text(r"$x and \(y")]);
+        assert_eq!(split_default("`$x$` $y$"), [Segment::Text("`$x$` "), Segment::Math { latex: "y", raw: "$y$", display: false }]);
+        let config = DelimiterConfig::default().code_spans(false);
+        assert_eq!(
+            split_math("`$x$`", &config),
+            [Segment::Text("`"), Segment::Math { latex: "x", raw: "$x$", display: false }, Segment::Text("`")]
+        );
+    }
+}
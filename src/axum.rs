@@ -0,0 +1,66 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    Json, Router,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::post,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, MacroValue, Options, Renderer, UsedFonts, font::font_extract, katex_renderer};
+
+// POST /renderの本文。optionsはKaTeXのオプションと同じ形のJSON
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RenderRequest {
+    pub latex: String,
+    #[serde(default)]
+    pub options: Options,
+    #[serde(default)]
+    pub macros: BTreeMap<String, MacroValue>,
+}
+
+// macrosは渡したマクロにその数式で定義されたものを足したもの
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenderResponse {
+    pub html: String,
+    pub macros: BTreeMap<String, MacroValue>,
+    pub fonts: UsedFonts,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+// 共有ワーカーで描画するPOST /render
+pub fn router() -> Router {
+    router_with_renderer(katex_renderer())
+}
+
+pub fn router_with_renderer(renderer: &'static Renderer) -> Router {
+    Router::new().route("/render", post(render)).with_state(renderer)
+}
+
+async fn render(State(renderer): State<&'static Renderer>, Json(request): Json<RenderRequest>) -> Response {
+    let RenderRequest { latex, options, mut macros } = request;
+    match renderer.render_with_opts_async(&latex, &options, &mut macros).await {
+        Ok(html) => Json(RenderResponse { fonts: font_extract(&html), html, macros }).into_response(),
+        Err(error) => error_response(error),
+    }
+}
+
+// 数式の誤りは400、混んでいるときや打ち切ったときは503、それ以外は500
+pub fn error_response(error: Error) -> Response {
+    let status = match &error {
+        Error::KaTeXError { .. } => StatusCode::BAD_REQUEST,
+        Error::Busy | Error::Timeout(_) | Error::OutOfMemory => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    let error = match error {
+        Error::KaTeXError { message, .. } => message,
+        error => error.to_string(),
+    };
+    (status, Json(ErrorResponse { error })).into_response()
+}
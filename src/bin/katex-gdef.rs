@@ -0,0 +1,167 @@
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, Read, Write},
+    path::PathBuf,
+    process::ExitCode,
+};
+
+use katex_gdef_v8::{Error, InitOptions, MacroStore, MacroValue, Options, Renderer};
+use serde::{Deserialize, Serialize};
+
+const USAGE: &str = "Usage: katex-gdef [OPTIONS] [FILE]...
+
+Renders LaTeX read from each FILE (or stdin when no FILE or `-` is given) and prints the HTML.
+Macros defined with \\gdef in one input are available in the following ones.
+
+With --serve-stdio, reads one JSON request per line from stdin instead and writes one JSON response per line:
+  {\"id\": 1, \"latex\": \"x^2\", \"options\": {\"displayMode\": true}}  ->  {\"id\": 1, \"html\": \"...\"}
+\"id\" is echoed back, \"options\" and \"macros\" are optional and \"macros\" replaces the macros carried so far.
+
+Options:
+  -d, --display              Render in display mode
+  -o, --output <FILE>        Write the HTML to FILE instead of stdout
+  -m, --macros-file <FILE>   Load macros from a MacroStore JSON file, or from a TeX preamble otherwise
+  -c, --cache <FILE>         Cache the engine snapshot in FILE
+  -s, --serve-stdio          Serve newline-delimited JSON requests on stdin
+  -h, --help                 Print this help";
+
+#[derive(Default)]
+struct Args {
+    display: bool,
+    output: Option<PathBuf>,
+    macros_file: Option<PathBuf>,
+    cache: Option<PathBuf>,
+    serve_stdio: bool,
+    inputs: Vec<String>,
+}
+
+fn parse_args() -> Result<Option<Args>, String> {
+    let mut args = Args::default();
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        let mut value = |name: &str| iter.next().ok_or_else(|| format!("{name} requires a value"));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-d" | "--display" => args.display = true,
+            "-o" | "--output" => args.output = Some(value(&arg)?.into()),
+            "-m" | "--macros-file" => args.macros_file = Some(value(&arg)?.into()),
+            "-c" | "--cache" => args.cache = Some(value(&arg)?.into()),
+            "-s" | "--serve-stdio" => args.serve_stdio = true,
+            "--" => args.inputs.extend(iter.by_ref()),
+            option if option.starts_with('-') && option != "-" => return Err(format!("unknown option: {option}")),
+            _ => args.inputs.push(arg),
+        }
+    }
+    if args.serve_stdio && (!args.inputs.is_empty() || args.output.is_some()) {
+        return Err("--serve-stdio takes neither FILE nor --output".to_string());
+    }
+    if args.inputs.is_empty() {
+        args.inputs.push("-".to_string());
+    }
+    Ok(Some(args))
+}
+
+fn read_input(input: &str) -> std::io::Result<String> {
+    if input == "-" {
+        let mut latex = String::new();
+        std::io::stdin().read_to_string(&mut latex)?;
+        Ok(latex)
+    } else {
+        std::fs::read_to_string(input)
+    }
+}
+
+#[derive(Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: serde_json::Value,
+    latex: String,
+    options: Option<Options>,
+    macros: Option<BTreeMap<String, MacroValue>>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Response {
+    Html { id: serde_json::Value, html: String },
+    Error { id: serde_json::Value, error: String },
+}
+
+// 一行に一つの要求を読み、一行に一つの応答を書く。読めない行にもidをnullにして応答する
+fn serve_stdio(renderer: &Renderer, options: &Options, macros: &mut BTreeMap<String, MacroValue>) -> Result<(), Error> {
+    let mut stdout = std::io::stdout().lock();
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => {
+                if let Some(request_macros) = request.macros {
+                    *macros = request_macros;
+                }
+                match renderer.render_with_opts(request.latex.trim(), request.options.as_ref().unwrap_or(options), macros) {
+                    Ok(html) => Response::Html { id: request.id, html },
+                    Err(Error::KaTeXError { message, .. }) => Response::Error { id: request.id, error: message },
+                    Err(error) => Response::Error { id: request.id, error: error.to_string() },
+                }
+            }
+            Err(error) => Response::Error { id: serde_json::Value::Null, error: error.to_string() },
+        };
+        serde_json::to_writer(&mut stdout, &response)?;
+        stdout.write_all(b"\n")?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+fn run(args: Args) -> Result<(), Error> {
+    let init = match &args.cache {
+        Some(cache) => InitOptions::default().cache(cache),
+        None => InitOptions::default(),
+    };
+    let renderer = Renderer::try_new(init)?;
+    let mut macros: BTreeMap<String, MacroValue> = BTreeMap::new();
+    if let Some(path) = &args.macros_file {
+        if path.extension().is_some_and(|extension| extension == "json") {
+            macros = MacroStore::load(path)?.macros;
+        } else {
+            renderer.macros_from_preamble(&std::fs::read_to_string(path)?, &mut macros)?;
+        }
+    }
+    let options = Options::builder().display(args.display).build();
+    if args.serve_stdio {
+        return serve_stdio(&renderer, &options, &mut macros);
+    }
+    let mut html = String::new();
+    for input in &args.inputs {
+        html.push_str(&renderer.render_with_opts(read_input(input)?.trim(), &options, &mut macros)?);
+        html.push('\n');
+    }
+    match &args.output {
+        Some(path) => std::fs::write(path, html)?,
+        None => print!("{html}"),
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(message) => {
+            eprintln!("katex-gdef: {message}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("katex-gdef: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    process::ExitCode,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use katex_gdef_v8::{
+    Error, InitOptions, MacroStore, MacroValue, Options, Renderer, UsedFonts,
+    axum::{ErrorResponse, RenderRequest, RenderResponse, error_response},
+    font_extract,
+};
+use serde::{Deserialize, Serialize};
+
+const USAGE: &str = "Usage: katexd [OPTIONS]
+
+Serves a JSON HTTP API for rendering LaTeX with KaTeX.
+
+Routes:
+  POST   /render                    Render one formula with the macros in the request
+  POST   /batch                     Render several formulas in one batch
+  POST   /namespaces/<key>/render   Render with the macros of namespace <key> and keep the ones it defines
+  POST   /namespaces/<key>/batch    Same as /namespaces/<key>/render for a batch
+  GET    /namespaces/<key>/macros   Print the macros of namespace <key>
+  DELETE /namespaces/<key>          Forget namespace <key>
+
+Options:
+  -a, --addr <ADDR>          Listen on ADDR (default 127.0.0.1:3000)
+  -m, --macros-file <FILE>   Start every namespace from the macros in a MacroStore JSON file, or a TeX preamble otherwise
+  -s, --macros-dir <DIR>     Save each namespace as DIR/<key>.json and load it again after a restart
+  -c, --cache <FILE>         Cache the engine snapshot in FILE
+  -h, --help                 Print this help";
+
+struct Args {
+    addr: String,
+    macros_file: Option<PathBuf>,
+    macros_dir: Option<PathBuf>,
+    cache: Option<PathBuf>,
+}
+
+fn parse_args() -> Result<Option<Args>, String> {
+    let mut args = Args { addr: "127.0.0.1:3000".to_string(), macros_file: None, macros_dir: None, cache: None };
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        let mut value = |name: &str| iter.next().ok_or_else(|| format!("{name} requires a value"));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-a" | "--addr" => args.addr = value(&arg)?,
+            "-m" | "--macros-file" => args.macros_file = Some(value(&arg)?.into()),
+            "-s" | "--macros-dir" => args.macros_dir = Some(value(&arg)?.into()),
+            "-c" | "--cache" => args.cache = Some(value(&arg)?.into()),
+            option => return Err(format!("unknown option: {option}")),
+        }
+    }
+    Ok(Some(args))
+}
+
+// 同じ名前空間への描画は一つずつ順に行う
+type Namespace = Arc<tokio::sync::Mutex<BTreeMap<String, MacroValue>>>;
+
+struct Namespaces {
+    renderer: &'static Renderer,
+    initial: BTreeMap<String, MacroValue>,
+    dir: Option<PathBuf>,
+    macros: Mutex<HashMap<String, Namespace>>,
+}
+
+enum ApiError {
+    InvalidKey(String),
+    Render(Error),
+}
+
+impl From<Error> for ApiError {
+    fn from(error: Error) -> Self {
+        ApiError::Render(error)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        match self {
+            ApiError::InvalidKey(key) => {
+                (StatusCode::BAD_REQUEST, Json(ErrorResponse { error: format!("invalid namespace key: {key}") })).into_response()
+            }
+            ApiError::Render(error) => error_response(error),
+        }
+    }
+}
+
+impl Namespaces {
+    // 初めて使う名前空間は、保存したものがあればそれを、無ければ起動時のマクロを持つ
+    fn get(&self, key: &str) -> Result<Namespace, ApiError> {
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(ApiError::InvalidKey(key.to_string()));
+        }
+        let mut namespaces = self.macros.lock().unwrap();
+        if let Some(macros) = namespaces.get(key) {
+            return Ok(macros.clone());
+        }
+        let macros = match self.path(key) {
+            Some(path) if path.exists() => MacroStore::load(path)?.macros,
+            _ => self.initial.clone(),
+        };
+        let macros = Arc::new(tokio::sync::Mutex::new(macros));
+        namespaces.insert(key.to_string(), macros.clone());
+        Ok(macros)
+    }
+    fn path(&self, key: &str) -> Option<PathBuf> {
+        self.dir.as_ref().map(|dir| dir.join(format!("{key}.json")))
+    }
+    fn save(&self, key: &str, macros: &BTreeMap<String, MacroValue>) -> Result<(), Error> {
+        match self.path(key) {
+            Some(path) => MacroStore::new(macros.clone()).save(path),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct BatchRequest {
+    items: Vec<BatchItem>,
+    #[serde(default)]
+    macros: BTreeMap<String, MacroValue>,
+}
+
+#[derive(Deserialize)]
+struct BatchItem {
+    latex: String,
+    #[serde(default)]
+    options: Options,
+}
+
+// 数式ごとに成功したか失敗したか。fontsは成功した数式の全体
+#[derive(Serialize)]
+struct BatchResponse {
+    results: Vec<BatchResult>,
+    macros: BTreeMap<String, MacroValue>,
+    fonts: UsedFonts,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum BatchResult {
+    Html { html: String },
+    Error { error: String },
+}
+
+async fn render(State(namespaces): State<Arc<Namespaces>>, Json(request): Json<RenderRequest>) -> Result<Json<RenderResponse>, ApiError> {
+    let RenderRequest { latex, options, mut macros } = request;
+    let html = namespaces.renderer.render_with_opts_async(&latex, &options, &mut macros).await?;
+    Ok(Json(RenderResponse { fonts: font_extract(&html), html, macros }))
+}
+
+// リクエストのmacrosは使わず、名前空間のマクロで描画する
+async fn render_in(
+    State(namespaces): State<Arc<Namespaces>>,
+    Path(key): Path<String>,
+    Json(request): Json<RenderRequest>,
+) -> Result<Json<RenderResponse>, ApiError> {
+    let namespace = namespaces.get(&key)?;
+    let mut macros = namespace.lock().await;
+    let before = macros.clone();
+    let html = namespaces.renderer.render_with_opts_async(&request.latex, &request.options, &mut macros).await?;
+    if *macros != before {
+        namespaces.save(&key, &macros)?;
+    }
+    Ok(Json(RenderResponse { fonts: font_extract(&html), html, macros: macros.clone() }))
+}
+
+async fn batch(State(namespaces): State<Arc<Namespaces>>, Json(request): Json<BatchRequest>) -> Result<Json<BatchResponse>, ApiError> {
+    Ok(Json(render_batch(namespaces.renderer, request.items, request.macros).await?))
+}
+
+async fn batch_in(
+    State(namespaces): State<Arc<Namespaces>>,
+    Path(key): Path<String>,
+    Json(request): Json<BatchRequest>,
+) -> Result<Json<BatchResponse>, ApiError> {
+    let namespace = namespaces.get(&key)?;
+    let mut macros = namespace.lock().await;
+    let response = render_batch(namespaces.renderer, request.items, macros.clone()).await?;
+    if response.macros != *macros {
+        *macros = response.macros.clone();
+        namespaces.save(&key, &macros)?;
+    }
+    Ok(Json(response))
+}
+
+// render_batchはワーカーを待って止まるので、非同期のスレッドの外で呼ぶ
+async fn render_batch(
+    renderer: &'static Renderer,
+    items: Vec<BatchItem>,
+    mut macros: BTreeMap<String, MacroValue>,
+) -> Result<BatchResponse, Error> {
+    tokio::task::spawn_blocking(move || {
+        let items: Vec<(&str, Options)> = items.iter().map(|item| (item.latex.as_str(), item.options.clone())).collect();
+        let mut fonts = UsedFonts::default();
+        let results = renderer
+            .render_batch(&items, &mut macros)?
+            .into_iter()
+            .map(|result| match result {
+                Ok(html) => {
+                    fonts.merge(font_extract(&html));
+                    BatchResult::Html { html }
+                }
+                Err(Error::KaTeXError { message, .. }) => BatchResult::Error { error: message },
+                Err(error) => BatchResult::Error { error: error.to_string() },
+            })
+            .collect();
+        Ok(BatchResponse { results, macros, fonts })
+    })
+    .await
+    .map_err(|error| Error::Io(std::io::Error::other(error)))?
+}
+
+async fn macros_of(
+    State(namespaces): State<Arc<Namespaces>>,
+    Path(key): Path<String>,
+) -> Result<Json<BTreeMap<String, MacroValue>>, ApiError> {
+    Ok(Json(namespaces.get(&key)?.lock().await.clone()))
+}
+
+// 保存したファイルも消す
+async fn forget(State(namespaces): State<Arc<Namespaces>>, Path(key): Path<String>) -> Result<StatusCode, ApiError> {
+    namespaces.get(&key)?;
+    namespaces.macros.lock().unwrap().remove(&key);
+    if let Some(path) = namespaces.path(&key)
+        && path.exists()
+    {
+        std::fs::remove_file(path).map_err(Error::from)?;
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn run(args: Args) -> Result<(), Error> {
+    let init = match &args.cache {
+        Some(cache) => InitOptions::default().cache(cache),
+        None => InitOptions::default(),
+    };
+    let renderer: &'static Renderer = Box::leak(Box::new(Renderer::try_new(init)?));
+    let mut initial = BTreeMap::new();
+    if let Some(path) = &args.macros_file {
+        if path.extension().is_some_and(|extension| extension == "json") {
+            initial = MacroStore::load(path)?.macros;
+        } else {
+            renderer.macros_from_preamble(&std::fs::read_to_string(path)?, &mut initial)?;
+        }
+    }
+    if let Some(dir) = &args.macros_dir {
+        std::fs::create_dir_all(dir)?;
+    }
+    let namespaces = Arc::new(Namespaces { renderer, initial, dir: args.macros_dir, macros: Mutex::default() });
+    let app = Router::new()
+        .route("/render", post(render))
+        .route("/batch", post(batch))
+        .route("/namespaces/{key}/render", post(render_in))
+        .route("/namespaces/{key}/batch", post(batch_in))
+        .route("/namespaces/{key}/macros", get(macros_of))
+        .route("/namespaces/{key}", axum::routing::delete(forget))
+        .with_state(namespaces);
+    let listener = tokio::net::TcpListener::bind(&args.addr).await?;
+    eprintln!("katexd: listening on {}", listener.local_addr()?);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(message) => {
+            eprintln!("katexd: {message}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    match run(args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("katexd: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use std::{
+    collections::BTreeMap,
+    io::Read,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use katex_gdef_v8::{Delimiter, Error, InitOptions, MacroStore, MacroValue, Options, Renderer, UsedFonts, font_extract};
+use serde_json::{Value, json};
+
+// book.tomlの例:
+// [preprocessor.katex-gdef]
+// macros-file = "preamble.tex"    # MacroStoreのJSONかTeXのプリアンブル
+// cache = "katex.snapshot"
+// fonts-file = "katex-fonts.json" # 使われたフォントを書き出す
+struct Config {
+    macros_file: Option<PathBuf>,
+    cache: Option<PathBuf>,
+    fonts_file: Option<PathBuf>,
+}
+impl Config {
+    fn from_context(context: &Value) -> Config {
+        let root = Path::new(context["root"].as_str().unwrap_or("."));
+        let table = &context["config"]["preprocessor"]["katex-gdef"];
+        let path = |key: &str| table[key].as_str().map(|path| root.join(path));
+        Config { macros_file: path("macros-file"), cache: path("cache"), fonts_file: path("fonts-file") }
+    }
+}
+
+struct Preprocessor {
+    renderer: Renderer,
+    delimiters: Vec<Delimiter>,
+    options: Options,
+    macros: BTreeMap<String, MacroValue>,
+    fonts: BTreeMap<String, UsedFonts>,
+}
+impl Preprocessor {
+    // 章は目次の順に描画し、前の章で\gdefしたマクロを次の章に持ち越す
+    fn process_items(&mut self, items: &mut Value) -> Result<(), Error> {
+        let Some(items) = items.as_array_mut() else { return Ok(()) };
+        for item in items {
+            let Some(chapter) = item.get_mut("Chapter") else { continue };
+            if let Some(content) = chapter["content"].as_str() {
+                let rendered = self.process_chapter(content)?;
+                let path = chapter["path"].as_str().or(chapter["name"].as_str()).unwrap_or_default().to_string();
+                self.fonts.insert(path, font_extract(&rendered));
+                chapter["content"] = Value::String(rendered);
+            }
+            if let Some(sub_items) = chapter.get_mut("sub_items") {
+                self.process_items(sub_items)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn process_chapter(&mut self, content: &str) -> Result<String, Error> {
+        let mut rendered = String::with_capacity(content.len());
+        for (is_code, part) in split_code(content) {
+            if is_code {
+                rendered.push_str(part);
+            } else {
+                rendered.push_str(&self.renderer.render_document(part, &self.delimiters, &self.options, &mut self.macros)?);
+            }
+        }
+        Ok(rendered)
+    }
+
+    fn fonts_json(&self) -> Value {
+        let all = self.fonts.values().fold(UsedFonts::default(), |all, fonts| all.union(fonts));
+        let chapters: BTreeMap<&str, Vec<&str>> = self.fonts.iter().map(|(path, fonts)| (path.as_str(), fonts.names().collect())).collect();
+        json!({ "fonts": all.names().collect::<Vec<_>>(), "chapters": chapters })
+    }
+}
+
+// (コードかどうか, 部分)。フェンスで囲まれたコードブロックとインラインコードの中の`$`は数式として扱わない
+fn split_code(markdown: &str) -> Vec<(bool, &str)> {
+    let mut parts = Vec::new();
+    let (mut text_start, mut index) = (0, 0);
+    while index < markdown.len() {
+        let rest = &markdown[index..];
+        let at_line_start = index == 0 || markdown.as_bytes()[index - 1] == b'\n';
+        let code_len = if at_line_start { fenced_block_len(rest) } else { None }.or_else(|| inline_code_len(rest));
+        match code_len {
+            Some(len) => {
+                if text_start < index {
+                    parts.push((false, &markdown[text_start..index]));
+                }
+                parts.push((true, &rest[..len]));
+                index += len;
+                text_start = index;
+            }
+            // 閉じられていないバッククォートの並びはまとめて飛ばす
+            None => {
+                let ticks = rest.len() - rest.trim_start_matches('`').len();
+                index += if ticks > 0 { ticks } else { rest.chars().next().map_or(1, char::len_utf8) };
+            }
+        }
+    }
+    if text_start < markdown.len() {
+        parts.push((false, &markdown[text_start..]));
+    }
+    parts
+}
+
+// 閉じるフェンスが無ければ文書の最後までをコードとする
+fn fenced_block_len(rest: &str) -> Option<usize> {
+    let indent = rest.len() - rest.trim_start_matches(' ').len();
+    let fence_char = rest[indent..].chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let fence_len = rest[indent..].len() - rest[indent..].trim_start_matches(fence_char).len();
+    if indent > 3 || fence_len < 3 {
+        return None;
+    }
+    let mut offset = rest.find('\n').map_or(rest.len(), |end| end + 1);
+    while offset < rest.len() {
+        let line_end = rest[offset..].find('\n').map_or(rest.len(), |end| offset + end + 1);
+        let line = rest[offset..line_end].trim();
+        if line.len() >= fence_len && line.chars().all(|c| c == fence_char) {
+            return Some(line_end);
+        }
+        offset = line_end;
+    }
+    Some(rest.len())
+}
+
+// 同じ長さのバッククォートの並びで閉じられていなければコードではない
+fn inline_code_len(rest: &str) -> Option<usize> {
+    let ticks = rest.len() - rest.trim_start_matches('`').len();
+    if ticks == 0 {
+        return None;
+    }
+    let mut offset = ticks;
+    while let Some(start) = rest[offset..].find('`') {
+        let start = offset + start;
+        let run = rest[start..].len() - rest[start..].trim_start_matches('`').len();
+        if run == ticks {
+            return Some(start + run);
+        }
+        offset = start + run;
+    }
+    None
+}
+
+fn run() -> Result<(), Error> {
+    let mut input = String::new();
+    std::io::stdin().read_to_string(&mut input)?;
+    let (context, mut book): (Value, Value) = serde_json::from_str(&input)?;
+    let config = Config::from_context(&context);
+    let init = match &config.cache {
+        Some(cache) => InitOptions::default().cache(cache),
+        None => InitOptions::default(),
+    };
+    let mut preprocessor = Preprocessor {
+        renderer: Renderer::try_new(init)?,
+        delimiters: Delimiter::defaults(),
+        options: Options::default(),
+        macros: BTreeMap::new(),
+        fonts: BTreeMap::new(),
+    };
+    if let Some(path) = &config.macros_file {
+        if path.extension().is_some_and(|extension| extension == "json") {
+            preprocessor.macros = MacroStore::load(path)?.macros;
+        } else {
+            preprocessor.renderer.macros_from_preamble(&std::fs::read_to_string(path)?, &mut preprocessor.macros)?;
+        }
+    }
+    // mdBook 0.4は"sections"、0.5は"items"
+    for key in ["sections", "items"] {
+        if let Some(items) = book.get_mut(key) {
+            preprocessor.process_items(items)?;
+        }
+    }
+    if let Some(path) = &config.fonts_file {
+        std::fs::write(path, serde_json::to_vec_pretty(&preprocessor.fonts_json())?)?;
+    }
+    serde_json::to_writer(std::io::stdout(), &book)?;
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // KaTeXのHTMLはhtmlレンダラーでしか意味を持たない
+    if let [command, renderer] = args.as_slice()
+        && command == "supports"
+    {
+        return if renderer == "html" { ExitCode::SUCCESS } else { ExitCode::FAILURE };
+    }
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("mdbook-katex-gdef: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use std::path::Path;
+
+use boa_engine::{Context, Source};
+use serde::de::DeserializeOwned;
+
+use crate::{Core, EngineOptions, Interrupt};
+
+pub type Error = BoaError;
+
+// boa_engine::JsErrorはSendではないので、メッセージにしてから返す
+#[derive(Debug, thiserror::Error)]
+pub enum BoaError {
+    #[error("JavaScript Error: {0}")]
+    Js(String),
+    #[error("IO Error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("JSON Error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+impl From<boa_engine::JsError> for BoaError {
+    fn from(error: boa_engine::JsError) -> Self {
+        BoaError::Js(error.to_string())
+    }
+}
+
+// Rustだけで書かれたBoaで動かす。C/C++のツールチェーンが要らない代わりに、V8やQuickJSより遅い
+// Contextは大きいので、multi::Engineの中で場所を取らないよう箱に入れる
+pub(crate) struct Engine {
+    ctx: Box<Context>,
+}
+
+impl Core for Engine {
+    type Error = BoaError;
+
+    fn new(code: &str, _options: &EngineOptions) -> Result<Self, Self::Error> {
+        let mut ctx = Box::new(Context::default());
+        ctx.eval(Source::from_bytes(code))?;
+        Ok(Engine { ctx })
+    }
+
+    // Boaにはスナップショットもバイトコードの書き出しも無いので毎回評価する
+    fn new_with_snapshot(code: &str, _path: &Path, _header: &str, options: &EngineOptions) -> Result<Self, Self::Error> {
+        Engine::new(code, options)
+    }
+
+    fn engine_id(_options: &EngineOptions) -> String {
+        "boa".to_string()
+    }
+
+    fn exec<O: DeserializeOwned>(&mut self, function: &str, input: &str) -> Result<O, Self::Error> {
+        let script = format!("{function}({input})");
+        let result = {
+            let _span = span!("katex.js", function);
+            self.ctx.eval(Source::from_bytes(&script))?
+        };
+        // 対になっていないサロゲートは\uXXXXとして書き出されるので、JSONの文字列の中ではそのまま読める
+        let result = result.to_string(&mut self.ctx)?.to_std_string_escaped();
+        let _span = span!("katex.deserialize");
+        Ok(serde_json::from_str(&result)?)
+    }
+
+    // Boaには別のスレッドから実行を止める仕組みが無い。時間切れの描画も最後まで実行される
+    fn interrupt_handle(&mut self) -> Interrupt {
+        Box::new(|| ())
+    }
+
+    fn clear_interrupt(&mut self) {}
+}
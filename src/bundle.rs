@@ -0,0 +1,46 @@
+include!("./bundles.rs");
+
+macro_rules! katex_version {
+    ($($feature:literal => $variant:ident, $version:literal, $path:literal;)*) => {
+        // 同梱しているKaTeXのバージョン。バージョンはbundles.rsの一覧から作る
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[non_exhaustive]
+        pub enum KatexVersion {
+            $(
+                #[cfg(feature = $feature)]
+                $variant,
+            )*
+        }
+        impl KatexVersion {
+            // 有効なfeatureのうち最も新しいもの
+            #[allow(unused_mut, unused_assignments)]
+            pub const fn latest() -> Option<KatexVersion> {
+                let mut latest = None;
+                $(
+                    #[cfg(feature = $feature)]
+                    {
+                        latest = Some(KatexVersion::$variant);
+                    }
+                )*
+                latest
+            }
+            pub const fn version(self) -> &'static str {
+                match self {
+                    $(
+                        #[cfg(feature = $feature)]
+                        KatexVersion::$variant => $version,
+                    )*
+                }
+            }
+            pub(crate) fn source(self) -> &'static str {
+                match self {
+                    $(
+                        #[cfg(feature = $feature)]
+                        KatexVersion::$variant => include_str!(concat!("./", $path)),
+                    )*
+                }
+            }
+        }
+    };
+}
+katex_bundles!(katex_version);
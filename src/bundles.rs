@@ -0,0 +1,9 @@
+// 同梱するKaTeXの一覧。古い順に、有効にするfeature、KatexVersionのバリアント、バージョン、srcからのパスを並べる。
+// 新しいバージョンはCargo.tomlのfeatureとここに一行ずつ足す。build.rsもinclude!で同じ一覧を読む
+macro_rules! katex_bundles {
+    ($callback:ident) => {
+        $callback! {
+            "katex-0_16" => V0_16, "0.16.21", "katex-0.16.min.js";
+        }
+    };
+}
@@ -0,0 +1,115 @@
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+static SNAPSHOT_HITS: AtomicU64 = AtomicU64::new(0);
+static SNAPSHOT_MISSES: AtomicU64 = AtomicU64::new(0);
+
+// (キャッシュから読めた数, 作り直した数)
+pub(crate) fn snapshot_stats() -> (u64, u64) {
+    (SNAPSHOT_HITS.load(Ordering::Relaxed), SNAPSHOT_MISSES.load(Ordering::Relaxed))
+}
+
+// キャッシュファイルの先頭に付ける見出し。作ったときと環境やコードが変わっていたら作り直す
+pub(crate) fn header(katex_version: &str, engine: &str, code: &str) -> String {
+    format!(
+        "katex-gdef-v8 {}\nkatex {katex_version}\nengine {engine}\ncode {:016x}\ncompression {}\n\n",
+        env!("CARGO_PKG_VERSION"),
+        fingerprint(code.as_bytes()),
+        if cfg!(feature = "zstd") { "zstd" } else { "none" }
+    )
+}
+
+// 見出しが一致しない・ファイルが無いときはNone
+fn read(path: &Path, header: &str) -> io::Result<Option<Vec<u8>>> {
+    let mut bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error),
+    };
+    if !bytes.starts_with(header.as_bytes()) {
+        return Ok(None);
+    }
+    bytes.drain(..header.len());
+    #[cfg(feature = "zstd")]
+    let bytes = zstd::decode_all(bytes.as_slice())?;
+    Ok(Some(bytes))
+}
+
+// 複数のプロセスが同じキャッシュを同時に作らないよう、ロックを取ってから読み直して、無ければ作る
+pub(crate) fn read_or_create<E: From<io::Error>>(
+    path: &Path,
+    header: &str,
+    create: impl FnOnce() -> Result<Vec<u8>, E>,
+) -> Result<Vec<u8>, E> {
+    let _span = span!("katex.snapshot_load", path = %path.display());
+    if let Some(bytes) = read(path, header)? {
+        SNAPSHOT_HITS.fetch_add(1, Ordering::Relaxed);
+        return Ok(bytes);
+    }
+    let _lock = lock(path);
+    if let Some(bytes) = read(path, header)? {
+        SNAPSHOT_HITS.fetch_add(1, Ordering::Relaxed);
+        return Ok(bytes);
+    }
+    SNAPSHOT_MISSES.fetch_add(1, Ordering::Relaxed);
+    let bytes = create()?;
+    write(path, header, &bytes)?;
+    Ok(bytes)
+}
+
+// ロックファイルを作れない・ロックに対応しないファイルシステムではロックなしで進める
+fn lock(path: &Path) -> Option<File> {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".lock");
+    let file = OpenOptions::new().create(true).truncate(false).write(true).open(path.with_file_name(file_name)).ok()?;
+    file.lock().ok()?;
+    Some(file)
+}
+
+// 同じディレクトリの一時ファイルに書いてからrenameし、書きかけのキャッシュが読まれないようにする
+fn write(path: &Path, header: &str, data: &[u8]) -> io::Result<()> {
+    #[cfg(feature = "zstd")]
+    let data = &zstd::encode_all(data, 0)?;
+    let mut bytes = Vec::with_capacity(header.len() + data.len());
+    bytes.extend_from_slice(header.as_bytes());
+    bytes.extend_from_slice(data);
+    let temp = temp_path(path);
+    let result = fs::write(&temp, bytes).and_then(|()| fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.subsec_nanos());
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(format!(".tmp-{}-{nanos}", process::id()));
+    path.with_file_name(file_name)
+}
+
+// ユーザーごとのキャッシュディレクトリ(XDG_CACHE_HOME, ~/Library/Caches, %LOCALAPPDATA%)の下に、クレートのバージョンとエンジンで分けて置く
+pub(crate) fn default_path_for(engine: &str) -> Option<PathBuf> {
+    let base = if cfg!(target_os = "windows") {
+        PathBuf::from(env::var_os("LOCALAPPDATA")?)
+    } else if cfg!(target_os = "macos") {
+        PathBuf::from(env::var_os("HOME")?).join("Library").join("Caches")
+    } else if let Some(xdg) = env::var_os("XDG_CACHE_HOME").filter(|xdg| Path::new(xdg).is_absolute()) {
+        PathBuf::from(xdg)
+    } else {
+        PathBuf::from(env::var_os("HOME")?).join(".cache")
+    };
+    Some(base.join("katex-gdef-v8").join(env!("CARGO_PKG_VERSION")).join(format!("{engine}-snapshot")))
+}
+
+// FNV-1a。Rustのバージョンが変わっても同じ値になるハッシュが欲しい
+fn fingerprint(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
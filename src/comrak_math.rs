@@ -0,0 +1,108 @@
+use std::{collections::BTreeMap, fmt, sync::Mutex};
+
+use comrak::{
+    Arena, Node,
+    adapters::CodefenceRendererAdapter,
+    nodes::{NodeMath, NodeValue, Sourcepos},
+};
+
+use crate::{Error, MacroValue, Options, Renderer, UsedFonts, font::font_extract, katex_renderer};
+
+// comrakのmath拡張(math_dollars, math_latex, math_code)の数式をKaTeXで描画する。
+// 一つの文書の中ではマクロを持ち越すので、文書ごとに一つ作って使う
+pub struct ComrakMath<'r> {
+    renderer: &'r Renderer,
+    options: Options,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    macros: BTreeMap<String, MacroValue>,
+    fonts: UsedFonts,
+    errors: Vec<Error>,
+}
+
+impl ComrakMath<'static> {
+    pub fn new() -> Self {
+        ComrakMath::with_renderer(katex_renderer())
+    }
+}
+
+impl Default for ComrakMath<'static> {
+    fn default() -> Self {
+        ComrakMath::new()
+    }
+}
+
+impl<'r> ComrakMath<'r> {
+    pub fn with_renderer(renderer: &'r Renderer) -> Self {
+        ComrakMath { renderer, options: Options::default(), state: Mutex::default() }
+    }
+    pub fn options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+    pub fn macros(self, macros: BTreeMap<String, MacroValue>) -> Self {
+        self.state.lock().unwrap().macros = macros;
+        self
+    }
+    pub fn defined_macros(&self) -> BTreeMap<String, MacroValue> {
+        self.state.lock().unwrap().macros.clone()
+    }
+    pub fn used_fonts(&self) -> UsedFonts {
+        self.state.lock().unwrap().fonts
+    }
+    // コードフェンスのプラグインとして描画に失敗した数式のエラー
+    pub fn take_errors(&self) -> Vec<Error> {
+        std::mem::take(&mut self.state.lock().unwrap().errors)
+    }
+
+    fn render(&self, latex: &str, display: bool) -> Result<String, Error> {
+        let options = Options { display_mode: display, ..self.options.clone() };
+        let mut state = self.state.lock().unwrap();
+        let html = self.renderer.render_with_opts(latex, &options, &mut state.macros)?;
+        state.fonts.merge(font_extract(&html));
+        Ok(html)
+    }
+
+    // 数式のノードと```mathのコードブロックを、描画したHTMLのRawノードに置き換える
+    pub fn render_math(&self, root: Node<'_>) -> Result<(), Error> {
+        for node in root.descendants() {
+            let mut ast = node.data_mut();
+            let math = match &ast.value {
+                NodeValue::Math(NodeMath { display_math, literal, .. }) => Some((literal.clone(), *display_math)),
+                NodeValue::CodeBlock(block) if block.info.split_whitespace().next() == Some("math") => Some((block.literal.clone(), true)),
+                _ => None,
+            };
+            if let Some((latex, display)) = math {
+                ast.value = NodeValue::Raw(self.render(&latex, display)?);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn markdown_to_html(&self, markdown: &str, options: &comrak::Options) -> Result<String, Error> {
+        let arena = Arena::new();
+        let root = comrak::parse_document(&arena, markdown, options);
+        self.render_math(root)?;
+        let mut html = String::new();
+        comrak::format_html(root, options, &mut html).map_err(|_| std::io::Error::other("failed to format HTML"))?;
+        Ok(html)
+    }
+}
+
+// RenderPluginsのcodefence_renderersに"math"として登録すると、```mathのブロックを描画する。
+// 描画に失敗したときは元のコードブロックのまま出し、エラーはtake_errors()で受け取る
+impl CodefenceRendererAdapter for ComrakMath<'_> {
+    fn write(&self, output: &mut dyn fmt::Write, _lang: &str, _meta: &str, code: &str, _sourcepos: Option<Sourcepos>) -> fmt::Result {
+        match self.render(code, true) {
+            Ok(html) => output.write_str(&html),
+            Err(error) => {
+                self.state.lock().unwrap().errors.push(error);
+                let code = code.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+                write!(output, "<pre><code class=\"language-math\">{code}</code></pre>")
+            }
+        }
+    }
+}
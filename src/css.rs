@@ -0,0 +1,270 @@
+use std::collections::BTreeSet;
+#[cfg(feature = "standalone")]
+use std::path::Path;
+
+#[cfg(feature = "standalone")]
+use base64::Engine as _;
+
+use crate::{
+    Options,
+    font::{UsedFonts, font_extract},
+};
+
+// katex.cssから不要な規則を削るために、数式の中で使われているclassを集める
+pub fn class_extract(html: &str) -> BTreeSet<String> {
+    let mut classes = BTreeSet::new();
+    // 数式の外ではNone、中では外側のspan.katexから数えたspanの深さ
+    let mut depth: Option<usize> = None;
+    let mut tokenizer = html5gum::Tokenizer::new(html);
+    while let Some(Ok(token)) = tokenizer.next() {
+        match token {
+            html5gum::Token::StartTag(tag) => {
+                let class_list = tag.attributes.get(b"class".as_slice()).and_then(|s| std::str::from_utf8(s).ok()).unwrap_or_default();
+                let is_span = tag.name.to_ascii_lowercase() == b"span" && !tag.self_closing;
+                if depth.is_none() && is_span && class_list.split_whitespace().any(|class| class == "katex" || class == "katex-display") {
+                    depth = Some(0);
+                }
+                let Some(depth) = &mut depth else { continue };
+                classes.extend(class_list.split_whitespace().map(str::to_string));
+                if is_span {
+                    *depth += 1;
+                }
+            }
+            html5gum::Token::EndTag(tag) if tag.name.to_ascii_lowercase() == b"span" => {
+                if let Some(current) = depth {
+                    depth = current.checked_sub(1).filter(|&current| current > 0);
+                }
+            }
+            _ => (),
+        }
+    }
+    classes
+}
+
+// 数式で使われるclassとフォントに必要な規則だけをkatex.cssから取り出し、<style>としてHTMLの前に付ける
+pub fn inline_css(html: &str, katex_css: &str) -> String {
+    let css = prune_css(katex_css, &class_extract(html), &font_extract(html));
+    format!("<style>{css}</style>{html}")
+}
+
+// 使われていないclassを含むセレクタと、使われていないフォントの@font-faceを落とす
+pub fn prune_css(css: &str, classes: &BTreeSet<String>, fonts: &UsedFonts) -> String {
+    let mut pruned = String::new();
+    let mut rest = css;
+    while let Some((prelude, body, after)) = next_rule(rest) {
+        rest = after;
+        let prelude = prelude.trim();
+        match body {
+            None => {
+                pruned.push_str(prelude);
+                pruned.push(';');
+            }
+            Some(body) if prelude.starts_with("@font-face") => {
+                if font_face_family(body).is_none_or(|name| fonts.contains(name)) {
+                    pruned.push_str(&format!("{prelude}{{{body}}}"));
+                }
+            }
+            Some(body) if prelude.starts_with("@media") || prelude.starts_with("@supports") => {
+                let inner = prune_css(body, classes, fonts);
+                if !inner.is_empty() {
+                    pruned.push_str(&format!("{prelude}{{{inner}}}"));
+                }
+            }
+            Some(body) if prelude.starts_with('@') => pruned.push_str(&format!("{prelude}{{{body}}}")),
+            Some(body) => {
+                let selectors: Vec<&str> = split_selectors(prelude)
+                    .into_iter()
+                    .filter(|selector| selector_classes(selector).all(|class| classes.contains(class)))
+                    .collect();
+                if !selectors.is_empty() {
+                    pruned.push_str(&format!("{}{{{body}}}", selectors.join(",")));
+                }
+            }
+        }
+    }
+    pruned
+}
+
+// (前置き, {}の中身, 残り)。`;`で終わるat規則は中身がNone
+fn next_rule(css: &str) -> Option<(&str, Option<&str>, &str)> {
+    let css = skip_space_and_comments(css);
+    if css.is_empty() {
+        return None;
+    }
+    let (mut depth, mut body_start, mut quote) = (0, 0, None);
+    let mut chars = css.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match (quote, c) {
+            (Some(q), '\\') if q != '/' => {
+                chars.next();
+            }
+            (Some('/'), '*') if css[index..].starts_with("*/") => {
+                chars.next();
+                quote = None;
+            }
+            (Some(q), c) if q == c && q != '/' => quote = None,
+            (Some(_), _) => (),
+            (None, '/') if css[index..].starts_with("/*") => quote = Some('/'),
+            (None, '"' | '\'') => quote = Some(c),
+            (None, ';') if depth == 0 => return Some((&css[..index], None, &css[index + 1..])),
+            (None, '{') => {
+                if depth == 0 {
+                    body_start = index + 1;
+                }
+                depth += 1;
+            }
+            (None, '}') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some((&css[..body_start - 1], Some(&css[body_start..index]), &css[index + 1..]));
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+fn skip_space_and_comments(mut css: &str) -> &str {
+    loop {
+        css = css.trim_start();
+        let Some(comment) = css.strip_prefix("/*") else { return css };
+        css = comment.split_once("*/").map_or("", |(_, rest)| rest);
+    }
+}
+
+// 括弧の中(:is(.a, .b)など)のカンマでは分けない
+fn split_selectors(prelude: &str) -> Vec<&str> {
+    let (mut selectors, mut depth, mut start) = (Vec::new(), 0, 0);
+    for (index, c) in prelude.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                selectors.push(prelude[start..index].trim());
+                start = index + 1;
+            }
+            _ => (),
+        }
+    }
+    selectors.push(prelude[start..].trim());
+    selectors
+}
+
+// セレクタが要求するclass。:not(...)の中は無くても一致するので数えない
+fn selector_classes(selector: &str) -> impl Iterator<Item = &str> {
+    let mut required = Vec::new();
+    let mut rest = selector;
+    while let Some(index) = rest.find(['.', ':']) {
+        let after = &rest[index + 1..];
+        if rest[index..].starts_with(":not(") {
+            let close = after.find(')').map_or(after.len(), |close| close + 1);
+            rest = &after[close..];
+            continue;
+        }
+        if rest.as_bytes()[index] == b'.' {
+            let end = after.find(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_')).unwrap_or(after.len());
+            required.push(&after[..end]);
+            rest = &after[end..];
+        } else {
+            rest = after;
+        }
+    }
+    required.into_iter()
+}
+
+// "src:url(fonts/KaTeX_AMS-Regular.woff2)"からフォントファイル名を取り出す
+fn font_face_family(body: &str) -> Option<&str> {
+    let src = &body[body.find("url(")?..];
+    let name = &src[src.find("KaTeX_")?..];
+    let end = name.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-')).unwrap_or(name.len());
+    Some(&name[..end])
+}
+
+// KaTeXの配布物(katex.min.cssとfonts/)から、必要なCSSとフォントを埋め込んだ単体のHTMLページを作る
+#[cfg(feature = "standalone")]
+pub fn standalone_html(html: &str, katex_css: &str, font_dir: &Path) -> String {
+    let css = embed_fonts(&prune_css(katex_css, &class_extract(html), &font_extract(html)), font_dir);
+    format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<style>{css}</style>\n</head>\n<body>\n{html}\n</body>\n</html>\n")
+}
+
+// @font-faceのsrcを、woff2を読み込んだdata URIに置き換える。ファイルが無ければ元のままにする
+#[cfg(feature = "standalone")]
+fn embed_fonts(css: &str, font_dir: &Path) -> String {
+    let mut embedded = String::new();
+    let mut rest = css;
+    while let Some((prelude, body, after)) = next_rule(rest) {
+        rest = after;
+        let prelude = prelude.trim();
+        match body {
+            None => {
+                embedded.push_str(prelude);
+                embedded.push(';');
+            }
+            Some(body) if prelude.starts_with("@font-face") => {
+                let font = font_face_family(body).and_then(|name| std::fs::read(font_dir.join(format!("{name}.woff2"))).ok());
+                match (font, body.find("src:")) {
+                    (Some(font), Some(src)) => {
+                        let src_end = body[src..].find(';').map_or(body.len(), |end| src + end);
+                        let data = base64::engine::general_purpose::STANDARD.encode(font);
+                        let embedded_src = format!("src:url(data:font/woff2;base64,{data}) format(\"woff2\")");
+                        embedded.push_str(&format!("{prelude}{{{}{embedded_src}{}}}", &body[..src], &body[src_end..]));
+                    }
+                    _ => embedded.push_str(&format!("{prelude}{{{body}}}")),
+                }
+            }
+            Some(body) => embedded.push_str(&format!("{prelude}{{{body}}}")),
+        }
+    }
+    embedded
+}
+
+// css_variablesで描画したHTMLに出てくる--katex-color-*と--katex-error-colorに、元の色を入れる:rootの規則。
+// テーマを変えるときは、これより後の規則で同じ名前を上書きする
+pub fn css_variables_stylesheet(html: &str, options: &Options) -> String {
+    let mut names = BTreeSet::new();
+    for (start, _) in html.match_indices("var(--katex-") {
+        let name = &html[start + 4..];
+        let end = name.find(|c: char| !(c.is_ascii_alphanumeric() || c == '-')).unwrap_or(name.len());
+        names.insert(&name[..end]);
+    }
+    let mut css = String::from(":root{");
+    for name in names {
+        let value = match name.strip_prefix("--katex-color-") {
+            // 3、4、6、8桁の16進は#を付けて戻し、それ以外は色の名前
+            Some(color) if matches!(color.len(), 3 | 4 | 6 | 8) && color.chars().all(|c| c.is_ascii_hexdigit()) => format!("#{color}"),
+            Some(color) => color.to_string(),
+            None if name == "--katex-error-color" => options.error_color.to_string(),
+            None => continue,
+        };
+        css.push_str(&format!("{name}:{value};"));
+    }
+    css.push('}');
+    css
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prune_drops_unused_selectors_and_fonts() {
+        let css = "/* c */.katex{a:b}.katex .mfrac{c:d}.x,.katex:not(.y){e:f}@charset \"utf-8\";\
+            @font-face{font-family:KaTeX_AMS;src:url(fonts/KaTeX_AMS-Regular.woff2)}\
+            @font-face{font-family:KaTeX_Main;src:url(fonts/KaTeX_Main-Regular.woff2)}\
+            @media print{.mfrac{g:h}}@media screen{.katex{content:\"}\"}}";
+        let classes = BTreeSet::from(["katex".to_string()]);
+        let fonts = UsedFonts::from_names(["KaTeX_Main-Regular"]);
+        assert_eq!(
+            prune_css(css, &classes, &fonts),
+            ".katex{a:b}.katex:not(.y){e:f}@charset \"utf-8\";\
+            @font-face{font-family:KaTeX_Main;src:url(fonts/KaTeX_Main-Regular.woff2)}@media screen{.katex{content:\"}\"}}"
+        );
+    }
+
+    #[test]
+    fn selectors_split_outside_parentheses() {
+        assert_eq!(split_selectors(".a:is(.b, .c), .d"), [".a:is(.b, .c)", ".d"]);
+        assert_eq!(selector_classes(".katex .mfrac>.frac-line:not(.x)").collect::<Vec<_>>(), ["katex", "mfrac", "frac-line"]);
+    }
+}
@@ -0,0 +1,382 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{Error, MacroValue, Options, Renderer, katex_renderer};
+
+// 別々に描画する数式にまたがって、別行立ての数式に番号を振る。
+// 一つの文書の中ではマクロと番号を持ち越すので、文書ごとに一つ作って使う
+pub struct DocumentRenderer<'r> {
+    renderer: &'r Renderer,
+    options: Options,
+    macros: BTreeMap<String, MacroValue>,
+    style: NumberStyle,
+    format: String,
+    next_number: u32,
+    last_number: Option<String>,
+    // \labelの名前と、\refで出す番号
+    labels: BTreeMap<String, String>,
+    // まだ定義されていない\labelを参照したときに入れた目印
+    forward: Vec<ForwardReference>,
+}
+
+struct ForwardReference {
+    placeholder: String,
+    label: String,
+    eqref: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NumberStyle {
+    #[default]
+    Arabic,
+    LowerRoman,
+    UpperRoman,
+    // a, b, ..., z, aa, ab, ...
+    LowerAlpha,
+    UpperAlpha,
+}
+impl NumberStyle {
+    pub fn format(self, number: u32) -> String {
+        match self {
+            NumberStyle::Arabic => number.to_string(),
+            NumberStyle::LowerRoman => roman(number),
+            NumberStyle::UpperRoman => roman(number).to_uppercase(),
+            NumberStyle::LowerAlpha => alpha(number),
+            NumberStyle::UpperAlpha => alpha(number).to_uppercase(),
+        }
+    }
+}
+
+// 0はローマ数字にならないのでそのまま出す
+fn roman(mut number: u32) -> String {
+    if number == 0 {
+        return "0".to_string();
+    }
+    const DIGITS: [(u32, &str); 13] = [
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+    let mut roman = String::new();
+    for (value, digit) in DIGITS {
+        while number >= value {
+            roman.push_str(digit);
+            number -= value;
+        }
+    }
+    roman
+}
+
+fn alpha(mut number: u32) -> String {
+    if number == 0 {
+        return "0".to_string();
+    }
+    let mut letters = Vec::new();
+    while number > 0 {
+        number -= 1;
+        letters.push(b'a' + (number % 26) as u8);
+        number /= 26;
+    }
+    letters.iter().rev().map(|&letter| letter as char).collect()
+}
+
+impl DocumentRenderer<'static> {
+    pub fn new() -> Self {
+        DocumentRenderer::with_renderer(katex_renderer())
+    }
+}
+
+impl Default for DocumentRenderer<'static> {
+    fn default() -> Self {
+        DocumentRenderer::new()
+    }
+}
+
+impl<'r> DocumentRenderer<'r> {
+    pub fn with_renderer(renderer: &'r Renderer) -> Self {
+        DocumentRenderer {
+            renderer,
+            options: Options::default(),
+            macros: BTreeMap::new(),
+            style: NumberStyle::Arabic,
+            format: "({})".to_string(),
+            next_number: 1,
+            last_number: None,
+            labels: BTreeMap::new(),
+            forward: Vec::new(),
+        }
+    }
+    pub fn options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+    pub fn macros(mut self, macros: BTreeMap<String, MacroValue>) -> Self {
+        self.macros = macros;
+        self
+    }
+    pub fn number_style(mut self, style: NumberStyle) -> Self {
+        self.style = style;
+        self
+    }
+    // 番号の書式。{}が番号に置き換わり、\tag*の中身になる。既定は"({})"
+    pub fn number_format(mut self, format: impl Into<String>) -> Self {
+        self.format = format.into();
+        self
+    }
+    // 章ごとに番号を振り直すときなど
+    pub fn first_number(mut self, number: u32) -> Self {
+        self.next_number = number;
+        self
+    }
+    pub fn defined_macros(&self) -> &BTreeMap<String, MacroValue> {
+        &self.macros
+    }
+    pub fn into_macros(self) -> BTreeMap<String, MacroValue> {
+        self.macros
+    }
+    // 次に振る番号
+    pub fn next_number(&self) -> u32 {
+        self.next_number
+    }
+    // 最後に振った番号(書式を当てた後のもの)
+    pub fn last_number(&self) -> Option<&str> {
+        self.last_number.as_deref()
+    }
+
+    // \refで出す番号。number_formatは当てず、\eqrefでは括弧で囲む
+    pub fn label(&self, name: &str) -> Option<&str> {
+        self.labels.get(name).map(String::as_str)
+    }
+    pub fn labels(&self) -> &BTreeMap<String, String> {
+        &self.labels
+    }
+    // 後ろで定義された\labelへの参照は、描画した時点では目印の文字列になっている。
+    // 文書を全て描画した後に、その文書のHTMLをこれに通して番号に置き換える。最後まで定義されなかったものは??になる
+    pub fn resolve_references(&self, html: &str) -> String {
+        let mut html = html.to_string();
+        for reference in &self.forward {
+            let text = match self.labels.get(&reference.label) {
+                Some(number) if reference.eqref => format!("({number})"),
+                Some(number) => number.clone(),
+                None => "??".to_string(),
+            };
+            let text = text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;");
+            html = html.replace(&reference.placeholder, &text);
+        }
+        html
+    }
+    // 最後まで定義されなかった\labelの名前を、名前順に一つずつ
+    pub fn unresolved_references(&self) -> Vec<&str> {
+        let labels: BTreeSet<&str> =
+            self.forward.iter().map(|reference| reference.label.as_str()).filter(|label| !self.labels.contains_key(*label)).collect();
+        labels.into_iter().collect()
+    }
+
+    pub fn render(&mut self, latex: &str, display: bool) -> Result<String, Error> {
+        if display { self.render_display(latex) } else { self.render_inline(latex) }
+    }
+
+    // 文中の\labelは番号が無いので登録しない
+    pub fn render_inline(&mut self, latex: &str) -> Result<String, Error> {
+        let options = Options { display_mode: false, ..self.options.clone() };
+        let (latex, _, forward) = self.resolve(latex);
+        let html = self.renderer.render_with_opts(&latex, &options, &mut self.macros)?;
+        self.forward.extend(forward);
+        Ok(html)
+    }
+
+    // \tag、\notag、\nonumberがある数式と、KaTeXが自分で番号を振る環境には番号を振らない。
+    // \labelは振った番号か\tagの中身を指す。描画に失敗したときは番号を進めず、\labelも登録しない
+    pub fn render_display(&mut self, latex: &str) -> Result<String, Error> {
+        let options = Options { display_mode: true, ..self.options.clone() };
+        let (latex, labels, forward) = self.resolve(latex);
+        let (html, number) = if numbered(&latex) {
+            // \labelには番号だけを入れ、書式は\tag*にだけ当てる
+            let label = self.style.format(self.next_number);
+            let number = self.format.replace("{}", &label);
+            // 末尾が%のコメントでも\tag*が消えないように改行を挟む
+            let html = self.renderer.render_with_opts(&format!("{latex}\n\\tag*{{{number}}}"), &options, &mut self.macros)?;
+            self.next_number += 1;
+            self.last_number = Some(number);
+            (html, Some(label))
+        } else {
+            let html = self.renderer.render_with_opts(&latex, &options, &mut self.macros)?;
+            (html, user_tag(&latex))
+        };
+        if let Some(number) = number {
+            self.labels.extend(labels.into_iter().map(|label| (label, number.clone())));
+        }
+        self.forward.extend(forward);
+        Ok(html)
+    }
+
+    // \labelを取り除いて名前を集め、\refと\eqrefを番号のテキストに置き換える
+    fn resolve(&self, latex: &str) -> (String, Vec<String>, Vec<ForwardReference>) {
+        let mut labels = Vec::new();
+        let latex = replace_command(latex, "label", |name| {
+            labels.push(name.to_string());
+            String::new()
+        });
+        let mut forward = Vec::new();
+        let mut reference = |name: &str, eqref: bool| match self.labels.get(name) {
+            Some(number) if eqref => format!("\\text{{({number})}}"),
+            Some(number) => format!("\\text{{{number}}}"),
+            None => {
+                // \textの中で一つの文字列になるように英数字だけにする
+                let placeholder = format!("katexgdefref{}x", self.forward.len() + forward.len());
+                forward.push(ForwardReference { placeholder: placeholder.clone(), label: name.to_string(), eqref });
+                format!("\\text{{{placeholder}}}")
+            }
+        };
+        let latex = replace_command(&latex, "eqref", |name| reference(name, true));
+        let latex = replace_command(&latex, "ref", |name| reference(name, false));
+        (latex, labels, forward)
+    }
+}
+
+// \tag{P}と\tag*{P}のP
+fn user_tag(latex: &str) -> Option<String> {
+    let mut tag = None;
+    replace_command(latex, "tag", |argument| {
+        tag.get_or_insert_with(|| argument.to_string());
+        String::new()
+    });
+    tag
+}
+
+fn numbered(latex: &str) -> bool {
+    const ENVIRONMENTS: [&str; 5] = ["equation", "align", "alignat", "gather", "multline"];
+    !["tag", "notag", "nonumber"].iter().any(|name| has_command(latex, name))
+        && !ENVIRONMENTS.iter().any(|environment| latex.contains(&format!("\\begin{{{environment}}}")))
+}
+
+// \tagは\tag*も含むが、\tagsのような別の名前は含まない
+fn has_command(latex: &str, name: &str) -> bool {
+    let command = format!("\\{name}");
+    latex.match_indices(&command).any(|(index, _)| is_command_at(latex, index, command.len()))
+}
+
+fn is_command_at(latex: &str, index: usize, len: usize) -> bool {
+    let escaped = latex[..index].chars().rev().take_while(|&c| c == '\\').count() % 2 == 1;
+    !escaped && !latex[index + len..].starts_with(|c: char| c.is_ascii_alphabetic())
+}
+
+// \name{argument}と\name*{argument}をf(argument)に置き換える
+fn replace_command(latex: &str, name: &str, mut f: impl FnMut(&str) -> String) -> String {
+    let command = format!("\\{name}");
+    let mut output = String::with_capacity(latex.len());
+    let mut rest = 0;
+    for (index, _) in latex.match_indices(&command) {
+        if index < rest || !is_command_at(latex, index, command.len()) {
+            continue;
+        }
+        let start = index + command.len();
+        let Some(end) = argument_end(latex, start) else { continue };
+        let argument = latex[start..end].trim_start_matches(|c: char| c.is_whitespace() || c == '*');
+        let argument = &argument[1..argument.len() - 1];
+        output.push_str(&latex[rest..index]);
+        output.push_str(&f(argument));
+        rest = end;
+    }
+    output.push_str(&latex[rest..]);
+    output
+}
+
+// 空白と*を飛ばした後の、中括弧で囲まれた引数の終わり
+fn argument_end(latex: &str, start: usize) -> Option<usize> {
+    let mut chars = latex[start..].char_indices().skip_while(|&(_, c)| c.is_whitespace() || c == '*');
+    let (open, '{') = chars.next()? else { return None };
+    let mut depth = 0;
+    let mut escaped = false;
+    for (offset, c) in latex[start + open..].char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(start + open + offset + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn number_styles() {
+        assert_eq!(NumberStyle::LowerRoman.format(1994), "mcmxciv");
+        assert_eq!(NumberStyle::UpperRoman.format(4), "IV");
+        assert_eq!(NumberStyle::LowerAlpha.format(26), "z");
+        assert_eq!(NumberStyle::UpperAlpha.format(28), "AB");
+        assert_eq!(NumberStyle::LowerAlpha.format(0), "0");
+    }
+
+    #[test]
+    fn commands_are_found_only_unescaped() {
+        assert!(numbered(r"x \tags"));
+        // \\の後のtagは命令ではない
+        assert!(numbered(r"x \\tag{1}"));
+        assert!(!numbered(r"\text{\\}\tag*{A}"));
+        assert!(!numbered(r"\begin{align}x\end{align}"));
+        assert_eq!(user_tag(r"x \tag*{ {A} }"), Some(" {A} ".to_string()));
+        assert_eq!(replace_command(r"a\label{x}\labels{y}\\\label{z}", "label", |_| String::new()), r"a\labels{y}\\");
+        assert_eq!(replace_command(r"\ref{a\}b}", "ref", |name| format!("[{name}]")), r"[a\}b]");
+    }
+
+    #[test]
+    fn numbers_continue_across_renders() {
+        let mut document = DocumentRenderer::new().number_style(NumberStyle::UpperRoman).first_number(3);
+        document.render_inline("w").unwrap();
+        document.render_display("x").unwrap();
+        document.render_display(r"y \tag{T}").unwrap();
+        document.render_display(r"z \nonumber").unwrap();
+        assert_eq!(document.last_number(), Some("(III)"));
+        assert_eq!(document.next_number(), 4);
+    }
+
+    #[test]
+    fn references_across_renders() {
+        let mut document = DocumentRenderer::new().number_style(NumberStyle::UpperRoman).first_number(3);
+        let forward = document.render_inline(r"\eqref{b}").unwrap();
+        document.render_display(r"x \label{a}").unwrap();
+        document.render_display(r"y \tag{T} \label{b}").unwrap();
+        assert_eq!(document.label("a"), Some("III"));
+        assert_eq!(document.label("b"), Some("T"));
+        assert!(document.render_inline(r"\ref{a}").unwrap().contains("III"));
+        assert!(document.resolve_references(&forward).contains("(T)"));
+    }
+
+    #[test]
+    fn labels_keep_the_number_without_the_format() {
+        let mut document = DocumentRenderer::new().number_format("[{}]");
+        document.render_display(r"x \label{a}").unwrap();
+        assert_eq!(document.last_number(), Some("[1]"));
+        assert_eq!(document.label("a"), Some("1"));
+        let html = document.render_inline(r"\ref{a}, \eqref{a}").unwrap();
+        assert!(html.contains(">1<") && html.contains("(1)") && !html.contains("[1]"));
+    }
+
+    #[test]
+    fn unresolved_references_are_listed_once() {
+        let mut document = DocumentRenderer::new();
+        document.render_inline(r"\ref{b} \ref{a} \eqref{b} \ref{c}").unwrap();
+        document.render_display(r"x \label{c}").unwrap();
+        assert_eq!(document.unresolved_references(), ["a", "b"]);
+    }
+}
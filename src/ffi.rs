@@ -0,0 +1,67 @@
+use std::{
+    collections::BTreeMap,
+    ffi::{CStr, CString, c_char},
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
+
+use crate::{Options, katex_renderer};
+
+// Cから呼ぶための関数。返した文字列はkatex_free_stringで解放する。
+// 失敗したときはNULLを返し、errorがNULLでなければエラーメッセージを書き込む(これも解放が必要)
+
+// optionsはKaTeXのオプションと同じ形のJSON({"displayMode":true}など)。NULLなら既定値
+/// # Safety
+/// `latex` and `options` must be NULL or point to NUL-terminated strings, and `error` must be NULL or writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn katex_render_opts(latex: *const c_char, options: *const c_char, error: *mut *mut c_char) -> *mut c_char {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| -> Result<String, String> {
+        let latex = unsafe { c_str(latex) }.ok_or("latex is NULL or not UTF-8")?;
+        let options: Options = match unsafe { c_str(options) } {
+            Some(options) => serde_json::from_str(options).map_err(|error| error.to_string())?,
+            None if options.is_null() => Options::default(),
+            None => return Err("options is not UTF-8".to_string()),
+        };
+        katex_renderer().render_with_opts(latex, &options, &mut BTreeMap::new()).map_err(|error| error.to_string())
+    }))
+    .unwrap_or_else(|_| Err("panicked while rendering".to_string()));
+    match result {
+        Ok(html) => into_c_string(html),
+        Err(message) => {
+            if !error.is_null() {
+                unsafe { *error = into_c_string(message) };
+            }
+            ptr::null_mut()
+        }
+    }
+}
+
+/// # Safety
+/// Same as [`katex_render_opts`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn katex_render(latex: *const c_char, error: *mut *mut c_char) -> *mut c_char {
+    unsafe { katex_render_opts(latex, ptr::null(), error) }
+}
+
+/// # Safety
+/// `string` must be NULL or a string returned by this module that has not been freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn katex_free_string(string: *mut c_char) {
+    if !string.is_null() {
+        drop(unsafe { CString::from_raw(string) });
+    }
+}
+
+unsafe fn c_str<'a>(string: *const c_char) -> Option<&'a str> {
+    if string.is_null() {
+        return None;
+    }
+    unsafe { CStr::from_ptr(string) }.to_str().ok()
+}
+
+// 途中のNULは取り除く
+fn into_c_string(string: String) -> *mut c_char {
+    CString::new(string)
+        .unwrap_or_else(|error| CString::new(error.into_vec().into_iter().filter(|&b| b != 0).collect::<Vec<_>>()).unwrap())
+        .into_raw()
+}
@@ -1,33 +1,198 @@
+use std::collections::{BTreeMap, BTreeSet};
+
 use serde::{Deserialize, Serialize};
 
+// "KaTeX_Main-Regular"のような、KaTeXのフォントファイル名
+pub type FontName = &'static str;
+
 #[inline(always)]
 pub fn font_extract(html: &str) -> UsedFonts {
     let mut fonts = UsedFonts::default();
+    scan(html, &mut fonts);
+    fonts
+}
+
+// サブセット化のために、フォントごとに使われた文字を集める
+pub fn glyph_extract(html: &str) -> BTreeMap<FontName, BTreeSet<char>> {
+    let mut glyphs = BTreeMap::new();
+    scan(html, &mut glyphs);
+    glyphs
+}
+
+// チャンクごとに受け取ったHTMLを、数式一つ分だけバッファしながら調べる
+#[derive(Debug, Clone, Default)]
+pub struct FontScanner {
+    pending: String,
+    fonts: UsedFonts,
+}
+impl FontScanner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn feed(&mut self, chunk: &str) {
+        const KATEX_START: &str = r#"<span class="katex">"#;
+        self.pending.push_str(chunk);
+        loop {
+            let Some(start) = self.pending.find(KATEX_START) else {
+                // 次のチャンクと合わせて開始タグになりうる末尾だけを残す
+                let keep = self.pending.rfind('<').filter(|&index| KATEX_START.starts_with(&self.pending[index..]));
+                self.pending.drain(..keep.unwrap_or(self.pending.len()));
+                return;
+            };
+            let Some(end) = katex_block_end(&self.pending[start..]) else {
+                self.pending.drain(..start);
+                return;
+            };
+            self.fonts.merge(font_extract(&self.pending[start..start + end]));
+            self.pending.drain(..start + end);
+        }
+    }
+    // 閉じていない数式が残っていれば、そこまでの分も数える
+    pub fn finish(self) -> UsedFonts {
+        let mut fonts = self.fonts;
+        fonts.merge(font_extract(&self.pending));
+        fonts
+    }
+}
+
+// 先頭の<span>に対応する</span>の直後の位置
+fn katex_block_end(html: &str) -> Option<usize> {
+    let (mut depth, mut position) = (0, 0);
+    loop {
+        let open = html[position..]
+            .match_indices("<span")
+            .map(|(index, _)| position + index)
+            .find(|&index| html[index + 5..].starts_with(|c: char| c == '>' || c.is_ascii_whitespace()));
+        let close = html[position..].find("</span>").map(|index| position + index)?;
+        match open {
+            Some(open) if open < close => {
+                depth += 1;
+                position = open + 5;
+            }
+            _ => {
+                depth -= 1;
+                position = close + 7;
+                if depth == 0 {
+                    return Some(position);
+                }
+            }
+        }
+    }
+}
+
+// katex-html以下の文字列を、そこで使われるフォントと一緒に受け取る
+trait FontSink {
+    fn text(&mut self, font: Font, text: &str);
+}
+impl FontSink for UsedFonts {
+    fn text(&mut self, font: Font, _text: &str) {
+        font_flag_set(font, self);
+    }
+}
+impl FontSink for BTreeMap<FontName, BTreeSet<char>> {
+    fn text(&mut self, font: Font, text: &str) {
+        for &name in font_names(font) {
+            self.entry(name).or_default().extend(text.chars().filter(|c| !c.is_ascii_whitespace()));
+        }
+    }
+}
+
+fn scan(html: &str, sink: &mut impl FontSink) {
     let mut tokenizer = html5gum::Tokenizer::new(html);
     while let Some(Ok(token)) = tokenizer.next() {
         let html5gum::Token::StartTag(tag) = token else { continue };
-        if tag.name.to_ascii_lowercase() != b"span" {
+        let name = tag.name.to_ascii_lowercase();
+        // ページ全体を読み、すべての数式の結果を合わせる。MathMLだけの出力では<math>を直接調べる
+        if name == b"math" {
+            calc_mathml_font(b"math", None, sink, &mut tokenizer);
             continue;
         }
-        let Some(Ok(class_list)) = tag.attributes.get(b"class".as_slice()).map(|s| std::str::from_utf8(&s)) else { continue };
-        if class_list.split_whitespace().any(|class| class == "katex-html") {
-            calc_font_property(Font::default(), &mut fonts, &mut tokenizer);
-            break;
+        if name != b"span" {
+            continue;
+        }
+        let Some(Ok(class_list)) = tag.attributes.get(b"class".as_slice()).map(|s| std::str::from_utf8(s)) else { continue };
+        // katex-htmlと同じ式なので、そちらだけを数える
+        if class_list.split_whitespace().any(|class| class == "katex-mathml") {
+            skip_until_end(b"math", &mut tokenizer);
+        } else if class_list.split_whitespace().any(|class| class == "katex-html") {
+            calc_font_property(Font::default(), sink, &mut tokenizer);
+        }
+    }
+}
+
+fn skip_until_end(name: &[u8], tokens: &mut html5gum::Tokenizer<html5gum::StringReader>) {
+    while let Some(Ok(token)) = tokens.next() {
+        if let html5gum::Token::EndTag(tag) = token
+            && tag.name.to_ascii_lowercase() == name
+        {
+            return;
+        }
+    }
+}
+
+// MathMLの要素を終了タグまで読む。mathvariantは子要素に引き継がれる
+fn calc_mathml_font(
+    element: &[u8],
+    variant: Option<&str>,
+    sink: &mut impl FontSink,
+    tokens: &mut html5gum::Tokenizer<html5gum::StringReader>,
+) {
+    while let Some(Ok(token)) = tokens.next() {
+        match token {
+            html5gum::Token::EndTag(tag) if tag.name.to_ascii_lowercase() == element => return,
+            html5gum::Token::String(s) if !s.trim_ascii().is_empty() => {
+                let text = String::from_utf8_lossy(&s);
+                for font in mathml_fonts(element, variant, &text) {
+                    sink.text(font, &text);
+                }
+            }
+            html5gum::Token::StartTag(tag) if !tag.self_closing => {
+                let name = tag.name.to_ascii_lowercase();
+                if name == b"annotation" || name == b"annotation-xml" {
+                    skip_until_end(&name, tokens);
+                    continue;
+                }
+                let child_variant = tag.attributes.get(b"mathvariant".as_slice()).and_then(|s| std::str::from_utf8(s).ok());
+                calc_mathml_font(&name, child_variant.or(variant), sink, tokens);
+            }
+            _ => (),
         }
     }
-    fonts
+}
+
+fn mathml_fonts(element: &[u8], variant: Option<&str>, text: &str) -> Vec<Font> {
+    let font = |family, bold, italic| Font { family, bold, italic, delimisizing_mult: false };
+    match variant {
+        // mathvariantの無い1文字の<mi>はイタリックになる
+        None if element == b"mi" && text.trim().chars().count() == 1 => vec![font(FontFamilies::Math, false, true)],
+        None | Some("normal") => vec![font(FontFamilies::Main, false, false)],
+        Some("bold") => vec![font(FontFamilies::Main, true, false)],
+        Some("italic") => vec![font(FontFamilies::Main, false, true)],
+        Some("bold-italic") => vec![font(FontFamilies::Math, true, true)],
+        Some("double-struck") => vec![font(FontFamilies::Ams, false, false)],
+        Some("fraktur") => vec![font(FontFamilies::Fraktur, false, false)],
+        Some("bold-fraktur") => vec![font(FontFamilies::Fraktur, true, false)],
+        // \mathcalも\mathscrもscriptになるので、両方とも必要とみなす
+        Some("script" | "bold-script") => vec![font(FontFamilies::Caligraphic, false, false), font(FontFamilies::Script, false, false)],
+        Some("sans-serif") => vec![font(FontFamilies::SansSerif, false, false)],
+        Some("bold-sans-serif") => vec![font(FontFamilies::SansSerif, true, false)],
+        Some("sans-serif-italic") => vec![font(FontFamilies::SansSerif, false, true)],
+        Some("sans-serif-bold-italic") => vec![font(FontFamilies::SansSerif, true, true)],
+        Some("monospace") => vec![font(FontFamilies::Typewriter, false, false)],
+        Some(_) => vec![font(FontFamilies::Main, false, false)],
+    }
 }
 
 // 開始タグ直後から終了タグ終わりまで読む関数
 #[inline]
-fn calc_font_property(font: Font, font_flags: &mut UsedFonts, tokens: &mut html5gum::Tokenizer<html5gum::StringReader>) {
+fn calc_font_property(font: Font, sink: &mut impl FontSink, tokens: &mut html5gum::Tokenizer<html5gum::StringReader>) {
     while let Some(Ok(token)) = tokens.next() {
         match token {
             html5gum::Token::EndTag(tag) if tag.name.to_ascii_lowercase() == b"span" => return,
-            html5gum::Token::String(s) if !s.trim_ascii().is_empty() => font_flag_set(font, font_flags),
+            html5gum::Token::String(s) if !s.trim_ascii().is_empty() => sink.text(font, &String::from_utf8_lossy(&s)),
             html5gum::Token::StartTag(tag) if tag.name.to_ascii_lowercase() == b"span" => {
                 let mut child_font = font;
-                if let Some(Ok(class_list)) = tag.attributes.get(b"class".as_slice()).map(|s| std::str::from_utf8(&s)) {
+                if let Some(Ok(class_list)) = tag.attributes.get(b"class".as_slice()).map(|s| std::str::from_utf8(s)) {
                     let (mut delimsizing, mut mult, mut op_symbol) = (false, false, false);
                     for class in class_list.split_whitespace() {
                         match class {
@@ -41,8 +206,9 @@ fn calc_font_property(font: Font, font_flags: &mut UsedFonts, tokens: &mut html5
                     for class in class_list.split_whitespace() {
                         font_stack_set(&mut child_font, class, delimsizing, op_symbol);
                     }
-                    calc_font_property(child_font, font_flags, tokens);
                 }
+                // classの無いspan(vlistの行など)も、終了タグを対応させるために一段下りる
+                calc_font_property(child_font, sink, tokens);
             }
             _ => (),
         }
@@ -58,7 +224,7 @@ struct Font {
 }
 #[derive(Debug, Clone, Copy, Default)]
 enum FontFamilies {
-    AMS,
+    Ams,
     Caligraphic,
     Fraktur,
     #[default]
@@ -98,7 +264,7 @@ fn font_stack_set(font: &mut Font, class: &str, delimisizing: bool, op_symbol: b
             font.bold = true;
             font.italic = true
         }
-        "amsrm" | "mathbb" | "textbb" => font.family = FontFamilies::AMS,
+        "amsrm" | "mathbb" | "textbb" => font.family = FontFamilies::Ams,
         "mathcal" => font.family = FontFamilies::Caligraphic,
         "mathfrak" | "textfrak" => font.family = FontFamilies::Fraktur,
         "mathboldfrak" | "textboldfrak" => {
@@ -131,7 +297,31 @@ fn font_stack_set(font: &mut Font, class: &str, delimisizing: bool, op_symbol: b
     }
 }
 
-#[derive(Debug, Clone, Copy, Hash, Deserialize, Serialize)]
+// UsedFontsのフィールドと同じ並び
+static FONT_NAMES: [FontName; 20] = [
+    "KaTeX_AMS-Regular",
+    "KaTeX_Caligraphic-Bold",
+    "KaTeX_Caligraphic-Regular",
+    "KaTeX_Fraktur-Bold",
+    "KaTeX_Fraktur-Regular",
+    "KaTeX_Main-Bold",
+    "KaTeX_Main-BoldItalic",
+    "KaTeX_Main-Italic",
+    "KaTeX_Main-Regular",
+    "KaTeX_Math-BoldItalic",
+    "KaTeX_Math-Italic",
+    "KaTeX_SansSerif-Bold",
+    "KaTeX_SansSerif-Italic",
+    "KaTeX_SansSerif-Regular",
+    "KaTeX_Script-Regular",
+    "KaTeX_Size1-Regular",
+    "KaTeX_Size2-Regular",
+    "KaTeX_Size3-Regular",
+    "KaTeX_Size4-Regular",
+    "KaTeX_Typewriter-Regular",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize, Serialize)]
 pub struct UsedFonts {
     katex_ams_regular: bool,
     katex_caligraphic_bold: bool,
@@ -154,33 +344,147 @@ pub struct UsedFonts {
     katex_size4_regular: bool,
     katex_typewriter_regular: bool,
 }
-impl Default for UsedFonts {
-    fn default() -> Self {
-        UsedFonts {
-            katex_ams_regular: false,
-            katex_caligraphic_bold: false,
-            katex_caligraphic_regular: false,
-            katex_fraktur_bold: false,
-            katex_fraktur_regular: false,
-            katex_main_bold: false,
-            katex_main_bolditalic: false,
-            katex_main_italic: false,
-            katex_main_regular: false,
-            katex_math_bolditalic: false,
-            katex_math_italic: false,
-            katex_sansserif_bold: false,
-            katex_sansserif_italic: false,
-            katex_sansserif_regular: false,
-            katex_script_regular: false,
-            katex_size1_regular: false,
-            katex_size2_regular: false,
-            katex_size3_regular: false,
-            katex_size4_regular: false,
-            katex_typewriter_regular: false,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FontFormat {
+    #[default]
+    Woff2,
+    Woff,
+    Ttf,
+}
+impl FontFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            FontFormat::Woff2 => "woff2",
+            FontFormat::Woff => "woff",
+            FontFormat::Ttf => "ttf",
+        }
+    }
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            FontFormat::Woff2 => "font/woff2",
+            FontFormat::Woff => "font/woff",
+            FontFormat::Ttf => "font/ttf",
         }
     }
 }
+
 impl UsedFonts {
+    // フォントのpreloadは同一オリジンでもcrossoriginが無いと二重に取得される
+    pub fn preload_links(&self, base_url: &str, format: FontFormat) -> Vec<String> {
+        let base_url = base_url.trim_end_matches('/').replace('&', "&amp;").replace('"', "&quot;");
+        (*self)
+            .map(|name| {
+                format!(
+                    r#"<link rel="preload" href="{base_url}/{name}.{}" as="font" type="{}" crossorigin>"#,
+                    format.extension(),
+                    format.mime_type()
+                )
+            })
+            .collect()
+    }
+    // selfを消費せずに使われているフォント名を列挙する
+    pub fn names(&self) -> impl Iterator<Item = FontName> + use<> {
+        *self
+    }
+    pub fn from_names<'a>(names: impl IntoIterator<Item = &'a str>) -> UsedFonts {
+        let mut flags = [false; 20];
+        for name in names {
+            if let Some(index) = FONT_NAMES.iter().position(|font| *font == name) {
+                flags[index] = true;
+            }
+        }
+        UsedFonts::from_flags(flags)
+    }
+    pub fn contains(&self, name: &str) -> bool {
+        FONT_NAMES.iter().zip(self.flags()).any(|(font, used)| used && *font == name)
+    }
+    pub fn len(&self) -> usize {
+        self.flags().into_iter().filter(|used| *used).count()
+    }
+    pub fn union(&self, other: &UsedFonts) -> UsedFonts {
+        self.combine(other, |a, b| a || b)
+    }
+    pub fn intersection(&self, other: &UsedFonts) -> UsedFonts {
+        self.combine(other, |a, b| a && b)
+    }
+    pub fn difference(&self, other: &UsedFonts) -> UsedFonts {
+        self.combine(other, |a, b| a && !b)
+    }
+    fn combine(&self, other: &UsedFonts, f: impl Fn(bool, bool) -> bool) -> UsedFonts {
+        let (a, b) = (self.flags(), other.flags());
+        UsedFonts::from_flags(std::array::from_fn(|index| f(a[index], b[index])))
+    }
+    fn flags(&self) -> [bool; 20] {
+        [
+            self.katex_ams_regular,
+            self.katex_caligraphic_bold,
+            self.katex_caligraphic_regular,
+            self.katex_fraktur_bold,
+            self.katex_fraktur_regular,
+            self.katex_main_bold,
+            self.katex_main_bolditalic,
+            self.katex_main_italic,
+            self.katex_main_regular,
+            self.katex_math_bolditalic,
+            self.katex_math_italic,
+            self.katex_sansserif_bold,
+            self.katex_sansserif_italic,
+            self.katex_sansserif_regular,
+            self.katex_script_regular,
+            self.katex_size1_regular,
+            self.katex_size2_regular,
+            self.katex_size3_regular,
+            self.katex_size4_regular,
+            self.katex_typewriter_regular,
+        ]
+    }
+    fn from_flags(flags: [bool; 20]) -> UsedFonts {
+        let [
+            katex_ams_regular,
+            katex_caligraphic_bold,
+            katex_caligraphic_regular,
+            katex_fraktur_bold,
+            katex_fraktur_regular,
+            katex_main_bold,
+            katex_main_bolditalic,
+            katex_main_italic,
+            katex_main_regular,
+            katex_math_bolditalic,
+            katex_math_italic,
+            katex_sansserif_bold,
+            katex_sansserif_italic,
+            katex_sansserif_regular,
+            katex_script_regular,
+            katex_size1_regular,
+            katex_size2_regular,
+            katex_size3_regular,
+            katex_size4_regular,
+            katex_typewriter_regular,
+        ] = flags;
+        UsedFonts {
+            katex_ams_regular,
+            katex_caligraphic_bold,
+            katex_caligraphic_regular,
+            katex_fraktur_bold,
+            katex_fraktur_regular,
+            katex_main_bold,
+            katex_main_bolditalic,
+            katex_main_italic,
+            katex_main_regular,
+            katex_math_bolditalic,
+            katex_math_italic,
+            katex_sansserif_bold,
+            katex_sansserif_italic,
+            katex_sansserif_regular,
+            katex_script_regular,
+            katex_size1_regular,
+            katex_size2_regular,
+            katex_size3_regular,
+            katex_size4_regular,
+            katex_typewriter_regular,
+        }
+    }
     pub fn is_empty(&self) -> bool {
         !self.katex_ams_regular
             && !self.katex_caligraphic_bold
@@ -226,6 +530,14 @@ impl UsedFonts {
         self.katex_typewriter_regular |= other.katex_typewriter_regular;
     }
 }
+// UsedFontsはCopyなので、コピーした方を消費して列挙する
+impl IntoIterator for &UsedFonts {
+    type Item = FontName;
+    type IntoIter = UsedFonts;
+    fn into_iter(self) -> UsedFonts {
+        *self
+    }
+}
 impl Iterator for UsedFonts {
     type Item = &'static str;
     fn next(&mut self) -> Option<Self::Item> {
@@ -315,7 +627,7 @@ impl Iterator for UsedFonts {
 #[inline(always)]
 fn font_flag_set(font: Font, flags: &mut UsedFonts) {
     match font.family {
-        FontFamilies::AMS => flags.katex_ams_regular = true,
+        FontFamilies::Ams => flags.katex_ams_regular = true,
         FontFamilies::Caligraphic if font.bold => flags.katex_caligraphic_bold = true,
         FontFamilies::Caligraphic => flags.katex_caligraphic_regular = true,
         FontFamilies::Fraktur if font.bold => flags.katex_fraktur_bold = true,
@@ -345,3 +657,34 @@ fn font_flag_set(font: Font, flags: &mut UsedFonts) {
         FontFamilies::Typewriter => flags.katex_typewriter_regular = true,
     }
 }
+
+// font_flag_setと同じ対応で、フォントファイル名を返す
+fn font_names(font: Font) -> &'static [FontName] {
+    match font.family {
+        FontFamilies::Ams => &["KaTeX_AMS-Regular"],
+        FontFamilies::Caligraphic if font.bold => &["KaTeX_Caligraphic-Bold"],
+        FontFamilies::Caligraphic => &["KaTeX_Caligraphic-Regular"],
+        FontFamilies::Fraktur if font.bold => &["KaTeX_Fraktur-Bold"],
+        FontFamilies::Fraktur => &["KaTeX_Fraktur-Regular"],
+        FontFamilies::Main => match (font.bold, font.italic) {
+            (false, false) => &["KaTeX_Main-Regular"],
+            (true, false) => &["KaTeX_Main-Bold"],
+            (false, true) => &["KaTeX_Main-Italic"],
+            (true, true) => &["KaTeX_Main-BoldItalic"],
+        },
+        FontFamilies::Math if font.bold => &["KaTeX_Math-BoldItalic"],
+        FontFamilies::Math => &["KaTeX_Math-Italic"],
+        FontFamilies::SansSerif => match (font.bold, font.italic) {
+            (false, false) => &["KaTeX_SansSerif-Regular"],
+            (true, false) => &["KaTeX_SansSerif-Bold"],
+            (false, true) => &["KaTeX_SansSerif-Italic"],
+            (true, true) => &["KaTeX_SansSerif-Bold", "KaTeX_SansSerif-Italic"],
+        },
+        FontFamilies::Script => &["KaTeX_Script-Regular"],
+        FontFamilies::Size1 => &["KaTeX_Size1-Regular"],
+        FontFamilies::Size2 => &["KaTeX_Size2-Regular"],
+        FontFamilies::Size3 => &["KaTeX_Size3-Regular"],
+        FontFamilies::Size4 => &["KaTeX_Size4-Regular"],
+        FontFamilies::Typewriter => &["KaTeX_Typewriter-Regular"],
+    }
+}
@@ -66,64 +66,1203 @@ let html2 = render_with_opts(
 println!("HTML 2: {}", html2);
 ```
 
+Collected macros are stored as `MacroValue`s: plain definitions are kept as `MacroValue::Simple`, while macros taking parameters (such as `\gdef\f#1#2{#2}`) are kept as `MacroValue::WithArgs` so their argument count survives the round trip.
+
+`Options` can also be assembled with chainable setters:
+
+```rust
+use katex_gdef_v8::{Options, KatexOutput};
+
+let options = Options::builder().display(true).output(KatexOutput::Html).trust(true).build();
+```
+
+Presets cover the common cases: `Options::display()` for display math, `Options::inline()` for prose that should show a colored error instead of failing (`throw_on_error: false`), and `Options::strict_secure()` for untrusted input (no `trust`, `strict: "error"`). They can be adjusted with struct update syntax:
+
+```rust
+use katex_gdef_v8::Options;
+
+let options = Options { leqno: true, ..Options::display() };
+```
+
+`max_expand` is a `MaxExpand`: `MaxExpand::Limit(n)` caps macro expansions (1000 by default) and `MaxExpand::Unlimited` corresponds to KaTeX's `maxExpand: Infinity`, for documents built from heavily nested macros. Likewise `max_size` is a `MaxSize`, where the default `MaxSize::Unlimited` is KaTeX's `maxSize: Infinity` and `MaxSize::Limit(em)` caps the size of rules and boxes.
+
+`Options` also implements `Deserialize` using KaTeX's option names, with missing fields falling back to their defaults, so it can be loaded from a configuration file:
+
+```rust
+use katex_gdef_v8::Options;
+
+let options: Options = serde_json::from_str(r#"{ "displayMode": true, "trust": true }"#).unwrap();
+assert!(options.display_mode);
+```
+
+### Macro Presets
+
+The `presets` module ships opt-in macro collections to merge into your own map: `physics()` (`\dv`, `\pdv`, `\abs`, `\norm`, `\vb`, ...), `braket()` (`\ketbra`, `\mel`, `\ip`, ... on top of KaTeX's `\bra`/`\ket`), `theorem()` (`\defeq`, `\floor`, `\inner`, ...) and `blackboard()` (`\N`, `\Q`, `\R`, `\E`, ...):
+
+```rust
+use katex_gdef_v8::{presets, render_with_opts};
+
+let mut macros = presets::physics();
+macros.extend(presets::blackboard());
+let html = render_with_opts(r"\dv{f}{x} = \abs{x}, \quad x \in \R", &Default::default(), &mut macros).unwrap();
+```
+
+### Loading a Preamble
+
+`macros_from_preamble` runs a preamble of `\newcommand`, `\def` and `\gdef` definitions through KaTeX once, without generating HTML, and adds the definitions to the macro map:
+
+```rust
+use katex_gdef_v8::{macros_from_preamble, render_with_opts};
+use std::collections::BTreeMap;
+
+let mut macros = BTreeMap::new();
+macros_from_preamble(r"\newcommand{\RR}{\mathbb{R}} \def\abs#1{\left|#1\right|}", &mut macros).unwrap();
+
+let html = render_with_opts(r"\abs{x} \in \RR", &Default::default(), &mut macros).unwrap();
+println!("{}", html);
+```
+
+### Harvesting Macros
+
+`collect_macros` parses every formula of a document once, in a single job, and returns the macros they define with `\gdef` and similar commands. It builds no HTML, and formulas that fail to parse are skipped. With the macro set frozen, a second pass can render the formulas in parallel, for example with several renderers:
+
+```rust
+use katex_gdef_v8::{Options, collect_macros, render_with_opts};
+
+let formulas = [r"\gdef\R{\mathbb{R}}", r"x \in \R", r"\frac{"];
+let macros = collect_macros(formulas, &Options::default()).unwrap();
+for latex in formulas {
+    let _ = render_with_opts(latex, &Options::default(), &mut macros.clone());
+}
+```
+
+### Checking Without Rendering
+
+`check` only parses the input, skipping HTML generation, which suits linters and CI jobs over large corpora. Failures come back as `ParseDiagnostics` with KaTeX's message and the byte offset of the problem:
+
+```rust
+use katex_gdef_v8::{Options, check};
+use std::collections::BTreeMap;
+
+let error = check(r"x^", &Options::default(), &BTreeMap::new()).unwrap_err();
+assert_eq!(error.raw_message, "Expected group after '^'");
+assert_eq!(error.position, Some(1));
+```
+
+### Editor Diagnostics
+
+`diagnostics` is meant for language servers and editor plugins that underline math as the user types. Besides a parse error it reports the nonstandard LaTeX that `strict` does not ignore, without stopping at the first one. Each `Diagnostic` has a severity (`Error` for parse errors and codes set to error, `Warning` for codes set to warn), the byte range it covers in the formula, KaTeX's message and, for `strict` findings, KaTeX's error code. If the engine itself fails, a single error covering the whole formula is returned:
+
+```rust
+use katex_gdef_v8::{Options, Severity, StrictMode, diagnostics};
+use std::collections::BTreeMap;
+
+let options = Options::builder().strict(StrictMode::Warn).build();
+let found = diagnostics("é + x^", &options, &BTreeMap::new());
+assert_eq!(found[0].severity, Severity::Warning);
+assert_eq!(found[0].code.as_deref(), Some("unicodeTextInMathMode"));
+assert_eq!(found[0].range, 0..2);
+assert_eq!((found[1].severity, found[1].range.clone()), (Severity::Error, 6..7));
+```
+
+### Parse Trees
+
+`parse_tree` returns KaTeX's own parse tree as a `serde_json::Value` (an array of nodes with `type`, `mode` and UTF-16 `loc` ranges), so tools can look for environments or count operators without a TeX parser of their own. The node shapes are KaTeX internals and may change between KaTeX versions:
+
+```rust
+use katex_gdef_v8::parse_tree;
+
+let tree = parse_tree(r"\frac{a}{b}", &Default::default()).unwrap();
+assert_eq!(tree[0]["type"], "genfrac");
+```
+
+### Expanding Macros
+
+`expand` runs only KaTeX's macro expander and returns the expanded TeX, which helps when debugging definitions or handing TeX to other tools. Definitions made inside the same input (`\gdef`) are not applied, since the parser executes them:
+
+```rust
+use katex_gdef_v8::{MacroValue, expand};
+use std::collections::BTreeMap;
+
+let macros = BTreeMap::from([(r"\R".to_string(), MacroValue::from(r"\mathbb{R}"))]);
+assert_eq!(expand(r"x \in \R", &macros).unwrap(), r"x \in\mathbb{R}");
+```
+
+### Finding Used Macros
+
+`macros_used` parses a formula and returns the names of the macros from the map that KaTeX expanded, including the ones used inside other macros. Documentation tooling can use it to ship only the definitions a page needs, or to find macros that no formula uses:
+
+```rust
+use katex_gdef_v8::macros_used;
+use std::collections::BTreeMap;
+
+let macros = BTreeMap::from([
+    (r"\RR".to_string(), r"\mathbb{\R}".into()),
+    (r"\R".to_string(), "R".into()),
+    (r"\dead".to_string(), "x".into()),
+]);
+let used = macros_used(r"x \in \RR", &macros).unwrap();
+assert_eq!(used.into_iter().collect::<Vec<_>>(), [r"\R", r"\RR"]);
+```
+
+### Persisting Macros
+
+`MacroStore` saves collected macros as versioned JSON, so incremental builds can start from the macros of the previous build:
+
+```rust
+use katex_gdef_v8::{render_with_opts, MacroStore};
+
+let path = std::env::temp_dir().join("katex-macros.json");
+
+let mut store = MacroStore::default();
+render_with_opts(r"\gdef\RR{\mathbb{R}}", &Default::default(), &mut store.macros).unwrap();
+store.save(&path).unwrap();
+
+let mut store = MacroStore::load(&path).unwrap();
+let html = render_with_opts(r"x \in \RR", &Default::default(), &mut store.macros).unwrap();
+println!("{}", html);
+```
+
+`MacroStore::canonical_json` writes the same macros as the same string every time: the compact store format with sorted names, which `MacroStore::load` also reads. `macros_fingerprint` hashes that string with FNV-1a, which does not depend on the Rust version or the process, so incremental build systems can use it as a cache key:
+
+```rust
+use katex_gdef_v8::{MacroValue, macros_fingerprint};
+use std::collections::BTreeMap;
+
+let a = BTreeMap::from([("\\R".to_string(), MacroValue::from("\\mathbb{R}"))]);
+let b = BTreeMap::from([("\\R".to_string(), MacroValue::WithArgs { num_args: 0, body: "\\mathbb{R}".to_string() })]);
+assert_eq!(macros_fingerprint(&a), macros_fingerprint(&b));
+```
+
+### Scoped Macros
+
+`MacroScope` layers macros, for example site-wide preamble → chapter → formula. `child` starts a new layer on top of the current definitions. `render_scoped` renders with every layer and stores the formula's new definitions in the innermost layer only, so macros defined in one chapter do not leak into the next. Clones share their layers and copy one only when it is changed, so taking a snapshot is cheap:
+
+```rust
+use katex_gdef_v8::{MacroScope, Options, render_scoped};
+
+let mut site = MacroScope::new();
+site.define("\\RR", "\\mathbb{R}");
+
+let mut chapter = site.child();
+render_scoped(r"\gdef\f{x^2} \f \in \RR", &Options::default(), &mut chapter).unwrap();
+assert!(chapter.get("\\f").is_some());
+assert!(site.get("\\f").is_none());
+```
+
+### Catching Macro Collisions
+
+`render_with_conflicts` works like `render_with_opts`, but reports every macro that a formula redefined with a different body, together with the old and new definitions and the formula responsible:
+
+```rust
+use katex_gdef_v8::render_with_conflicts;
+use std::collections::BTreeMap;
+
+let (mut macros, mut conflicts) = (BTreeMap::new(), Vec::new());
+render_with_conflicts(r"\gdef\v{\mathbf{v}}", &Default::default(), &mut macros, &mut conflicts).unwrap();
+render_with_conflicts(r"\gdef\v{\vec{v}}", &Default::default(), &mut macros, &mut conflicts).unwrap();
+assert_eq!(conflicts[0].name, r"\v");
+assert_eq!(conflicts[0].latex, r"\gdef\v{\vec{v}}");
+```
+
+A formula that fails is checked too: under `MacroCommit::Always` its definitions are still written back, so they are reported before the error is returned. `MacroConflict::detect(&before, &after, latex)` does the same comparison for maps you merge yourself.
+
+### Committing Macros
+
+`Options::macro_commit` decides when the macros defined by a formula are written back to the caller's map. With the default `MacroCommit::OnSuccess`, a formula that fails leaves the map unchanged, so it cannot half-define macros used later. This includes a formula that `throw_on_error(false)` renders as an error; KaTeX reports the error itself, so neither `\htmlClass{katex-error}` nor a helper or `minify` changes the outcome. `MacroCommit::Always` keeps the definitions made before the error, and `MacroCommit::Never` never changes the map. The macros at the point of failure are still available in `Error::KaTeXError`:
+
+```rust
+use katex_gdef_v8::{MacroCommit, Options, render_with_opts};
+use std::collections::BTreeMap;
+
+let mut macros = BTreeMap::new();
+assert!(render_with_opts(r"\gdef\a{1} \frac{", &Options::default(), &mut macros).is_err());
+assert!(macros.is_empty());
+
+let options = Options::builder().macro_commit(MacroCommit::Always).build();
+assert!(render_with_opts(r"\gdef\a{1} \frac{", &options, &mut macros).is_err());
+assert!(macros.contains_key(r"\a"));
+```
+
+The same policy applies to every call that writes macros back: `render_parts`, `render_with_speech`, `render_batch` (per formula) and `macros_from_preamble`.
+
+### Protecting Macros
+
+`Options::macro_filter` restricts what a formula may write back. `MacroFilter::protect` (or `protect_all` with the keys of a preamble) keeps the listed macros from being redefined, so user content cannot change a site-wide `\RR`. After `allow_prefix`, only names starting with one of the prefixes can be defined. Rejected definitions still apply inside the formula itself, but they are not kept for later formulas:
+
+```rust
+use katex_gdef_v8::{MacroFilter, Options, macros_from_preamble, render_with_opts};
+use std::collections::BTreeMap;
+
+let mut macros = BTreeMap::new();
+macros_from_preamble(r"\gdef\RR{\mathbb{R}}", &mut macros).unwrap();
+let filter = MacroFilter::new().protect_all(macros.keys().cloned()).allow_prefix(r"\user");
+let options = Options::builder().macro_filter(filter).build();
+
+render_with_opts(r"\gdef\RR{X} \gdef\userA{a} \gdef\b{b}", &options, &mut macros).unwrap();
+assert_eq!(macros[r"\RR"].body(), r"\mathbb{R}");
+assert!(macros.contains_key(r"\userA") && !macros.contains_key(r"\b"));
+```
+
+`render_batch` and `render_document` apply the filter and `macro_commit` after each formula, so a rejected definition is not visible to the formulas after it either.
+
+### Separate HTML and MathML
+
+`render_parts` renders both outputs at once and returns them separately, so the MathML can be placed in its own accessibility node or indexed independently. `Options::helpers` are applied to the HTML part only, since they take HTML; `minify`, `dark_colors` and `css_variables` apply to both:
+
+```rust
+use katex_gdef_v8::render_parts;
+use std::collections::BTreeMap;
+
+let parts = render_parts(r"x^2", &Default::default(), &mut BTreeMap::new()).unwrap();
+assert!(parts.mathml.starts_with("<math"));
+assert!(!parts.html.contains("<math"));
+```
+
+### Speech Text
+
+The `speech` feature adds `render_with_speech`, which returns the HTML together with an English reading of the formula for an `aria-label`. The reading is built in Rust from KaTeX's parse tree, so macros are expanded the same way as in the HTML. It covers common notation (fractions, roots, scripts, big operators, delimiters and matrices) rather than a full MathSpeak grammar. `speech_text` reads a tree returned by `parse_tree`:
+
+```toml
+[dependencies]
+katex-gdef-v8 = { version = "0.1.6", features = ["speech"] }
+```
+
+```rust,ignore
+use katex_gdef_v8::render_with_speech;
+use std::collections::BTreeMap;
+
+let spoken = render_with_speech(r"\frac{1}{2} + x^2", &Default::default(), &mut BTreeMap::new()).unwrap();
+assert_eq!(spoken.speech, "the fraction 1 over 2 plus x squared");
+let html = format!(r#"<span role="math" aria-label="{}">{}</span>"#, spoken.speech, spoken.html);
+```
+
+### Plain Text
+
+`to_text` renders MathML and flattens it into a single readable line for alt text, search snippets and terminal output. Only the KaTeX options of `options` are used; `minify`, `dark_colors`, `css_variables`, `helpers` and the post-processor are for HTML and are skipped. Symbols stay as the Unicode characters KaTeX emits; scripts, fractions and roots are written with `^`, `_`, `/` and `√`. `mathml_to_text` does the same for MathML you already have:
+
+```rust
+use katex_gdef_v8::to_text;
+use std::collections::BTreeMap;
+
+let text = to_text(r"x^2 + \frac{1}{2}", &Default::default(), &mut BTreeMap::new()).unwrap();
+assert_eq!(text, "x^2 + 1/2");
+```
+
+### Writing to a Sink
+
+`render_to_writer` writes the HTML straight into any `std::io::Write`, such as a file or a large output buffer. Write failures are returned as `Error::Io`:
+
+```rust
+use katex_gdef_v8::render_to_writer;
+use std::collections::BTreeMap;
+
+let mut page = Vec::new();
+render_to_writer(r"\int_0^1 x\,dx", &Default::default(), &mut BTreeMap::new(), &mut page).unwrap();
+assert!(page.starts_with(b"<span"));
+```
+
+### Rendering Whole Documents
+
+`render_document` is a port of KaTeX's auto-render extension: it finds math between delimiters, renders every formula in one batch and splices the results back into the text. An escaped `\$` is not treated as a delimiter. As in auto-render, the text between formulas is copied unchanged, so the backslash stays; unescape it in your own pipeline if needed.
+
+```rust
+use katex_gdef_v8::{render_document, Delimiter};
+use std::collections::BTreeMap;
+
+let html = render_document(
+    r"The price is \$5, and $x^2$ is inline while $$\int f$$ is displayed.",
+    &Delimiter::defaults(),
+    &Default::default(),
+    &mut BTreeMap::new()
+).unwrap();
+assert!(html.starts_with(r#"The price is \$5, and <span class="katex">"#));
+```
+
+### Splitting Math from Text
+
+`split_math` exposes the tokenizer behind `render_document` for integrators that render formulas themselves. It returns the text and math segments in order, skipping escaped delimiters such as `\$`, which stay escaped in the text segments. With `code_spans` set (the default), math delimiters inside Markdown code spans are left alone:
+
+```rust
+use katex_gdef_v8::{DelimiterConfig, Segment, split_math};
+
+let segments = split_math(r"Use `$HOME` for $x^2$ and \[y\]", &DelimiterConfig::default());
+assert_eq!(segments, [
+    Segment::Text("Use `$HOME` for "),
+    Segment::Math { latex: "x^2", raw: "$x^2$", display: false },
+    Segment::Text(" and "),
+    Segment::Math { latex: "y", raw: r"\[y\]", display: true },
+]);
+```
+
+### Equation Numbers
+
+`DocumentRenderer` numbers display equations across separate render calls, which KaTeX cannot do on its own. Formulas with their own `\tag`, `\notag` or `\nonumber` keep them, and environments KaTeX numbers itself (`equation`, `align`, ...) are left alone. Macros carry over like in `render_with_opts`:
+
+```rust
+use katex_gdef_v8::{DocumentRenderer, NumberStyle};
+
+let mut doc = DocumentRenderer::new().number_style(NumberStyle::Arabic).number_format("(2.{})");
+doc.render_display(r"\gdef\E{\mathcal E} \E = mc^2").unwrap(); // (2.1)
+doc.render_display(r"a^2 + b^2 = c^2 \tag{P}").unwrap(); // (P), not counted
+doc.render_inline(r"\E").unwrap();
+doc.render_display(r"\E_0 = 0").unwrap(); // (2.2)
+assert_eq!(doc.last_number(), Some("(2.2)"));
+```
+
+KaTeX has no `\label`, `\ref` or `\eqref`, so `DocumentRenderer` handles them itself. `\label{name}` in a numbered display equation records its number (or its `\tag`), and later `\ref{name}` / `\eqref{name}` are replaced with it. The recorded number is the bare `number_style` value: `number_format` only shapes the tag, and `\eqref` puts the number in parentheses itself. References to labels defined further down render as placeholders; pass the finished HTML through `resolve_references` once the whole document is rendered:
+
+```rust
+use katex_gdef_v8::DocumentRenderer;
+
+let mut doc = DocumentRenderer::new();
+let mut html = String::new();
+html += &doc.render_inline(r"\text{by }\eqref{eq:pyth}").unwrap();
+html += &doc.render_display(r"a^2 + b^2 = c^2 \label{eq:pyth}").unwrap();
+let html = doc.resolve_references(&html);
+assert!(html.contains("(1)"));
+assert!(doc.unresolved_references().is_empty());
+```
+
+### Copyable TeX Source
+
+KaTeX's copy-tex extension reads the TeX source from the MathML annotation. `KatexOutput::HtmlAndTex` renders HTML only but keeps that annotation, so pages can offer copying the source without shipping the full MathML:
+
+```rust
+use katex_gdef_v8::{render_with_opts, Options, KatexOutput};
+use std::collections::BTreeMap;
+
+let options = Options { output: KatexOutput::HtmlAndTex, ..Default::default() };
+let html = render_with_opts(r"x^2", &options, &mut BTreeMap::new()).unwrap();
+assert!(html.contains(r#"<annotation encoding="application/x-tex">x^2</annotation>"#));
+```
+
+### Minified Output
+
+`minify` is a crate option rather than a KaTeX one: it removes whitespace between tags that spans a line break, compacts the path data of stretchy SVGs, and drops trailing `;` and empty `style` attributes. Whitespace that is the content of an element (such as the `<mtext>` of `\,`) is kept, so the result looks the same:
+
+```rust
+use katex_gdef_v8::Options;
+use std::collections::BTreeMap;
+
+let options = Options::builder().minify(true).build();
+let minified = katex_gdef_v8::render_with_opts(r"\sqrt{x}", &options, &mut BTreeMap::new()).unwrap();
+let full = katex_gdef_v8::render_with_opts(r"\sqrt{x}", &Default::default(), &mut BTreeMap::new()).unwrap();
+assert!(minified.len() < full.len());
+```
+
+### Dark-Mode Colors
+
+Colors given with `\color` or `\textcolor` are written into the output as fixed inline styles, so they can become unreadable when a site switches to a dark theme. `Options::dark_colors` rewrites them after rendering. The dark color keeps the hue and saturation and inverts the lightness, so `darkred` becomes a light red. `DarkColors::Replace` writes the dark color instead of the original. `DarkColors::LightDark` writes `light-dark(original, dark)`, so the browser picks one based on the page's `color-scheme`:
+
+```rust
+use katex_gdef_v8::{DarkColors, Options, render_with_opts};
+
+let options = Options::builder().dark_colors(DarkColors::LightDark).build();
+let html = render_with_opts(r"\color{darkred}{x}", &options, &mut Default::default()).unwrap();
+assert!(html.contains("color:light-dark(darkred,#ff7474)"));
+```
+
+### Themable Colors
+
+`Options::css_variables` writes the colors of `\color`, `\colorbox` and `\fcolorbox` as CSS custom properties instead of fixed values, so a site can change them with CSS alone without rendering again. Each color gets `--katex-color-` followed by the lowercase color without `#`, such as `--katex-color-darkred` or `--katex-color-cc0000`. Anything drawn in the error color uses `--katex-error-color`. `css_variables_stylesheet` collects the properties used in some HTML and returns a `:root` rule holding the original colors, and later rules can override them:
+
+```rust
+use katex_gdef_v8::{Options, css_variables_stylesheet, render_with_opts};
+
+let options = Options::builder().css_variables(true).build();
+let html = render_with_opts(r"\color{darkred}{x}", &options, &mut Default::default()).unwrap();
+assert!(html.contains("color:var(--katex-color-darkred)"));
+let css = css_variables_stylesheet(&html, &options);
+assert_eq!(css, ":root{--katex-color-darkred:darkred;}");
+// A dark theme can then override it: .dark { --katex-color-darkred: #ff7474; }
+```
+
+MathML has no way to refer to CSS properties, so `mathcolor` attributes keep their fixed colors.
+
+### Pretty-Printed HTML
+
+`format_html` puts each element on its own line, indented by depth, with attributes in name order. This makes golden-file diffs of rendered formulas reviewable. Elements holding only text stay on one line, and whitespace-only text between elements is dropped, so use it for comparing output rather than for serving it:
+
+```rust
+use katex_gdef_v8::format_html;
+
+let html = katex_gdef_v8::render(r"x^2").unwrap();
+let formatted = format_html(&html);
+assert!(formatted.starts_with("<span class=\"katex\">\n  <span class=\"katex-mathml\">\n"));
+```
+
+### Snapshot Tests
+
+The `testing` feature adds `testing::assert_render_snapshot`, which guards a downstream crate against KaTeX upgrades silently changing its output. It renders the formula, normalizes it with `format_html` and em lengths rounded to three decimals, and compares the result with `tests/snapshots/<name>.html` in the crate under test. A missing snapshot is written and the assertion passes. With `KATEX_GDEF_UPDATE_SNAPSHOTS=1` set, mismatching snapshots are rewritten instead of failing:
+
+```toml
+[dev-dependencies]
+katex-gdef-v8 = { version = "0.1.6", features = ["testing"] }
+```
+
+```rust,ignore
+use katex_gdef_v8::{Options, testing::assert_render_snapshot};
+
+#[test]
+fn quadratic_formula() {
+    assert_render_snapshot("quadratic", r"x = \frac{-b \pm \sqrt{b^2-4ac}}{2a}", &Options::display());
+}
+```
+
+### Restricting Link URLs
+
+`UrlPolicy` limits which URLs `\href`, `\url` and `\includegraphics` may use, so a wiki can allow links in math without allowing `javascript:`. It is checked inside KaTeX's `trust` callback, so it applies on top of `trust` and only to commands `trust` already allows. A command whose URL is rejected renders as an untrusted command. Domains include their subdomains. URLs without a host (such as `mailto:`) are checked by scheme only, and relative URLs always pass:
+
+```rust
+use katex_gdef_v8::{Options, TrustPolicy, UrlPolicy};
+use std::collections::BTreeMap;
+
+let options = Options::builder()
+    .trust(TrustPolicy::commands(["\\href", "\\url"]))
+    .url_policy(UrlPolicy::new(["https"]).domains(["example.com"]))
+    .build();
+let html = katex_gdef_v8::render_with_opts(r"\href{https://docs.example.com/}{x}", &options, &mut BTreeMap::new()).unwrap();
+assert!(html.contains("<a href"));
+let html = katex_gdef_v8::render_with_opts(r"\href{javascript:alert(1)}{x}", &options, &mut BTreeMap::new()).unwrap();
+assert!(!html.contains("<a href"));
+```
+
+### Sanitizing Trusted Output
+
+With `trust` enabled, `\href`, `\includegraphics` and the `\html...` commands put user-chosen URLs, classes and styles into the HTML. The `sanitize` feature adds `sanitize_html`, a post-render pass built on `ammonia` that keeps only the elements and attributes KaTeX itself emits. It also drops `href`/`src` values outside `http`, `https`, `mailto` and relative URLs, and style declarations other than sizes, spacing and colors (so no `position` or `url()`):
+
+```toml
+[dependencies]
+katex-gdef-v8 = { version = "0.1.6", features = ["sanitize"] }
+```
+
+```rust,ignore
+use katex_gdef_v8::{Options, TrustPolicy, sanitize_html};
+use std::collections::BTreeMap;
+
+let options = Options::builder().trust(TrustPolicy::Always).build();
+let html = katex_gdef_v8::render_with_opts(r"\href{javascript:alert(1)}{x}", &options, &mut BTreeMap::new()).unwrap();
+assert!(!sanitize_html(&html).contains("javascript:"));
+```
+
+### Post-Processing HTML
+
+`set_post_processor` registers a function that every rendered formula passes through before it is returned, so rewriting classes, adding attributes or wrapping the output happens in one place instead of at each call site. It applies regardless of `Options`, and `Renderer::set_post_processor` sets it for one renderer. Whether defined macros are committed is decided from the HTML before post-processing:
+
+```rust
+use katex_gdef_v8::{render, set_post_processor};
+
+set_post_processor(|html| format!("<span class=\"math\">{html}</span>"));
+let html = render("x").unwrap();
+assert!(html.starts_with(r#"<span class="math"><span class="katex">"#));
+```
+
+### Pre-Processing LaTeX
+
+`set_pre_processor` is the input-side counterpart: every formula passes through it before it reaches KaTeX, so stripping comments, normalizing a Unicode minus or rewriting legacy commands is done once for all call sites. It applies to every function that takes a formula, including `check`, `expand` and `collect_macros`, but not to `macros_from_preamble`. Error messages and diagnostic positions refer to the processed formula. `Renderer::set_pre_processor` sets it for one renderer:
+
+```rust
+use katex_gdef_v8::{Renderer, InitOptions};
+use std::borrow::Cow;
+
+let renderer = Renderer::new(InitOptions::default());
+renderer.set_pre_processor(|latex| if latex.contains('−') { Cow::Owned(latex.replace('−', "-")) } else { Cow::Borrowed(latex) });
+assert_eq!(renderer.render("a−b").unwrap(), renderer.render("a-b").unwrap());
+```
+
+### Strict Mode Warnings
+
+With `strict` set to warn, KaTeX reports nonstandard LaTeX through `console.warn`, which goes nowhere inside the engine. `render_with_warnings` collects these messages and returns them with the HTML:
+
+```rust,no_run
+use katex_gdef_v8::{Options, StrictMode, render_with_warnings};
+use std::collections::BTreeMap;
+
+let options = Options::builder().strict(StrictMode::Warn).build();
+let rendered = render_with_warnings("é", &options, &mut BTreeMap::new()).unwrap();
+for warning in &rendered.warnings {
+    eprintln!("warning: {warning}");
+}
+```
+
+### Memoizing Renders
+
+`RenderCache` remembers rendered formulas, together with the fonts they use, so a static site generator in watch mode does not re-render formulas that did not change. The key is a hash of the formula, the options, and the macros the formula can reach. Changing an unrelated macro therefore keeps the entry valid. Macros defined by a cached formula are applied again on a hit. The cache holds at most `capacity` entries and drops the least recently used one first. KaTeX errors are not cached:
+
+```rust
+use std::collections::BTreeMap;
+use katex_gdef_v8::{Options, RenderCache};
+
+let cache = RenderCache::new(10_000);
+let mut macros = BTreeMap::new();
+let first = cache.render(r"\frac{1}{2}", &Options::default(), &mut macros).unwrap();
+let again = cache.render(r"\frac{1}{2}", &Options::default(), &mut macros).unwrap();
+assert_eq!(first, again);
+println!("{} {:?}", again.html, again.fonts.collect::<Vec<_>>());
+```
+
+### Batch Rendering
+
+Rendering many formulas one by one pays a round-trip to the worker thread for each of them. `render_batch` sends all of them at once, threading macros through the expressions in order:
+
+```rust
+use katex_gdef_v8::{render_batch, Options};
+use std::collections::BTreeMap;
+
+let mut macros = BTreeMap::new();
+let display = Options { display_mode: true, ..Default::default() };
+
+let results = render_batch(
+    &[(r"\gdef\myvar{x} \myvar", Options::default()), (r"\myvar^2", display)],
+    &mut macros
+).unwrap();
+
+for result in results {
+    // Each expression succeeds or fails independently
+    println!("{}", result.unwrap());
+}
+```
+
+The outer `Result` reports a failure of the whole job rather than of a formula: the worker thread could not be spawned, the engine failed to start, the renderer was shut down or ran out of memory, or the engine raised a JS error outside KaTeX. In that case no formula has a result and `macros` is left unchanged. KaTeX errors in a single formula are in the inner results, and the other formulas still render.
+
+### Parallel Batch Rendering
+
+`render_batch` runs on one worker. For book-sized builds with several shared workers (see `KATEX_GDEF_WORKERS` below), the `rayon` feature adds `render_batch_parallel`, which splits the formulas into one share per worker, renders the shares at once, and returns the results in input order. With a single worker it renders them one after another. Every formula starts from the given macros, and macros it defines are not carried to the others, so collect document-wide definitions first with `collect_macros`:
+
+```rust,ignore
+use katex_gdef_v8::{Options, collect_macros, render_batch_parallel};
+
+let formulas = [r"\gdef\R{\mathbb{R}} \R", r"\R^2"];
+let macros = collect_macros(formulas, &Options::default()).unwrap();
+let items: Vec<(&str, Options)> = formulas.iter().map(|latex| (*latex, Options::default())).collect();
+let results = render_batch_parallel(&items, &macros);
+```
+
+### Pipelined Rendering
+
+When formulas come from a stream, `render_iter` keeps a few of them queued on the worker while you consume earlier results, so JS execution overlaps with your own work. Macros carry over in order, as with `render_batch`:
+
+```rust
+use katex_gdef_v8::{render_iter, Options};
+
+let formulas = vec![(r"\gdef\n{\mathbb N}".to_string(), Options::default()), (r"\n \ni 0".to_string(), Options::default())];
+let mut iter = render_iter(formulas).depth(8);
+for html in iter.by_ref() {
+    println!("{}", html.unwrap());
+}
+assert!(iter.defined_macros().contains_key(r"\n"));
+```
+
+The worker keeps two lanes. Jobs from `render_batch`, `render_document` and `render_iter` go into the `Priority::Batch` lane and only run when no `Priority::Interactive` job is waiting, so a live preview isn't stuck behind a background indexer sharing the renderer. Use `render_iter(..).priority(Priority::Interactive)` for a stream that should not wait. A single `render_batch` call is one job and still runs to completion once started.
+
+### Async Rendering
+
+With the `tokio` feature, `render_async` and `render_with_opts_async` wait for the worker thread without blocking the async runtime:
+
+```toml
+[dependencies]
+katex-gdef-v8 = { version = "0.1.6", features = ["tokio"] }
+```
+
+```rust,ignore
+let html = katex_gdef_v8::render_async(r"E = mc^2").await.unwrap();
+```
+
+### Non-Blocking Rendering
+
+`render_spawn` hands a formula to the worker and returns a `RenderHandle` right away. A GUI can start rendering, keep drawing frames, and poll with `try_get`, or block with `wait` when it needs the result. The handle takes its own copy of the macros. After the render finishes, `defined_macros` returns them with the new definitions added:
+
+```rust
+use std::collections::BTreeMap;
+use katex_gdef_v8::{Options, render_spawn};
+
+let mut handle = render_spawn(r"\gdef\R{\mathbb{R}} x \in \R", &Options::default(), BTreeMap::new());
+let html = loop {
+    if let Some(result) = handle.try_get() {
+        break result.unwrap();
+    }
+    // draw a frame
+};
+println!("{html}");
+assert!(handle.defined_macros().contains_key("\\R"));
+```
+
+### Timeouts
+
+`render_timeout` gives up on inputs that take too long (for example runaway macro expansion). The running script is terminated, so the worker stays usable for the next call:
+
+```rust
+use katex_gdef_v8::{Error, Options, render_timeout};
+use std::{collections::BTreeMap, time::Duration};
+
+let mut macros = BTreeMap::new();
+match render_timeout(r"x^2", &Options::default(), &mut macros, Duration::from_secs(1)) {
+    Ok(html) => println!("{html}"),
+    Err(Error::Timeout(timeout)) => eprintln!("gave up after {timeout:?}"),
+    Err(e) => eprintln!("{e}"),
+}
+```
+
+### Shedding Load
+
+Interactive applications can bound the worker's queue with `InitOptions::queue_capacity` and call `try_render`, which returns `Error::Busy` instead of waiting when that many jobs are already queued. Blocking calls such as `render_with_opts` are not limited. Without a capacity, `try_render` waits like `render_with_opts`.
+
+```rust
+use katex_gdef_v8::{Error, InitOptions, Renderer};
+use std::collections::BTreeMap;
+
+let renderer = Renderer::new(InitOptions::default().queue_capacity(16));
+match renderer.try_render(r"x^2", &Default::default(), &mut BTreeMap::new()) {
+    Ok(html) => println!("{html}"),
+    Err(Error::Busy) => println!("<code>x^2</code>"),
+    Err(e) => eprintln!("{e}"),
+}
+```
+
+### Limiting Memory
+
+`InitOptions::max_heap_mb` caps the V8 heap of a renderer, so adversarial formulas cannot exhaust the memory of a service. When the heap approaches the limit, the running render is aborted and returns `Error::OutOfMemory`. The engine is then cleaned up and keeps serving later requests. The option only exists in builds with the `v8` or `sandbox` feature, and with the [sandboxed engine](#sandboxed-engine) it caps the guest's linear memory instead:
+
+```rust
+# #[cfg(any(feature = "v8", feature = "sandbox"))] {
+use katex_gdef_v8::{Error, InitOptions, Renderer};
+
+let renderer = Renderer::new(InitOptions::default().max_heap_mb(256));
+match renderer.render(r"x^2") {
+    Err(Error::OutOfMemory) => eprintln!("formula too large"),
+    result => println!("{}", result.unwrap()),
+}
+# }
+```
+
+### Configuring V8
+
+`InitOptions::configure_v8` receives the `deno_core::RuntimeOptions` every time a JsRuntime is created, after the crate has filled in its own settings. Embedders can use it to add extensions, set create parameters, or attach an inspector without forking the crate. `deno_core` is re-exported so the versions match. The hook is called again when the worker restarts and when the snapshot is created, so it should always apply the same settings. If an extension changes the contents of the snapshot, use a separate cache path:
+
+```rust
+# #[cfg(feature = "v8")] {
+use katex_gdef_v8::{InitOptions, Renderer, deno_core};
+
+let renderer = Renderer::new(InitOptions::default().configure_v8(|options: &mut deno_core::RuntimeOptions| {
+    options.v8_platform = None;
+}));
+# }
+```
+
+### Tracing
+
+The `tracing` feature emits [tracing](https://crates.io/crates/tracing) spans at debug level, so you can see where rendering time goes under load:
+
+| Span | Covers |
+| --- | --- |
+| `katex.queue_wait` | waiting in the worker queue |
+| `katex.exec` | running one job in the worker |
+| `katex.serialize` / `katex.deserialize` | JSON conversion of the input and output |
+| `katex.js` | executing the KaTeX call in the JS engine |
+| `katex.snapshot_load` | reading or creating the cached snapshot |
+
+### Render Statistics
+
+`stats()` (or `Renderer::stats`) returns counters kept by the worker, ready to export to Prometheus or similar: formulas rendered and failed, jobs run and their average time in the JS engine, the number of jobs waiting in the queue, and snapshot cache hits and misses (counted for the whole process):
+
+```rust,no_run
+let stats = katex_gdef_v8::stats();
+println!("{} renders, {} failures, {:?} per job, {} queued", stats.renders, stats.failures, stats.average_js_time, stats.queue_depth);
+```
+
+### Chemistry with mhchem
+
+The `mhchem` feature bundles KaTeX's mhchem extension (into the snapshot as well), enabling `\ce` and `\pu`:
+
+```toml
+[dependencies]
+katex-gdef-v8 = { version = "0.1.6", features = ["mhchem"] }
+```
+
+```rust,ignore
+let html = katex_gdef_v8::render(r"\ce{2H2 + O2 -> 2H2O}").unwrap();
+```
+
 ### Font Detection
 
-The library can analyze rendered KaTeX HTML to determine which fonts are used:
+The library can analyze rendered KaTeX HTML to determine which fonts are used:
+
+```rust
+use katex_gdef_v8::{render, font_extract};
+use std::collections::HashSet;
+
+// Render a LaTeX expression
+let html = render(r"\mathcal{F}(x) = \int_{-\infty}^{\infty} f(x) e^{-2\pi i x \xi} dx").unwrap();
+
+// Extract font information
+let used_fonts = font_extract(&html);
+
+// Check if specific fonts are used
+println!("Is empty: {}", used_fonts.is_empty());
+
+// Iterate through used fonts (iterating a reference leaves `used_fonts` intact)
+for font_name in &used_fonts {
+    // Each font_name is the base name (e.g., "KaTeX_Math-Italic")
+    // To get the complete font file name, add file extension:
+    println!("Font file: {}.woff2", font_name);
+}
+
+// Collect all font names into a HashSet
+let font_set: HashSet<&str> = used_fonts.collect();
+
+// Example assertion for testing
+assert_eq!(
+    font_set,
+    HashSet::from([
+        "KaTeX_Main-Regular",
+        "KaTeX_Math-Italic",
+        "KaTeX_Size1-Regular",
+        "KaTeX_Caligraphic-Regular"
+    ])
+);
+```
+
+The `FontFlags` struct provides detailed information about all KaTeX fonts used in the rendered output, which can be useful for:
+
+- Optimizing font loading by only including required fonts (each font name can be used with extensions like `.woff2`, `.woff`, `.ttf`)
+- Selective font preloading in web applications
+
+`font_extract` accepts a whole HTML page as well as a single fragment; every rendered formula on the page is scanned and the results are merged. Output rendered with `KatexOutput::Mathml` has no `katex-html` part; in that case the `<math>` element is analyzed instead, mapping `mathvariant` values to the KaTeX fonts a browser would use.
+
+For streaming pipelines, `FontScanner` takes the page chunk by chunk and only buffers the formula it is currently inside:
+
+```rust
+use katex_gdef_v8::{FontScanner, render};
+
+let mut scanner = FontScanner::new();
+scanner.feed("<p>Euler: ");
+scanner.feed(&render(r"e^{i\pi} + 1 = 0").unwrap());
+scanner.feed("</p>");
+assert!(scanner.finish().contains("KaTeX_Math-Italic"));
+```
+
+`UsedFonts` also supports set operations, which is handy for comparing a page against the fonts you already ship:
+
+```rust
+use katex_gdef_v8::{UsedFonts, font_extract, render};
+
+let shipped = UsedFonts::from_names(["KaTeX_Main-Regular", "KaTeX_Math-Italic"]);
+let used = font_extract(&render(r"\mathbb{R}").unwrap());
+let missing = used.difference(&shipped);
+assert!(missing.contains("KaTeX_AMS-Regular"));
+assert_eq!(missing.len(), 1);
+```
+
+`preload_links` turns the result into `<link rel="preload">` tags (with the `crossorigin` attribute fonts require) for server-side rendering:
+
+```rust
+use katex_gdef_v8::{FontFormat, font_extract, render};
+
+let html = render(r"x^2").unwrap();
+for link in font_extract(&html).preload_links("/fonts", FontFormat::Woff2) {
+    // <link rel="preload" href="/fonts/KaTeX_Main-Regular.woff2" as="font" type="font/woff2" crossorigin>
+    println!("{link}");
+}
+```
+
+For subsetting, `glyph_extract` goes one step further and records which characters each font has to render:
+
+```rust
+use katex_gdef_v8::{glyph_extract, render};
+
+let html = render(r"\mathbf{A} + x").unwrap();
+let glyphs = glyph_extract(&html);
+assert!(glyphs["KaTeX_Main-Bold"].contains(&'A'));
+assert!(glyphs["KaTeX_Math-Italic"].contains(&'x'));
+```
+
+### CSS Class Coverage
+
+`class_extract` lists the CSS classes used inside the rendered formulas of a fragment or page, so unused rules can be purged from `katex.css`:
+
+```rust
+use katex_gdef_v8::{class_extract, render};
+
+let classes = class_extract(&render(r"\frac{a}{b}").unwrap());
+assert!(classes.contains("mfrac"));
+assert!(!classes.contains("sqrt"));
+```
+
+`inline_css` uses that coverage (together with the detected fonts) to cut a copy of `katex.css` down to the rules the fragment needs and prepends them as a `<style>` block, giving self-styled HTML for e-mail templates and similar environments. `prune_css` does the pruning alone. The stylesheet is not bundled; pass the `katex.min.css` that matches your KaTeX version:
+
+```rust,no_run
+use katex_gdef_v8::{inline_css, render};
+
+let katex_css = std::fs::read_to_string("vendor/katex.min.css").unwrap();
+let html = inline_css(&render(r"\sqrt{2}").unwrap(), &katex_css);
+```
+
+### Standalone Pages
+
+With the `standalone` feature, `render_standalone` renders one formula into a complete HTML document for offline previews and self-contained reports. It takes the directory of a KaTeX distribution (containing `katex.min.css` and `fonts/`), inlines the CSS rules the formula needs and embeds the used fonts as base64 `woff2` data URIs:
+
+```rust,no_run
+# #[cfg(feature = "standalone")] {
+use katex_gdef_v8::{Options, render_standalone};
+
+let page = render_standalone(r"e^{i\pi} + 1 = 0", &Options::builder().display(true).build(), "vendor/katex").unwrap();
+std::fs::write("euler.html", page).unwrap();
+# }
+```
+
+`standalone_html` does the same for HTML you have already rendered.
+
+### SVG and PNG Images
+
+The `png` feature adds `render_svg`, which lays out KaTeX's HTML without a browser and returns a standalone SVG, sized like the HTML with a 16px body font. The layout is an approximation of KaTeX's CSS written for this crate: it follows KaTeX's metrics for scripts, fractions, roots, accents, stretchy arrows and braces, arrays and boxes, but does not break long inline formulas into lines, and unusual markup can come out misplaced. Use the HTML output where a browser is available. Glyphs are written as text in the KaTeX fonts, so whatever displays the SVG needs those fonts. As with `measure`, `output` and `throw_on_error` are ignored:
+
+```rust
+# #[cfg(feature = "png")] {
+use katex_gdef_v8::{Options, render_svg};
+
+let svg = render_svg(r"\frac{a}{b}", &Options::default()).unwrap();
+assert!(svg.starts_with("<svg"));
+# }
+```
+
+`render_png` rasterizes that SVG with [resvg](https://crates.io/crates/resvg) at the requested DPI (96 DPI keeps the SVG's own pixel size), so chat bots and badge generators get image bytes straight from LaTeX. The KaTeX fonts are read from the `fonts/` directory of a KaTeX distribution, as with `render_standalone`. `svg_to_png` rasterizes any other SVG with the system fonts. Fonts are loaded once per directory and reused:
+
+```rust,ignore
+let png = katex_gdef_v8::render_png(r"\frac{a}{b}", &Default::default(), "node_modules/katex/dist", 300.0).unwrap();
+std::fs::write("formula.png", png).unwrap();
+```
+
+### Measuring Formulas
+
+`measure` returns the size of a formula in `em` without a browser, for PDF and typesetting integrations that need to reserve space and align baselines. `height_em` and `depth_em` come from KaTeX's layout (above and below the baseline); KaTeX does not track widths, so `width_em` is estimated from glyph metrics and spacing. The formula is always laid out as HTML for this, whatever `output` says, and a parse error is returned as an error even with `throw_on_error: false`:
+
+```rust,no_run
+use katex_gdef_v8::{Options, measure};
+
+let size = measure(r"\frac{a}{b}", &Options::default()).unwrap();
+println!("{} x {} (baseline at {})", size.width_em, size.height_em + size.depth_em, size.height_em);
+```
+
+### Command Line
+
+With the `cli` feature a `katex-gdef` binary is built (`cargo install katex-gdef-v8 --features cli`). It renders each file given (or stdin) and prints the HTML, carrying `\gdef` macros from one input to the next:
+
+```sh
+echo '\frac{a}{b}' | katex-gdef --display
+katex-gdef --macros-file preamble.tex --cache /tmp/katex.snapshot --output out.html a.tex b.tex
+```
+
+`--macros-file` accepts a `MacroStore` JSON file (`.json`) or a TeX preamble.
+
+Editors and build tools can keep one warm process with `--serve-stdio`. It reads one JSON request per line from stdin and answers each with one line on stdout, carrying macros across requests as above. The `id` of a request is echoed back, `options` defaults to the command line's, and `macros` replaces the macros carried so far:
+
+```sh
+$ katex-gdef --serve-stdio
+{"id": 1, "latex": "\\gdef\\R{\\mathbb{R}} \\R", "options": {"displayMode": true}}
+{"id":1,"html":"<span class=\"katex-display\">..."}
+{"id": 2, "latex": "\\frac{"}
+{"id":2,"error":"KaTeX parse error: Expected '}', got 'EOF' at end of input: \\frac{"}
+```
+
+### Markdown
+
+With the `markdown` feature, `MarkdownMath` wraps a [pulldown-cmark](https://crates.io/crates/pulldown-cmark) event iterator and replaces math with rendered HTML: the `InlineMath`/`DisplayMath` events of `ENABLE_MATH` as well as `$...$` and `$$...$$` in text (code blocks are left alone). Macros and used fonts are collected across the whole document; formulas that fail to render stay as text and their errors are kept:
+
+```rust,ignore
+use katex_gdef_v8::MarkdownMath;
+use pulldown_cmark::{Parser, html};
+
+let mut math = MarkdownMath::new(Parser::new(r"Let $\gdef\R{\mathbb{R}} x \in \R$ and $$\R^2$$"));
+let mut out = String::new();
+html::push_html(&mut out, math.by_ref());
+let (macros, fonts, errors) = math.finish();
+```
+
+### comrak
+
+With the `comrak` feature, `ComrakMath` renders the math of [comrak](https://crates.io/crates/comrak)'s math extensions (`math_dollars`, `math_latex`, `math_code` and ```` ```math ```` blocks) with KaTeX, sharing macros across the document. Use `markdown_to_html`, call `render_math` on a parsed AST yourself, or register it as the `"math"` code fence renderer in `RenderPlugins`:
+
+```rust,ignore
+use katex_gdef_v8::ComrakMath;
+
+let mut options = comrak::Options::default();
+options.extension.math_dollars = true;
+let math = ComrakMath::new();
+let html = math.markdown_to_html(r"$\gdef\R{\mathbb{R}} x \in \R$ and $$\R^2$$", &options).unwrap();
+let fonts = math.used_fonts();
+```
+
+### minijinja
+
+With the `minijinja` feature, `KatexFilter` registers a `katex` filter on a [minijinja](https://crates.io/crates/minijinja) `Environment`, so templates can write `{{ formula | katex }}` or `{{ formula | katex(display=true) }}`. Macros defined by one formula are available to the later ones in the same template render and start over with the next render. The output is marked safe, and a formula that fails to render fails the template with the KaTeX error as its source:
+
+```rust,ignore
+use katex_gdef_v8::KatexFilter;
+
+let mut env = minijinja::Environment::new();
+KatexFilter::new().register(&mut env);
+let html = env.render_str(r"{{ '\gdef\R{\mathbb{R}} x' | katex }} {{ '\R^2' | katex(display=true) }}", ()).unwrap();
+```
+
+### axum
+
+With the `axum` feature, `axum::router()` returns an [axum](https://crates.io/crates/axum) `Router` serving `POST /render`, so an internal render service takes a few lines. The request body is `{"latex": ..., "options": ..., "macros": ...}` with `options` in KaTeX's camelCase form and both optional. The response is `{"html": ..., "macros": ..., "fonts": ...}`, where `macros` includes the macros defined by the formula. A KaTeX error is answered with 400, an overloaded renderer with 503 and anything else with 500, each with an `{"error": ...}` body. `router_with_renderer` serves from a renderer of your own:
+
+```rust,ignore
+let app = katex_gdef_v8::axum::router();
+let listener = tokio::net::TcpListener::bind("127.0.0.1:3000").await?;
+axum::serve(listener, app).await?;
+```
+
+### Render Daemon
+
+With the `katexd` feature a `katexd` binary is built, which serves the API of the `axum` feature over HTTP for stacks that are not written in Rust. Besides `POST /render` it accepts `POST /batch` with `{"items": [{"latex": ..., "options": ...}], "macros": ...}` and renders all items in one batch. Each item gets either an `html` or an `error` field. Under `/namespaces/<key>/` the same two routes use macros kept on the server per client key instead of the ones in the request, so `\gdef` in one request is available in the next. `GET /namespaces/<key>/macros` returns them and `DELETE /namespaces/<key>` drops them:
+
+```sh
+katexd --addr 0.0.0.0:3000 --cache katex.snapshot --macros-file preamble.tex --macros-dir namespaces/
+curl -d '{"latex": "\\gdef\\R{\\mathbb{R}} \\R"}' localhost:3000/namespaces/blog/render
+curl -d '{"latex": "\\R^2"}' localhost:3000/namespaces/blog/render
+```
+
+`--macros-file` gives every new namespace a starting set of macros, from a `MacroStore` JSON file or a TeX preamble. With `--macros-dir`, each namespace is saved as `<key>.json` whenever it changes and survives restarts. Keys may contain ASCII letters, digits, `-` and `_`.
+
+A service rendering untrusted LaTeX can keep KaTeX away from the rest of the application with the [sandboxed engine](#sandboxed-engine), or by running `katexd` as a separate process under an unprivileged user, a container or seccomp. Combine either with `Options::strict_secure()`, `render_timeout` and `max_heap_mb`.
+
+### mdBook
+
+With the `mdbook` feature a `mdbook-katex-gdef` preprocessor is built. It renders the math of every chapter in table-of-contents order, so macros defined with `\gdef` in one chapter are available in the later ones. Math inside code blocks and inline code is left alone:
+
+```toml
+[preprocessor.katex-gdef]
+macros-file = "preamble.tex"    # optional, MacroStore JSON or a TeX preamble
+cache = "katex.snapshot"        # optional
+fonts-file = "katex-fonts.json" # optional, used fonts of the whole book and of each chapter
+```
+
+### C Bindings
+
+The `ffi` feature exports `katex_render`, `katex_render_opts` and `katex_free_string` with the C ABI, declared in [`include/katex_gdef_v8.h`](include/katex_gdef_v8.h). Build a shared library with:
+
+```sh
+cargo rustc --release --lib --features ffi --crate-type cdylib
+```
+
+```c
+char *error = NULL;
+char *html = katex_render_opts("x^2", "{\"displayMode\":true}", &error);
+if (html) { puts(html); katex_free_string(html); } else { fputs(error, stderr); katex_free_string(error); }
+```
+
+### WebAssembly
+
+For `wasm32` targets, disable the default features and enable `wasm`. Instead of embedding V8, KaTeX then runs in the host JavaScript environment (browser or Node.js) through `wasm-bindgen`, with the same Rust API:
+
+```toml
+[dependencies]
+katex-gdef-v8 = { version = "0.1.6", default-features = false, features = ["wasm", "katex-0_16"] }
+```
+
+There are no threads on `wasm32`, so rendering runs synchronously on the calling thread; snapshots are not used and `render_timeout` cannot interrupt a running formula.
+
+### Pure-Rust Engine
+
+The `boa` feature runs KaTeX on [Boa](https://boajs.dev), a JavaScript engine written in Rust. It needs no C or C++ toolchain and no prebuilt V8 download, so it builds on targets where V8 and QuickJS are painful to compile:
+
+```toml
+[dependencies]
+katex-gdef-v8 = { version = "0.1.6", default-features = false, features = ["boa", "katex-0_16"] }
+```
+
+The API is the same as with V8. Boa is noticeably slower, has no snapshots (KaTeX is evaluated again whenever a worker starts, and the cache path is ignored), and cannot stop a running script, so `render_timeout` returns `Error::Timeout` but the worker finishes the formula before taking the next job.
+
+### rquickjs
+
+The `rquickjs` feature runs KaTeX on QuickJS through [rquickjs](https://github.com/DelSkayn/rquickjs) instead of the `qjs` crate. It bundles its own copy of QuickJS and builds it with only a C compiler, without libclang:
+
+```toml
+[dependencies]
+katex-gdef-v8 = { version = "0.1.6", default-features = false, features = ["rquickjs", "katex-0_16"] }
+```
+
+rquickjs cannot write a script back as bytecode, so KaTeX is evaluated again whenever a worker starts and the cache path is ignored. `render_timeout` interrupts a running formula as with the other QuickJS backend.
+
+### Sandboxed Engine
+
+The `sandbox` feature runs KaTeX on QuickJS compiled to WebAssembly, inside [wasmtime](https://wasmtime.dev). The guest sees no files, network, environment or clock; it can only touch its own linear memory. A bug in KaTeX or in the JS engine therefore cannot reach the rest of the process:
+
+```toml
+[dependencies]
+katex-gdef-v8 = { version = "0.1.6", default-features = false, features = ["sandbox", "katex-0_16"] }
+```
+
+The build script compiles the guest in `sandbox-guest/` with a nested cargo, so the `wasm32-wasip1` target must be installed (`rustup target add wasm32-wasip1`). rquickjs-sys builds QuickJS with wasi-sdk, which it downloads or takes from the `WASI_SDK` environment variable. Two environment variables change how the guest is built:
+
+- `KATEX_GDEF_SANDBOX_GUEST=boa` runs Boa in the guest instead of QuickJS. It needs no C compiler and no wasi-sdk, but it is slower.
+- `KATEX_GDEF_SANDBOX_WASM=<path>` uses a guest built beforehand and skips the nested build.
+
+`max_heap_mb` caps the guest's linear memory, and a render that grows beyond it returns `Error::OutOfMemory`. `render_timeout` stops the running formula through wasmtime's epoch interruption. After either, the guest is rebuilt from a copy of its memory taken after KaTeX was loaded, so later renders keep working. The cache path stores that memory image; it holds only guest data, so a modified cache file cannot escape the sandbox. wasmtime compiles the guest once per process when the first worker starts. This takes a few seconds in release builds and much longer in debug builds.
+
+### Choosing the Engine at Runtime
+
+With more than one of the `v8`, `qjs`, `rquickjs`, `boa` and `sandbox` features enabled, one binary contains all of them and `InitOptions::engine` picks one per renderer. `Engine::ALL` lists the engines in the build. The default is the first of V8, QuickJS, rquickjs, Boa and the sandbox that is built. `max_heap_mb` only applies to V8 and sandbox renderers, and `configure_v8` only to V8 renderers:
+
+```toml
+[dependencies]
+katex-gdef-v8 = { version = "0.1.6", features = ["qjs"] }
+```
+
+```rust,ignore
+use katex_gdef_v8::{Engine, InitOptions, Renderer};
+
+let renderer = Renderer::new(InitOptions::default().engine(Engine::QuickJs));
+println!("{}", renderer.render(r"x^2").unwrap());
+```
+
+Renderers with different engines should use different cache paths, because a snapshot made by one engine cannot be loaded by the other and would be recreated every time.
+
+### Setting Up Cache
+
+```rust
+use katex_gdef_v8::{set_cache, render};
+use std::path::Path;
+
+// Set path to cache V8 snapshot
+set_cache(Path::new("./katex-cache"));
+
+// Subsequent renderings will be faster
+let html = render(r"E = mc^2").unwrap();
+println!("{}", html);
+```
+
+`set_cache_default()` picks a per-user location instead (`$XDG_CACHE_HOME` or `~/.cache` on Linux, `~/Library/Caches` on macOS, `%LOCALAPPDATA%` on Windows), namespaced by crate version and engine, and returns the chosen path:
+
+```rust,no_run
+let path = katex_gdef_v8::set_cache_default().unwrap();
+println!("caching snapshots at {}", path.display());
+```
+
+Cache files start with a small header recording the crate version, the KaTeX version, the engine (with its V8 version) and a fingerprint of the loaded scripts. A cache written by a different setup is regenerated instead of producing a broken runtime. Snapshots are written to a temporary file and renamed into place, and creation is guarded by an advisory lock on a `.lock` file next to the cache, so many processes sharing one cache path build it only once.
+
+V8 snapshots of KaTeX take several megabytes. The `zstd` feature compresses cache files on write and decompresses them on startup, which helps on slow or network filesystems.
+
+Tools that cannot write a cache can enable the `embedded-snapshot` feature instead. The build script creates the V8 snapshot at compile time and embeds it in the binary, so the first render needs no file I/O. The embedded snapshot is used whenever the default KaTeX build is selected, no `extra_js` is given, and neither `configure_v8` nor `max_heap_mb` is set. The build script applies neither of them, so with either one the snapshot is created at runtime as without the feature.
+
+```toml
+[dependencies]
+katex-gdef-v8 = { version = "0.1.6", features = ["embedded-snapshot"] }
+```
+
+The engine is created lazily on a worker thread. Call `init()` (or `Renderer::warm_up` / `Renderer::try_new`) at startup to build it up front and get JS or IO failures as an `Error` instead of a failed first render:
+
+```rust
+katex_gdef_v8::init().expect("failed to start KaTeX");
+```
+
+Without `init()`, a render that reaches a worker whose engine could not be built returns the same construction error, as `Error::JSError`, instead of a dead-worker error. Each later call tries to build the engine again.
+
+### Configuring from the Environment
+
+`init_from_env()` sets up the shared renderer from environment variables, so deployments can tune it without code changes:
+
+- `KATEX_GDEF_CACHE`: snapshot cache path, or `default` for `default_cache_path()`.
+- `KATEX_GDEF_ENGINE`: `v8`, `qjs`, `rquickjs`, `boa`, `sandbox` or `wasm`. An engine that is not part of the build is reported as an error. When several of `v8`, `qjs`, `rquickjs`, `boa` and `sandbox` are enabled, any of them can be chosen.
+- `KATEX_GDEF_WORKERS`: number of shared worker threads, each with its own engine. Calls go to the worker with the fewest queued jobs.
+
+```rust,no_run
+// KATEX_GDEF_CACHE=default KATEX_GDEF_WORKERS=4 ./server
+katex_gdef_v8::init_from_env().unwrap();
+let html = katex_gdef_v8::render(r"\sum_{k=1}^n k").unwrap();
+```
+
+Call it before anything else uses the shared renderer; afterwards only `KATEX_GDEF_ENGINE` is checked. `stats()` adds up the counters of all workers.
+
+### Releasing Idle Engines
+
+A KaTeX engine holds tens of megabytes. Applications that render math rarely can let the worker stop after a quiet period; the next call starts it again, from the snapshot cache when one is configured:
 
 ```rust
-use katex_gdef_v8::{render, font_extract};
-use std::collections::HashSet;
+use katex_gdef_v8::{InitOptions, Renderer};
+use std::time::Duration;
 
-// Render a LaTeX expression
-let html = render(r"\mathcal{F}(x) = \int_{-\infty}^{\infty} f(x) e^{-2\pi i x \xi} dx").unwrap();
+let renderer = Renderer::new(InitOptions::default().cache("./katex-cache").idle_timeout(Duration::from_secs(300)));
+let html = renderer.render(r"e^{i\pi} + 1 = 0").unwrap();
+```
 
-// Extract font information
-let used_fonts = font_extract(&html);
+### Independent Renderers
 
-// Check if specific fonts are used
-println!("Is empty: {}", used_fonts.is_empty());
+The free functions share one process-wide KaTeX worker. A `Renderer` owns its own worker thread, so libraries can keep their caches and settings apart:
 
-// Iterate through used fonts
-for font_name in used_fonts.clone() {
-    // Each font_name is the base name (e.g., "KaTeX_Math-Italic")
-    // To get the complete font file name, add file extension:
-    println!("Font file: {}.woff2", font_name);
-}
+```rust
+use katex_gdef_v8::{InitOptions, Renderer};
 
-// Collect all font names into a HashSet
-let font_set: HashSet<&str> = used_fonts.collect();
+let renderer = Renderer::new(InitOptions::default().cache("./katex-cache-mylib"));
+let html = renderer.render(r"E = mc^2").unwrap();
+println!("{}", html);
+```
 
-// Example assertion for testing
-assert_eq!(
-    font_set,
-    HashSet::from([
-        "KaTeX_Main-Regular",
-        "KaTeX_Math-Italic",
-        "KaTeX_Size1-Regular",
-        "KaTeX_Caligraphic-Regular"
-    ])
+Additional KaTeX extensions or helper scripts can be injected with `InitOptions::extra_js`. They run after `katex.min.js` and are captured in the snapshot:
+
+```rust
+use katex_gdef_v8::{InitOptions, Renderer};
+
+let renderer = Renderer::new(InitOptions::default().extra_js(vec![
+    r#"katex.__defineMacro("\\half", "\\frac{1}{2}");"#.to_string(),
+]));
+let html = renderer.render(r"\half").unwrap();
+println!("{}", html);
+```
+
+Named JS functions can be registered with `InitOptions::helper`. They are defined after the shim and before `extra_js`, so they are in the snapshot as well. `Options::helpers` passes the rendered HTML through the named helpers in order inside the engine, for example to rewrite what `\htmlData` put into the HTML. `eval_helper` calls a helper directly with JSON arguments and returns its JSON result:
+
+```rust
+use katex_gdef_v8::{InitOptions, Options, Renderer};
+use serde_json::json;
+use std::collections::BTreeMap;
+
+let renderer = Renderer::new(
+    InitOptions::default()
+        .helper("upper", "text => text.toUpperCase()")
+        .helper("links", r#"html => html.replace(/data-href="([^"]*)"/g, 'data-href="https://example.com/$1"')"#),
 );
+assert_eq!(renderer.eval_helper("upper", &json!("abc")).unwrap(), json!("ABC"));
+
+let options = Options::builder().trust(true).helpers(["links"]).build();
+let html = renderer.render_with_opts(r"\htmlData{href=x}{y}", &options, &mut BTreeMap::new()).unwrap();
+assert!(html.contains(r#"data-href="https://example.com/x""#));
+let parts = renderer.render_parts(r"\htmlData{href=x}{y}", &options, &mut BTreeMap::new()).unwrap();
+assert!(parts.html.contains(r#"data-href="https://example.com/x""#));
 ```
 
-The `FontFlags` struct provides detailed information about all KaTeX fonts used in the rendered output, which can be useful for:
+To pin a newer or patched KaTeX build, pass its source with `Renderer::with_katex_source` (or `InitOptions::katex_source`). Disabling the default `katex-0_16` feature leaves the bundled KaTeX out of the binary; renderers then need an explicit source:
 
-- Optimizing font loading by only including required fonts (each font name can be used with extensions like `.woff2`, `.woff`, `.ttf`)
-- Selective font preloading in web applications
+```rust,no_run
+use katex_gdef_v8::Renderer;
 
-### Setting Up Cache
+let katex_js = std::fs::read_to_string("vendor/katex.min.js").unwrap();
+let renderer = Renderer::with_katex_source(&katex_js);
+```
+
+Bundled KaTeX builds are selected by features (currently `katex-0_16`, enabled by default) and by `InitOptions::katex_version`. Each bundled version gets its own snapshot file, named after the cache path with the KaTeX version appended (for example `./katex-cache-0.16.21`), and the version is also written into the snapshot header. Without `katex_version` the newest enabled build is used; `KATEX_VERSION` is its version. `KatexVersion` is `#[non_exhaustive]`, so a later `katex-0_17` feature adds a variant without breaking code that matches on it:
 
 ```rust
-use katex_gdef_v8::{set_cache, render};
-use std::path::Path;
+use katex_gdef_v8::{InitOptions, KatexVersion, Renderer};
 
-// Set path to cache V8 snapshot
-set_cache(Path::new("./katex-cache"));
+let renderer = Renderer::new(InitOptions::default().katex_version(KatexVersion::V0_16).cache("./katex-cache"));
+```
 
-// Subsequent renderings will be faster
-let html = render(r"E = mc^2").unwrap();
-println!("{}", html);
+The worker thread can be named for debuggers and profilers, and given a larger stack for deeply nested input, where KaTeX's recursion can overflow the default stack. If the thread cannot be spawned, `Renderer::try_new` and `warm_up` return `Error::Spawn`. Later calls try to spawn it again and return the same error if that fails too:
+
+```rust
+use katex_gdef_v8::{InitOptions, Renderer};
+
+let renderer = Renderer::try_new(InitOptions::default().thread_name("katex").stack_size(16 * 1024 * 1024)).unwrap();
 ```
 
+If the worker thread dies (for example after a panic inside the engine), the next call starts a fresh worker from the same snapshot and retries the request once.
+
+Dropping a `Renderer` (or calling `Renderer::shutdown`) lets the worker finish the queued requests and then joins its thread. `katex_gdef_v8::shutdown()` does the same for the shared worker; afterwards the free functions return `Error::ShutDown`.
+
 ## Comparison with `katex-rs`
 
 * **Macro collection and reuse**: Ability to reuse macros defined in equations in subsequent renderings (main differentiating feature)
@@ -138,190 +1277,885 @@ Note that `katex-rs` supports more JavaScript engines (duktape, wasm-js, etc.),
 This project is licensed under the MIT License - see the [LICENSE](LICENSE) file for details.
 */
 
+// tracingが有効なときだけ、スコープを抜けるまで続くspanに入る
+macro_rules! span {
+    ($($args:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!($($args)*).entered();
+        #[cfg(not(feature = "tracing"))]
+        let span = crate::NoSpan;
+        span
+    }};
+}
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct NoSpan;
+
+mod auto_render;
+#[cfg(feature = "axum")]
+pub mod axum;
+mod bundle;
+// wasm、rquickjs、Boaのエンジンはスナップショットを使わない
+#[cfg_attr(any(feature = "wasm", not(any(feature = "v8", feature = "qjs", feature = "sandbox"))), allow(dead_code))]
+mod cache;
+#[cfg(feature = "comrak")]
+mod comrak_math;
+mod css;
+mod document;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 mod font;
+mod macros;
+#[cfg(feature = "markdown")]
+mod markdown;
+mod memo;
+#[cfg(feature = "minijinja")]
+mod minijinja_filter;
+mod options;
+#[cfg(feature = "png")]
+mod png;
+pub mod presets;
+mod pretty;
+mod renderer;
+#[cfg(feature = "sanitize")]
+mod sanitize;
+#[cfg(feature = "speech")]
+mod speech;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod text;
 
 #[cfg(feature = "v8")]
-#[cfg(not(feature = "qjs"))]
+#[cfg(not(feature = "wasm"))]
 mod v8;
 #[cfg(feature = "v8")]
-#[cfg(not(feature = "qjs"))]
-type Engine = v8::Engine;
+#[cfg(not(any(multi_engine, feature = "wasm")))]
+type JsEngine = v8::Engine;
 #[cfg(feature = "v8")]
-#[cfg(not(feature = "qjs"))]
+#[cfg(not(any(multi_engine, feature = "wasm")))]
 pub use v8::Error as JSError;
+// configure_v8で使うdeno_coreの型を、同じ版のまま使えるようにする
+#[cfg(feature = "v8")]
+#[cfg(not(feature = "wasm"))]
+pub use deno_core;
 
 #[cfg(feature = "qjs")]
+#[cfg(not(feature = "wasm"))]
 mod qjs;
 #[cfg(feature = "qjs")]
-type Engine = qjs::Engine;
+#[cfg(not(any(multi_engine, feature = "wasm")))]
+type JsEngine = qjs::Engine;
 #[cfg(feature = "qjs")]
+#[cfg(not(any(multi_engine, feature = "wasm")))]
 pub use qjs::Error as JSError;
 
-#[cfg(not(any(feature = "v8", feature = "qjs")))]
-compile_error!("At least one of the features 'v8' or 'qjs' must be enabled");
+#[cfg(feature = "rquickjs")]
+#[cfg(not(feature = "wasm"))]
+mod rqjs;
+#[cfg(feature = "rquickjs")]
+#[cfg(not(any(multi_engine, feature = "wasm")))]
+type JsEngine = rqjs::Engine;
+#[cfg(feature = "rquickjs")]
+#[cfg(not(any(multi_engine, feature = "wasm")))]
+pub use rqjs::Error as JSError;
+
+#[cfg(feature = "boa")]
+#[cfg(not(feature = "wasm"))]
+mod boa;
+#[cfg(feature = "boa")]
+#[cfg(not(any(multi_engine, feature = "wasm")))]
+type JsEngine = boa::Engine;
+#[cfg(feature = "boa")]
+#[cfg(not(any(multi_engine, feature = "wasm")))]
+pub use boa::Error as JSError;
+
+#[cfg(feature = "sandbox")]
+#[cfg(not(feature = "wasm"))]
+mod sandbox;
+#[cfg(feature = "sandbox")]
+#[cfg(not(any(multi_engine, feature = "wasm")))]
+type JsEngine = sandbox::Engine;
+#[cfg(feature = "sandbox")]
+#[cfg(not(any(multi_engine, feature = "wasm")))]
+pub use sandbox::Error as JSError;
+
+// v8/qjs/rquickjs/boa/sandboxのうち二つ以上を有効にしたときは、InitOptions::engineでレンダラーごとに選ぶ(multi_engineはbuild.rsで決める)
+#[cfg(multi_engine)]
+mod multi;
+#[cfg(multi_engine)]
+type JsEngine = multi::Engine;
+#[cfg(multi_engine)]
+pub use multi::Error as JSError;
+
+// wasm32向け。V8やQuickJSを埋め込まず、ホストのJS環境を使う
+#[cfg(feature = "wasm")]
+mod wasm;
+#[cfg(feature = "wasm")]
+type JsEngine = wasm::Engine;
+#[cfg(feature = "wasm")]
+pub use wasm::Error as JSError;
+
+#[cfg(not(any(feature = "v8", feature = "qjs", feature = "rquickjs", feature = "boa", feature = "sandbox", feature = "wasm")))]
+compile_error!("At least one of the features 'v8', 'qjs', 'rquickjs', 'boa', 'sandbox' or 'wasm' must be enabled");
 
 use once_cell::sync::OnceCell;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use std::{
     borrow::Cow,
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     path::{Path, PathBuf},
-    sync::mpsc::{self, Receiver, Sender},
-    thread,
+    sync::{Arc, mpsc},
+    time::Duration,
 };
-pub static KATEX_VERSION: &str = "0.16.21";
-static KATEX_CODE: &str = concat!(
-    include_str!("./katex.min.js"),
-    r#"function renderToStringAndMacros(input) {
-        try {
-            const html = katex.renderToString(
-                input.latex,
-                Object.assign({}, input.options, { macros: input.macros })
-            );
-            for (let key in input.macros) if (typeof input.macros[key] !== "string") {
-                input.macros[key] = input.macros[key].tokens.map(token => token.text).reverse().join("");
-            }
-            return JSON.stringify({ html: html, macros: input.macros });
-        } catch (e) {
-            if (e instanceof katex.ParseError) {
-                for (let key in input.macros) if (typeof input.macros[key] !== "string") {
-                    input.macros[key] = input.macros[key].tokens.map(token => token.text).reverse().join("");
-                }
-                return JSON.stringify({ error: e.message, macros: input.macros });
-            } else {
-                throw e;
-            }
-        }
-    }"#
-);
+// 既定で使う同梱版のバージョン。同梱版がなければ"custom"
+pub static KATEX_VERSION: &str = match KatexVersion::latest() {
+    Some(version) => version.version(),
+    None => "custom",
+};
+#[cfg(feature = "mhchem")]
+static MHCHEM_JS: &str = include_str!("./contrib/mhchem.min.js");
 
+// KaTeX本体(指定がなければ同梱版)、拡張、シム、ヘルパー、追加スクリプトの順に連結する
+fn katex_code(katex_source: Option<&str>, helpers: &BTreeMap<String, String>, extra_js: &[String]) -> String {
+    let mut code = String::from(katex_source.unwrap_or_default());
+    #[cfg(feature = "mhchem")]
+    {
+        code.push('\n');
+        code.push_str(MHCHEM_JS);
+    }
+    code.push('\n');
+    code.push_str(SHIM_JS);
+    #[cfg(feature = "png")]
+    {
+        code.push('\n');
+        code.push_str(SVG_JS);
+    }
+    for (name, function) in helpers {
+        code.push_str(&format!("\nkatexHelpers[{}] = ({function});", serde_json::Value::from(name.as_str())));
+    }
+    for script in extra_js {
+        code.push('\n');
+        code.push_str(script);
+    }
+    code
+}
+// katex.min.jsの後に読み込まれ、Rust側から呼ばれる関数群
+static SHIM_JS: &str = include_str!("./shim.js");
+// render_svgでHTMLの木をSVGに並べる部分。shim.jsのwithHtmlTreeを使う
+#[cfg(feature = "png")]
+static SVG_JS: &str = include_str!("./svg.js");
+
+// 呼び出し側でJSONにしてからワーカーに渡すので、借用したままでよい
 #[derive(Clone, Debug, Serialize)]
-struct Input {
-    pub latex: String,
-    pub options: Options,
-    pub macros: BTreeMap<String, String>,
+struct Input<'a> {
+    pub latex: &'a str,
+    pub options: &'a Options,
+    pub macros: &'a BTreeMap<String, MacroValue>,
 }
 
 #[derive(Clone, Debug, Serialize)]
+struct BatchInput<'a> {
+    pub items: Vec<BatchItem<'a>>,
+    pub macros: &'a BTreeMap<String, MacroValue>,
+}
+#[derive(Clone, Debug, Serialize)]
+struct BatchItem<'a> {
+    pub latex: &'a str,
+    pub options: &'a Options,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Output {
+    Success {
+        #[serde(flatten)]
+        rendered: Rendered,
+        // throw_on_errorがfalseでKaTeXがエラーを描画した
+        #[serde(default)]
+        failed: bool,
+        macros: BTreeMap<String, MacroValue>,
+    },
+    Error {
+        error: String,
+        macros: BTreeMap<String, MacroValue>,
+    },
+}
+impl Output {
+    fn into_html(self, latex: &str, macros: &mut BTreeMap<String, MacroValue>, options: &Options) -> Result<String, Error> {
+        Ok(self.into_rendered(latex, macros, options)?.html)
+    }
+    fn into_rendered(self, latex: &str, macros: &mut BTreeMap<String, MacroValue>, options: &Options) -> Result<Rendered, Error> {
+        self.commit(macros, options);
+        self.into_result(latex)
+    }
+    // マクロはどこにも書き戻さず、描画の結果だけを取り出す
+    fn into_result(self, latex: &str) -> Result<Rendered, Error> {
+        match self {
+            Output::Success { rendered, .. } => Ok(rendered),
+            Output::Error { error, macros: macros_value } => {
+                Err(Error::KaTeXError { message: error, latex: latex.to_string(), macros: macros_value })
+            }
+        }
+    }
+    // throw_on_errorがfalseでエラーを描画したものは、失敗したものとして扱う
+    fn commit(&self, macros: &mut BTreeMap<String, MacroValue>, options: &Options) {
+        match self {
+            Output::Success { failed, macros: returned, .. } => commit_macros(macros, returned, !failed, options),
+            Output::Error { macros: returned, .. } => commit_macros(macros, returned, false, options),
+        }
+    }
+}
+
+// macro_commitとmacro_filterに従って、数式が返したマクロを呼び出し側の表に書き戻す。マクロを返す描画は全てここを通す
+fn commit_macros(macros: &mut BTreeMap<String, MacroValue>, returned: &BTreeMap<String, MacroValue>, succeeded: bool, options: &Options) {
+    if !options.macro_commit.applies(succeeded) {
+        return;
+    }
+    match &options.macro_filter {
+        Some(filter) => filter.merge(macros, returned),
+        None => *macros = returned.clone(),
+    }
+}
+
+// warningsはstrictが"warn"のときにKaTeXが出した警告
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rendered {
+    pub html: String,
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RenderedParts {
+    pub html: String,
+    pub mathml: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum PartsOutput {
+    Success {
+        #[serde(flatten)]
+        parts: RenderedParts,
+        #[serde(default)]
+        failed: bool,
+        macros: BTreeMap<String, MacroValue>,
+    },
+    Error {
+        error: String,
+        macros: BTreeMap<String, MacroValue>,
+    },
+}
+
+// 単位はem。heightはベースラインより上、depthは下の長さ
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Dimensions {
+    pub width_em: f64,
+    pub height_em: f64,
+    pub depth_em: f64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum MeasureOutput {
+    Success { dimensions: Dimensions },
+    Error { error: String, macros: BTreeMap<String, MacroValue> },
+}
+
+#[cfg(feature = "png")]
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum SvgOutput {
+    Success { svg: String },
+    Error { error: String, macros: BTreeMap<String, MacroValue> },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum MacrosOutput {
+    Error { error: String, macros: BTreeMap<String, MacroValue> },
+    Success { macros: BTreeMap<String, MacroValue> },
+}
+
+// checkで見つかったパースのエラー。positionとlengthはlatexの中のバイト単位の位置で、KaTeXが位置を示さなかったときはNone。
+// エンジンが動かなかったときもmessageだけを入れて返す
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, thiserror::Error)]
 #[serde(rename_all = "camelCase")]
-pub struct Options {
-    pub display_mode: bool,
-    pub output: KatexOutput,
-    pub leqno: bool,
-    pub fleqn: bool,
-    pub throw_on_error: bool,
-    pub error_color: Cow<'static, str>,
-    pub min_rule_thickness: Option<f64>,
-    pub color_is_text_color: bool,
-    pub max_size: f64,
-    pub max_expand: i32,
-    pub strict: Option<bool>,
-    pub trust: bool,
-    pub global_group: bool,
-}
-impl Default for Options {
-    fn default() -> Self {
-        Options {
-            display_mode: false,
-            output: KatexOutput::HtmlAndMathml,
-            leqno: false,
-            fleqn: false,
-            throw_on_error: true,
-            error_color: "#cc0000".into(),
-            min_rule_thickness: None,
-            color_is_text_color: false,
-            max_size: std::f64::INFINITY,
-            max_expand: 1000,
-            strict: None,
-            trust: false,
-            global_group: false,
+#[error("{message}")]
+pub struct ParseDiagnostics {
+    pub message: String,
+    // "KaTeX parse error: "や位置の表示を含まないもの
+    #[serde(default)]
+    pub raw_message: String,
+    #[serde(default)]
+    pub position: Option<usize>,
+    #[serde(default)]
+    pub length: Option<usize>,
+}
+impl ParseDiagnostics {
+    // JSの位置はUTF-16なので、latexのバイト位置に直す
+    fn locate(mut self, latex: &str) -> Self {
+        if let Some(position) = self.position {
+            let start = utf16_to_byte(latex, position);
+            self.length = self.length.map(|length| utf16_to_byte(latex, position + length) - start);
+            self.position = Some(start);
         }
+        self
+    }
+}
+
+fn utf16_to_byte(text: &str, utf16: usize) -> usize {
+    let mut units = 0;
+    text.char_indices()
+        .find(|&(_, c)| {
+            units += c.len_utf16();
+            units > utf16
+        })
+        .map_or(text.len(), |(index, _)| index)
+}
+impl From<Error> for ParseDiagnostics {
+    fn from(error: Error) -> Self {
+        ParseDiagnostics { raw_message: error.to_string(), message: error.to_string(), position: None, length: None }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub enum KatexOutput {
-    Html,
-    Mathml,
-    HtmlAndMathml,
+pub enum Severity {
+    Error,
+    Warning,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+// LSPのサーバーなどで数式に下線を引くための診断。rangeはlatexの中のバイト単位の範囲で、KaTeXが位置を示さなかったときは数式全体。
+// codeはstrictのerrorCode("unicodeTextInMathMode"など)で、パースのエラーではNone
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub range: std::ops::Range<usize>,
+    pub message: String,
+    pub code: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DiagnoseOutput {
+    diagnostics: Vec<RawDiagnostic>,
+}
+
+// JSから届く、UTF-16で位置を示した診断
+#[derive(Debug, Deserialize)]
+struct RawDiagnostic {
+    severity: Severity,
+    message: String,
+    code: Option<String>,
+    position: Option<usize>,
+    length: Option<usize>,
+}
+impl RawDiagnostic {
+    fn locate(self, latex: &str) -> Diagnostic {
+        let range = match self.position {
+            Some(position) => {
+                let start = utf16_to_byte(latex, position);
+                start..utf16_to_byte(latex, position + self.length.unwrap_or(0))
+            }
+            None => 0..latex.len(),
+        };
+        Diagnostic { severity: self.severity, range, message: self.message, code: self.code }
+    }
+}
+
+// speechはhtmlの読み上げ文。aria-labelに入れる
+#[cfg(feature = "speech")]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpokenRendered {
+    pub html: String,
+    pub speech: String,
+}
+
+#[cfg(feature = "speech")]
+#[derive(Debug, Deserialize)]
 #[serde(untagged)]
-enum Output {
-    Success { html: String, macros: BTreeMap<String, String> },
-    Error { error: String, macros: BTreeMap<String, String> },
+enum SpeechOutput {
+    Success {
+        html: String,
+        tree: serde_json::Value,
+        #[serde(default)]
+        failed: bool,
+        macros: BTreeMap<String, MacroValue>,
+    },
+    Error {
+        error: String,
+        macros: BTreeMap<String, MacroValue>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum TreeOutput {
+    Error { error: String, macros: BTreeMap<String, MacroValue> },
+    Success { tree: serde_json::Value },
+}
+
+#[derive(Debug, Serialize)]
+struct HelperInput<'a> {
+    name: &'a str,
+    args: &'a serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct HelperOutput {
+    found: bool,
+    #[serde(default)]
+    result: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct CheckOutput {
+    diagnostics: Option<ParseDiagnostics>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum UsedOutput {
+    Success { used: BTreeSet<String> },
+    Error { error: String, macros: BTreeMap<String, MacroValue> },
+}
+
+#[derive(Debug, Deserialize)]
+struct CollectOutput {
+    macros: BTreeMap<String, MacroValue>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ExpandOutput {
+    Error { error: String, macros: BTreeMap<String, MacroValue> },
+    Success { expanded: String },
 }
 
-struct KatexWorker(Sender<(Input, Sender<Result<Output, Error>>)>);
-static KATEX_WORKER: OnceCell<KatexWorker> = OnceCell::new();
+// 共有ワーカー。init_from_envでKATEX_GDEF_WORKERSを指定したときだけ複数になる
+static KATEX_RENDERERS: OnceCell<Vec<Renderer>> = OnceCell::new();
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("JS Error: {0}")]
-    JSError(#[from] JSError),
+    JSError(JSError),
     #[error("Recv Error: {0}")]
     RecvError(#[from] mpsc::RecvError),
     #[error("Send Error")]
     SendError,
+    #[error("Renderer has been shut down")]
+    ShutDown,
+    #[error("Renderer is busy")]
+    Busy,
+    #[error("Render timed out after {0:?}")]
+    Timeout(Duration),
+    // InitOptions::max_heap_mbの上限に近づいたので打ち切った
+    #[error("Renderer ran out of memory")]
+    OutOfMemory,
+    #[error("Could not spawn the worker thread: {0}")]
+    Spawn(std::io::Error),
+    #[error("IO Error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("JSON Error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[cfg(feature = "png")]
+    #[error("PNG Error: {0}")]
+    Png(String),
+    #[error("Invalid environment variable {name}: {message}")]
+    Env { name: &'static str, message: String },
+    #[error("Unknown helper: {0}")]
+    UnknownHelper(String),
+    #[error("Unsupported macro store version: {0}")]
+    MacroStoreVersion(u32),
     #[error("KaTeX Error: math: {latex}, macros: {macros:?}, error: {message}")]
-    KaTeXError { message: String, latex: String, macros: BTreeMap<String, String> },
+    KaTeXError { message: String, latex: String, macros: BTreeMap<String, MacroValue> },
+}
+
+impl From<JSError> for Error {
+    fn from(error: JSError) -> Self {
+        #[cfg(feature = "v8")]
+        #[cfg(not(any(multi_engine, feature = "wasm")))]
+        if matches!(error, JSError::OutOfMemory) {
+            return Error::OutOfMemory;
+        }
+        #[cfg(all(multi_engine, feature = "v8"))]
+        if matches!(error, JSError::V8(v8::Error::OutOfMemory)) {
+            return Error::OutOfMemory;
+        }
+        #[cfg(feature = "sandbox")]
+        #[cfg(not(any(multi_engine, feature = "wasm")))]
+        if matches!(error, JSError::OutOfMemory) {
+            return Error::OutOfMemory;
+        }
+        #[cfg(all(multi_engine, feature = "sandbox"))]
+        if matches!(error, JSError::Sandbox(sandbox::Error::OutOfMemory)) {
+            return Error::OutOfMemory;
+        }
+        Error::JSError(error)
+    }
 }
 
 pub fn set_cache(path: impl AsRef<Path>) {
-    init_katex_worker(Some(path.as_ref().to_path_buf()));
+    KATEX_RENDERERS.get_or_init(|| vec![Renderer::new(InitOptions::default().cache(path))]);
+}
+
+// 同梱のKaTeXを使う場合、実際のファイル名にはKaTeXのバージョンが付く
+pub fn default_cache_path() -> Option<PathBuf> {
+    cache::default_path_for(ENGINE_NAME)
+}
+
+pub fn set_cache_default() -> Result<PathBuf, Error> {
+    let path =
+        default_cache_path().ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "could not determine a cache directory"))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    set_cache(&path);
+    Ok(path)
+}
+
+// InitOptionsのうち、エンジンを作るときに渡すもの
+#[derive(Debug, Clone, Default)]
+pub(crate) struct EngineOptions {
+    // V8のヒープ、またはサンドボックスの線形メモリの上限
+    #[cfg(any(feature = "v8", feature = "sandbox"))]
+    #[cfg(not(feature = "wasm"))]
+    pub(crate) max_heap_mb: Option<usize>,
+    #[cfg(feature = "v8")]
+    #[cfg(not(feature = "wasm"))]
+    pub(crate) configure_v8: Option<v8::ConfigureV8>,
+    #[cfg(multi_engine)]
+    pub(crate) engine: Engine,
 }
 
 pub(crate) trait Core: Sized {
     type Error;
     // スナップショットを採れなかったとき
-    fn new() -> Result<Self, Self::Error>;
-    // snapshotを取り出す/または作成してからランタイムを返す
-    fn new_with_snapshot(path: &Path) -> Result<Self, Self::Error>;
-    fn exec(&mut self, input: Input) -> Result<Output, Self::Error>;
+    fn new(code: &str, options: &EngineOptions) -> Result<Self, Self::Error>;
+    // ビルド時に埋め込んだスナップショットから起動する。埋め込みが無ければ普通に作る
+    fn new_embedded(code: &str, options: &EngineOptions) -> Result<Self, Self::Error> {
+        Self::new(code, options)
+    }
+    // snapshotを取り出す/または作成してからランタイムを返す。headerが合わないキャッシュは作り直す
+    fn new_with_snapshot(code: &str, path: &Path, header: &str, options: &EngineOptions) -> Result<Self, Self::Error>;
+    // キャッシュの見出しに入れるエンジンの名前とバージョン
+    fn engine_id(options: &EngineOptions) -> String;
+    // JSの関数をJSONの引数で呼び出し、返されたJSON文字列をデシリアライズする
+    fn exec<O: DeserializeOwned>(&mut self, function: &str, input: &str) -> Result<O, Self::Error>;
+    // 別のスレッドから実行中のスクリプトを打ち切るための関数
+    fn interrupt_handle(&mut self) -> Interrupt;
+    // 打ち切られた後も次のジョブを実行できるように戻す
+    fn clear_interrupt(&mut self);
 }
 
-fn init_katex_worker(cache: Option<PathBuf>) {
-    if KATEX_WORKER.get().is_some() {
-        return;
+pub(crate) type Interrupt = Box<dyn Fn() + Send + Sync>;
+
+fn katex_renderers() -> &'static [Renderer] {
+    KATEX_RENDERERS.get_or_init(|| vec![Renderer::new(InitOptions::default())])
+}
+
+// 待っているジョブが一番少ないワーカーを使う
+fn katex_renderer() -> &'static Renderer {
+    katex_renderers().iter().min_by_key(|renderer| renderer.queue_depth()).expect("at least one worker")
+}
+
+// 共有ワーカーのエンジンを先に作っておき、JSやIOのエラーをここで返す
+pub fn init() -> Result<(), Error> {
+    katex_renderers().iter().try_for_each(Renderer::warm_up)
+}
+
+// 複数あるときは既定のエンジン(Engine::default)と同じ
+const ENGINE_NAME: &str = if cfg!(feature = "wasm") {
+    "wasm"
+} else if cfg!(feature = "v8") {
+    "v8"
+} else if cfg!(feature = "qjs") {
+    "qjs"
+} else if cfg!(feature = "rquickjs") {
+    "rquickjs"
+} else if cfg!(feature = "boa") {
+    "boa"
+} else {
+    "sandbox"
+};
+
+// 環境変数から共有ワーカーを設定してinit()する。既に共有ワーカーを使っていたらKATEX_GDEF_CACHEとKATEX_GDEF_WORKERSは効かない。
+// KATEX_GDEF_CACHE: スナップショットのパス。"default"ならdefault_cache_path()、空ならキャッシュしない
+// KATEX_GDEF_ENGINE: "v8"/"qjs"/"rquickjs"/"boa"/"sandbox"/"wasm"。ビルドに含まれないものを指定するとエラーにする。複数あるときはどれも選べる
+// KATEX_GDEF_WORKERS: ワーカーの数(1以上)。各ワーカーが別々にエンジンを持つ
+pub fn init_from_env() -> Result<(), Error> {
+    fn var(name: &'static str) -> Result<Option<String>, Error> {
+        match std::env::var(name) {
+            Ok(value) => Ok(Some(value.trim().to_string())),
+            Err(std::env::VarError::NotPresent) => Ok(None),
+            Err(std::env::VarError::NotUnicode(_)) => Err(Error::Env { name, message: "not valid UTF-8".to_string() }),
+        }
     }
-    let (tx, rx): (Sender<(Input, Sender<Result<Output, Error>>)>, Receiver<(Input, Sender<Result<Output, Error>>)>) = mpsc::channel();
-    thread::spawn(move || {
-        let mut runtime =
-            if let Some(cache) = cache { <Engine as Core>::new_with_snapshot(&cache).unwrap() } else { <Engine as Core>::new().unwrap() };
-        for (katex_input, sender) in rx {
-            let res = runtime.exec(katex_input).map_err(Error::from);
-            sender.send(res).unwrap();
+    let mut options = InitOptions::default();
+    #[cfg(not(multi_engine))]
+    let engine = match var("KATEX_GDEF_ENGINE")?.filter(|engine| !engine.is_empty()) {
+        Some(engine) if !engine.eq_ignore_ascii_case(ENGINE_NAME) => {
+            return Err(Error::Env {
+                name: "KATEX_GDEF_ENGINE",
+                message: format!("{engine} is not available, this build uses {ENGINE_NAME}"),
+            });
+        }
+        _ => ENGINE_NAME,
+    };
+    #[cfg(multi_engine)]
+    let engine = match var("KATEX_GDEF_ENGINE")?.filter(|engine| !engine.is_empty()) {
+        None => Engine::default(),
+        Some(engine) => match Engine::ALL.iter().find(|candidate| engine.eq_ignore_ascii_case(candidate.name())) {
+            Some(&engine) => engine,
+            None => {
+                let names: Vec<&str> = Engine::ALL.iter().map(|engine| engine.name()).collect();
+                return Err(Error::Env {
+                    name: "KATEX_GDEF_ENGINE",
+                    message: format!("{engine} is not available, this build uses {}", names.join(" or ")),
+                });
+            }
+        },
+    };
+    #[cfg(multi_engine)]
+    let engine = {
+        options = options.engine(engine);
+        engine.name()
+    };
+    match var("KATEX_GDEF_CACHE")?.as_deref() {
+        None | Some("") => {}
+        Some("default") => {
+            let path = cache::default_path_for(engine)
+                .ok_or_else(|| Error::Env { name: "KATEX_GDEF_CACHE", message: "could not determine a cache directory".to_string() })?;
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            options = options.cache(path);
         }
-    });
-    KATEX_WORKER.set(KatexWorker(tx)).ok().unwrap();
+        Some(path) => options = options.cache(path),
+    }
+    let workers = match var("KATEX_GDEF_WORKERS")?.as_deref() {
+        None | Some("") => 1,
+        Some(workers) => match workers.parse::<usize>() {
+            Ok(workers) if workers > 0 => workers,
+            _ => return Err(Error::Env { name: "KATEX_GDEF_WORKERS", message: format!("{workers} is not a positive integer") }),
+        },
+    };
+    KATEX_RENDERERS.get_or_init(|| (0..workers).map(|_| Renderer::new(options.clone())).collect());
+    init()
+}
+
+// 共有ワーカーを止める。以降の呼び出しはError::ShutDownを返す
+pub fn shutdown() {
+    for renderer in KATEX_RENDERERS.get().into_iter().flatten() {
+        renderer.shutdown();
+    }
 }
 
 pub fn render(latex: &str) -> Result<String, Error> {
-    render_with_opts(latex, &Default::default(), &mut BTreeMap::new())
+    katex_renderer().render(latex)
 }
 
-pub fn render_with_opts(latex: &str, options: &Options, macros: &mut BTreeMap<String, String>) -> Result<String, Error> {
-    let (tx, rx) = mpsc::channel();
-    let Some(worker) = KATEX_WORKER.get() else {
-        init_katex_worker(None);
-        return render_with_opts(latex, options, macros);
-    };
+pub fn render_with_opts(latex: &str, options: &Options, macros: &mut BTreeMap<String, MacroValue>) -> Result<String, Error> {
+    katex_renderer().render_with_opts(latex, options, macros)
+}
 
-    worker
-        .0
-        .send((Input { latex: latex.to_string(), options: options.clone(), macros: macros.clone() }, tx))
-        .map_err(|_| Error::SendError)?;
-    match rx.recv()?? {
-        Output::Success { html, macros: macros_value } => {
-            *macros = macros_value;
-            Ok(html)
-        }
-        Output::Error { error, macros: macros_value } => {
-            Err(Error::KaTeXError { message: error, latex: latex.to_string(), macros: macros_value })
-        }
+pub fn render_scoped(latex: &str, options: &Options, scope: &mut MacroScope) -> Result<String, Error> {
+    katex_renderer().render_scoped(latex, options, scope)
+}
+
+pub fn render_with_conflicts(
+    latex: &str,
+    options: &Options,
+    macros: &mut BTreeMap<String, MacroValue>,
+    conflicts: &mut Vec<MacroConflict>,
+) -> Result<String, Error> {
+    katex_renderer().render_with_conflicts(latex, options, macros, conflicts)
+}
+
+pub fn render_to_writer(
+    latex: &str,
+    options: &Options,
+    macros: &mut BTreeMap<String, MacroValue>,
+    writer: &mut impl std::io::Write,
+) -> Result<(), Error> {
+    katex_renderer().render_to_writer(latex, options, macros, writer)
+}
+
+pub fn try_render(latex: &str, options: &Options, macros: &mut BTreeMap<String, MacroValue>) -> Result<String, Error> {
+    katex_renderer().try_render(latex, options, macros)
+}
+
+pub fn render_timeout(
+    latex: &str,
+    options: &Options,
+    macros: &mut BTreeMap<String, MacroValue>,
+    timeout: Duration,
+) -> Result<String, Error> {
+    katex_renderer().render_timeout(latex, options, macros, timeout)
+}
+
+#[cfg(feature = "tokio")]
+pub async fn render_async(latex: &str) -> Result<String, Error> {
+    katex_renderer().render_async(latex).await
+}
+
+#[cfg(feature = "tokio")]
+pub async fn render_with_opts_async(latex: &str, options: &Options, macros: &mut BTreeMap<String, MacroValue>) -> Result<String, Error> {
+    katex_renderer().render_with_opts_async(latex, options, macros).await
+}
+
+// 外側のErrはジョブ全体の失敗(ワーカーを起動できない、エンジンの初期化、停止、OutOfMemory、KaTeX以外のJSのエラー)で、
+// どの数式の結果も得られず、macrosも変わらない。数式ごとのKaTeXのエラーは内側のResultに入る
+pub fn render_batch(items: &[(&str, Options)], macros: &mut BTreeMap<String, MacroValue>) -> Result<Vec<Result<String, Error>>, Error> {
+    katex_renderer().render_batch(items, macros)
+}
+
+// 互いに独立した数式を共有ワーカーの数に切り分け、ワーカーごとに並列に描画して結果は元の順に返す。
+// どの数式もmacrosから描画し、数式で定義したマクロは他の数式にもmacrosにも持ち越さない
+#[cfg(feature = "rayon")]
+pub fn render_batch_parallel(items: &[(&str, Options)], macros: &BTreeMap<String, MacroValue>) -> Vec<Result<String, Error>> {
+    use rayon::prelude::*;
+    let renderers = katex_renderers();
+    let chunk_size = items.len().div_ceil(renderers.len()).max(1);
+    let chunks: Vec<Vec<Result<String, Error>>> = items
+        .par_chunks(chunk_size)
+        .zip(renderers.par_iter())
+        .map(|(chunk, renderer)| chunk.iter().map(|(latex, options)| renderer.render_independent(latex, options, macros)).collect())
+        .collect();
+    chunks.into_iter().flatten().collect()
+}
+
+pub fn render_spawn(latex: &str, options: &Options, macros: BTreeMap<String, MacroValue>) -> RenderHandle<'static> {
+    katex_renderer().render_spawn(latex, options, macros)
+}
+
+pub fn render_iter<I: IntoIterator<Item = (String, Options)>>(items: I) -> RenderIter<'static, I::IntoIter> {
+    katex_renderer().render_iter(items)
+}
+
+pub fn render_document(
+    text: &str,
+    delimiters: &[Delimiter],
+    options: &Options,
+    macros: &mut BTreeMap<String, MacroValue>,
+) -> Result<String, Error> {
+    katex_renderer().render_document(text, delimiters, options, macros)
+}
+
+pub fn render_parts(latex: &str, options: &Options, macros: &mut BTreeMap<String, MacroValue>) -> Result<RenderedParts, Error> {
+    katex_renderer().render_parts(latex, options, macros)
+}
+
+#[cfg(feature = "speech")]
+pub fn render_with_speech(latex: &str, options: &Options, macros: &mut BTreeMap<String, MacroValue>) -> Result<SpokenRendered, Error> {
+    katex_renderer().render_with_speech(latex, options, macros)
+}
+
+pub fn to_text(latex: &str, options: &Options, macros: &mut BTreeMap<String, MacroValue>) -> Result<String, Error> {
+    katex_renderer().to_text(latex, options, macros)
+}
+
+pub fn macros_from_preamble(preamble: &str, macros: &mut BTreeMap<String, MacroValue>) -> Result<(), Error> {
+    katex_renderer().macros_from_preamble(preamble, macros)
+}
+
+pub fn macros_used(latex: &str, macros: &BTreeMap<String, MacroValue>) -> Result<BTreeSet<String>, Error> {
+    katex_renderer().macros_used(latex, macros)
+}
+
+pub fn collect_macros<'a>(fragments: impl IntoIterator<Item = &'a str>, options: &Options) -> Result<BTreeMap<String, MacroValue>, Error> {
+    katex_renderer().collect_macros(fragments, options)
+}
+
+pub fn parse_tree(latex: &str, options: &Options) -> Result<serde_json::Value, Error> {
+    katex_renderer().parse_tree(latex, options)
+}
+
+pub fn check(latex: &str, options: &Options, macros: &BTreeMap<String, MacroValue>) -> Result<(), ParseDiagnostics> {
+    katex_renderer().check(latex, options, macros)
+}
+
+pub fn diagnostics(latex: &str, options: &Options, macros: &BTreeMap<String, MacroValue>) -> Vec<Diagnostic> {
+    katex_renderer().diagnostics(latex, options, macros)
+}
+
+pub fn eval_helper(name: &str, args: &serde_json::Value) -> Result<serde_json::Value, Error> {
+    katex_renderer().eval_helper(name, args)
+}
+
+pub fn expand(latex: &str, macros: &BTreeMap<String, MacroValue>) -> Result<String, Error> {
+    katex_renderer().expand(latex, macros)
+}
+
+#[cfg(feature = "standalone")]
+pub fn render_standalone(latex: &str, options: &Options, katex_dist: impl AsRef<Path>) -> Result<String, Error> {
+    katex_renderer().render_standalone(latex, options, katex_dist)
+}
+
+pub fn render_with_warnings(latex: &str, options: &Options, macros: &mut BTreeMap<String, MacroValue>) -> Result<Rendered, Error> {
+    katex_renderer().render_with_warnings(latex, options, macros)
+}
+
+// 共有ワーカーの全てに同じ後処理を設定する
+pub fn set_post_processor(post_processor: impl Fn(String) -> String + Send + Sync + 'static) {
+    let post_processor: renderer::PostProcessor = Arc::new(post_processor);
+    for renderer in katex_renderers() {
+        renderer.set_shared_post_processor(Some(post_processor.clone()));
     }
 }
 
-pub use font::{UsedFonts, font_extract};
+// 共有ワーカーの全てに同じ前処理を設定する
+pub fn set_pre_processor(pre_processor: impl Fn(&str) -> Cow<'_, str> + Send + Sync + 'static) {
+    let pre_processor: renderer::PreProcessor = Arc::new(pre_processor);
+    for renderer in katex_renderers() {
+        renderer.set_shared_pre_processor(Some(pre_processor.clone()));
+    }
+}
+
+// 共有ワーカーが複数あるときは合計
+pub fn stats() -> RenderStats {
+    renderer::pool_stats(katex_renderers())
+}
+
+pub fn measure(latex: &str, options: &Options) -> Result<Dimensions, Error> {
+    katex_renderer().measure(latex, options)
+}
+
+#[cfg(feature = "png")]
+pub fn render_svg(latex: &str, options: &Options) -> Result<String, Error> {
+    katex_renderer().render_svg(latex, options)
+}
+
+#[cfg(feature = "png")]
+pub fn render_png(latex: &str, options: &Options, katex_dist: impl AsRef<Path>, dpi: f32) -> Result<Vec<u8>, Error> {
+    katex_renderer().render_png(latex, options, katex_dist, dpi)
+}
+
+pub use auto_render::{Delimiter, DelimiterConfig, Segment, split_math};
+pub use bundle::KatexVersion;
+#[cfg(feature = "comrak")]
+pub use comrak_math::ComrakMath;
+#[cfg(feature = "standalone")]
+pub use css::standalone_html;
+pub use css::{class_extract, css_variables_stylesheet, inline_css, prune_css};
+pub use document::{DocumentRenderer, NumberStyle};
+pub use font::{FontFormat, FontName, FontScanner, UsedFonts, font_extract, glyph_extract};
+pub use macros::{MACRO_STORE_VERSION, MacroConflict, MacroFilter, MacroScope, MacroStore, MacroValue, macros_fingerprint};
+#[cfg(feature = "markdown")]
+pub use markdown::MarkdownMath;
+pub use memo::{CachedRender, RenderCache};
+#[cfg(feature = "minijinja")]
+pub use minijinja_filter::KatexFilter;
+pub use options::{
+    DarkColors, KatexOutput, MacroCommit, MaxExpand, MaxSize, Options, OptionsBuilder, Strict, StrictMode, TrustPolicy, UrlPolicy,
+};
+#[cfg(feature = "png")]
+pub use png::svg_to_png;
+pub use pretty::format_html;
+#[cfg(multi_engine)]
+pub use renderer::Engine;
+pub use renderer::{InitOptions, Priority, RenderHandle, RenderIter, RenderStats, Renderer};
+#[cfg(feature = "sanitize")]
+pub use sanitize::sanitize_html;
+#[cfg(feature = "speech")]
+pub use speech::speech_text;
+pub use text::mathml_to_text;
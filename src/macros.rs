@@ -0,0 +1,210 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+    sync::Arc,
+};
+
+use crate::Error;
+
+pub static MACRO_STORE_VERSION: u32 = 1;
+
+// 引数を取るマクロは本体だけでは引数の数が復元できないので、numArgsと一緒に持つ
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MacroValue {
+    Simple(String),
+    WithArgs {
+        #[serde(rename = "numArgs")]
+        num_args: usize,
+        body: String,
+    },
+}
+impl MacroValue {
+    pub fn body(&self) -> &str {
+        match self {
+            MacroValue::Simple(body) => body,
+            MacroValue::WithArgs { body, .. } => body,
+        }
+    }
+    pub fn num_args(&self) -> usize {
+        match self {
+            MacroValue::Simple(_) => 0,
+            MacroValue::WithArgs { num_args, .. } => *num_args,
+        }
+    }
+}
+impl From<String> for MacroValue {
+    fn from(body: String) -> Self {
+        MacroValue::Simple(body)
+    }
+}
+impl From<&str> for MacroValue {
+    fn from(body: &str) -> Self {
+        MacroValue::Simple(body.to_string())
+    }
+}
+
+// ビルドを跨いでマクロを持ち越すための保存形式
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MacroStore {
+    pub macros: BTreeMap<String, MacroValue>,
+}
+#[derive(Serialize, Deserialize)]
+struct MacroStoreFile<M> {
+    version: u32,
+    macros: M,
+}
+impl MacroStore {
+    pub fn new(macros: BTreeMap<String, MacroValue>) -> Self {
+        MacroStore { macros }
+    }
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let file: MacroStoreFile<BTreeMap<String, MacroValue>> = serde_json::from_slice(&std::fs::read(path)?)?;
+        if file.version != MACRO_STORE_VERSION {
+            return Err(Error::MacroStoreVersion(file.version));
+        }
+        Ok(MacroStore { macros: file.macros })
+    }
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let file = MacroStoreFile { version: MACRO_STORE_VERSION, macros: &self.macros };
+        std::fs::write(path, serde_json::to_vec_pretty(&file)?)?;
+        Ok(())
+    }
+    // 同じマクロなら常に同じ文字列。保存形式を空白無しで書いたもので、そのままloadでも読める。
+    // 引数の数が0のWithArgsはSimpleとして書く
+    pub fn canonical_json(&self) -> String {
+        let macros: BTreeMap<&str, MacroValue> = self
+            .macros
+            .iter()
+            .map(|(name, value)| match value {
+                MacroValue::WithArgs { num_args: 0, body } => (name.as_str(), MacroValue::Simple(body.clone())),
+                value => (name.as_str(), value.clone()),
+            })
+            .collect();
+        serde_json::to_string(&MacroStoreFile { version: MACRO_STORE_VERSION, macros }).expect("macros serialize to JSON")
+    }
+}
+
+// canonical_jsonのFNV-1aハッシュ。Rustのバージョンやプロセスによらないので、ビルドを跨ぐキャッシュのキーに使える
+pub fn macros_fingerprint(macros: &BTreeMap<String, MacroValue>) -> u64 {
+    MacroStore::new(macros.clone())
+        .canonical_json()
+        .bytes()
+        .fold(0xcbf29ce484222325, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3))
+}
+impl From<BTreeMap<String, MacroValue>> for MacroStore {
+    fn from(macros: BTreeMap<String, MacroValue>) -> Self {
+        MacroStore { macros }
+    }
+}
+
+// 一つ上の層(サイト全体のプリアンブル → 章 → 数式)に重ねて定義するマクロ。定義は自分の層にだけ入るので、
+// 章の中で定義したマクロが他の章に漏れない。cloneは層を共有し、書き換えたときに初めて自分の層を複製する
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MacroScope {
+    parent: Option<Arc<MacroScope>>,
+    local: Arc<BTreeMap<String, MacroValue>>,
+}
+impl MacroScope {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    // 今の定義を親にした、空の層を作る。後でこの層を書き換えても子には見えない
+    pub fn child(&self) -> MacroScope {
+        MacroScope { parent: Some(Arc::new(self.clone())), local: Arc::default() }
+    }
+    pub fn parent(&self) -> Option<&MacroScope> {
+        self.parent.as_deref()
+    }
+    pub fn get(&self, name: &str) -> Option<&MacroValue> {
+        self.local.get(name).or_else(|| self.parent.as_ref()?.get(name))
+    }
+    pub fn define(&mut self, name: impl Into<String>, value: impl Into<MacroValue>) {
+        Arc::make_mut(&mut self.local).insert(name.into(), value.into());
+    }
+    // この層で定義したものだけ
+    pub fn local(&self) -> &BTreeMap<String, MacroValue> {
+        &self.local
+    }
+    // 親の層を下に敷いた、描画に渡す表
+    pub fn flatten(&self) -> BTreeMap<String, MacroValue> {
+        let mut macros = self.parent.as_ref().map(|parent| parent.flatten()).unwrap_or_default();
+        macros.extend(self.local.iter().map(|(name, value)| (name.clone(), value.clone())));
+        macros
+    }
+    // flattenした表を描画に渡した後、変わったものをこの層に定義する
+    pub(crate) fn absorb(&mut self, before: &BTreeMap<String, MacroValue>, after: BTreeMap<String, MacroValue>) {
+        for (name, value) in after {
+            if before.get(&name) != Some(&value) {
+                self.define(name, value);
+            }
+        }
+    }
+}
+impl From<BTreeMap<String, MacroValue>> for MacroScope {
+    fn from(macros: BTreeMap<String, MacroValue>) -> Self {
+        MacroScope { parent: None, local: Arc::new(macros) }
+    }
+}
+
+// 数式が定義したマクロを呼び出し側の表に書き戻すときに、書き戻してよいものを絞る。
+// 許可する接頭辞を一つでも足すと、それで始まる名前だけを定義でき、protectした名前(サイトのプリアンブルの\RRなど)は定義し直せない
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct MacroFilter {
+    allowed_prefixes: Vec<String>,
+    protected: BTreeSet<String>,
+}
+impl MacroFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn allow_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.allowed_prefixes.push(prefix.into());
+        self
+    }
+    pub fn protect(mut self, name: impl Into<String>) -> Self {
+        self.protected.insert(name.into());
+        self
+    }
+    // プリアンブルで定義したものをまとめて守るときは、その表のkeys()を渡す
+    pub fn protect_all<S: Into<String>>(mut self, names: impl IntoIterator<Item = S>) -> Self {
+        self.protected.extend(names.into_iter().map(Into::into));
+        self
+    }
+    pub fn allows(&self, name: &str) -> bool {
+        !self.protected.contains(name)
+            && (self.allowed_prefixes.is_empty() || self.allowed_prefixes.iter().any(|prefix| name.starts_with(prefix.as_str())))
+    }
+    // 許されない名前は元の表のまま残す
+    pub fn merge(&self, macros: &mut BTreeMap<String, MacroValue>, returned: &BTreeMap<String, MacroValue>) {
+        for (name, value) in returned {
+            if self.allows(name) && macros.get(name) != Some(value) {
+                macros.insert(name.clone(), value.clone());
+            }
+        }
+    }
+}
+
+// 元の表にあったマクロが、数式の中で別の本体に定義し直されたもの
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacroConflict {
+    pub name: String,
+    pub old: MacroValue,
+    pub new: MacroValue,
+    // 定義し直した数式
+    pub latex: String,
+}
+impl MacroConflict {
+    // \df@tagのように@を含むものはKaTeXが内部で使うので数えない
+    pub fn detect(before: &BTreeMap<String, MacroValue>, after: &BTreeMap<String, MacroValue>, latex: &str) -> Vec<MacroConflict> {
+        before
+            .iter()
+            .filter(|(name, _)| !name.contains('@'))
+            .filter_map(|(name, old)| {
+                let new = after.get(name).filter(|new| *new != old)?;
+                Some(MacroConflict { name: name.clone(), old: old.clone(), new: new.clone(), latex: latex.to_string() })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,143 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    iter::Peekable,
+};
+
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+
+use crate::{
+    Delimiter, Error, MacroValue, Options, Renderer, UsedFonts,
+    auto_render::{Segment, split_at_delimiters},
+    font::font_extract,
+    katex_renderer,
+};
+
+// pulldown-cmarkのイベント列の数式をKaTeXのHTMLに置き換える。
+// ENABLE_MATHのInlineMath/DisplayMathに加え、テキスト中の`$...$`も数式として扱う。
+// 描画に失敗した数式は元のテキストのまま残し、エラーはerrors()に溜める
+pub struct MarkdownMath<'r, 'a, I: Iterator<Item = Event<'a>>> {
+    renderer: &'r Renderer,
+    events: Peekable<I>,
+    delimiters: Vec<Delimiter>,
+    options: Options,
+    macros: BTreeMap<String, MacroValue>,
+    fonts: UsedFonts,
+    errors: Vec<Error>,
+    pending: VecDeque<Event<'a>>,
+    in_code_block: bool,
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> MarkdownMath<'static, 'a, I> {
+    pub fn new(events: I) -> Self {
+        MarkdownMath::with_renderer(katex_renderer(), events)
+    }
+}
+
+impl<'r, 'a, I: Iterator<Item = Event<'a>>> MarkdownMath<'r, 'a, I> {
+    pub fn with_renderer(renderer: &'r Renderer, events: I) -> Self {
+        MarkdownMath {
+            renderer,
+            events: events.peekable(),
+            delimiters: vec![Delimiter::new("$$", "$$", true), Delimiter::new("$", "$", false)],
+            options: Options::default(),
+            macros: BTreeMap::new(),
+            fonts: UsedFonts::default(),
+            errors: Vec::new(),
+            pending: VecDeque::new(),
+            in_code_block: false,
+        }
+    }
+    pub fn options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+    pub fn macros(mut self, macros: BTreeMap<String, MacroValue>) -> Self {
+        self.macros = macros;
+        self
+    }
+    // テキスト中の数式の区切り。空にするとInlineMath/DisplayMathだけを置き換える
+    pub fn delimiters(mut self, delimiters: Vec<Delimiter>) -> Self {
+        self.delimiters = delimiters;
+        self
+    }
+    pub fn defined_macros(&self) -> &BTreeMap<String, MacroValue> {
+        &self.macros
+    }
+    pub fn used_fonts(&self) -> UsedFonts {
+        self.fonts
+    }
+    pub fn errors(&self) -> &[Error] {
+        &self.errors
+    }
+    pub fn finish(self) -> (BTreeMap<String, MacroValue>, UsedFonts, Vec<Error>) {
+        (self.macros, self.fonts, self.errors)
+    }
+
+    fn render(&mut self, latex: &str, display: bool) -> Option<String> {
+        let options = Options { display_mode: display, ..self.options.clone() };
+        match self.renderer.render_with_opts(latex, &options, &mut self.macros) {
+            Ok(html) => {
+                self.fonts.merge(font_extract(&html));
+                Some(html)
+            }
+            Err(error) => {
+                self.errors.push(error);
+                None
+            }
+        }
+    }
+
+    fn render_event(&mut self, latex: &str, raw: String, display: bool) -> Event<'a> {
+        match self.render(latex, display) {
+            Some(html) => Event::InlineHtml(html.into()),
+            None => Event::Text(raw.into()),
+        }
+    }
+
+    fn split_text(&mut self, text: CowStr<'a>) {
+        let delimiters = std::mem::take(&mut self.delimiters);
+        for segment in split_at_delimiters(&text, &delimiters) {
+            let event = match segment {
+                Segment::Text(text) => Event::Text(text.to_string().into()),
+                Segment::Math { latex, raw, display } => self.render_event(latex, raw.to_string(), display),
+            };
+            self.pending.push_back(event);
+        }
+        self.delimiters = delimiters;
+    }
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> Iterator for MarkdownMath<'_, 'a, I> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Event<'a>> {
+        if let Some(event) = self.pending.pop_front() {
+            return Some(event);
+        }
+        match self.events.next()? {
+            Event::InlineMath(latex) => Some(self.render_event(&latex, format!("${latex}$"), false)),
+            Event::DisplayMath(latex) => Some(self.render_event(&latex, format!("$${latex}$$"), true)),
+            event @ Event::Start(Tag::CodeBlock(_)) => {
+                self.in_code_block = true;
+                Some(event)
+            }
+            event @ Event::End(TagEnd::CodeBlock) => {
+                self.in_code_block = false;
+                Some(event)
+            }
+            // `_`や`*`の前後でテキストが分かれることがあるので、続くテキストをつなげてから数式を探す
+            Event::Text(mut text) if !self.in_code_block => {
+                while let Some(Event::Text(next)) = self.events.peek() {
+                    text = format!("{text}{next}").into();
+                    self.events.next();
+                }
+                if !self.delimiters.iter().any(|delimiter| text.contains(&*delimiter.left)) {
+                    return Some(Event::Text(text));
+                }
+                self.split_text(text);
+                self.next()
+            }
+            event => Some(event),
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::Mutex,
+};
+
+use crate::{Error, MacroValue, Options, Renderer, UsedFonts, font_extract, katex_renderer};
+
+// 数式、オプション、数式から辿れるマクロのハッシュで描画結果を覚えておく。ウォッチモードの静的サイトジェネレーターのように、
+// 殆ど変わらない数式を何度も描画するときに使う。capacityを超えたら一番長く使っていないものから捨てる
+pub struct RenderCache<'r> {
+    renderer: &'r Renderer,
+    capacity: usize,
+    entries: Mutex<Entries>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedRender {
+    pub html: String,
+    pub fonts: UsedFonts,
+}
+
+struct Entry {
+    rendered: CachedRender,
+    // その数式で定義し直したマクロ。Noneは消えたもの
+    defined: BTreeMap<String, Option<MacroValue>>,
+    used: u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    entries: HashMap<u64, Entry>,
+    // 最後に使った順。古いものから捨てる
+    order: BTreeMap<u64, u64>,
+    clock: u64,
+}
+impl Entries {
+    fn get(&mut self, key: u64) -> Option<&Entry> {
+        self.clock += 1;
+        let entry = self.entries.get_mut(&key)?;
+        self.order.remove(&entry.used);
+        entry.used = self.clock;
+        self.order.insert(self.clock, key);
+        Some(entry)
+    }
+    fn insert(&mut self, key: u64, rendered: CachedRender, defined: BTreeMap<String, Option<MacroValue>>, capacity: usize) {
+        self.clock += 1;
+        if let Some(old) = self.entries.insert(key, Entry { rendered, defined, used: self.clock }) {
+            self.order.remove(&old.used);
+        }
+        self.order.insert(self.clock, key);
+        while self.entries.len() > capacity {
+            let Some((_, oldest)) = self.order.pop_first() else { break };
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+impl RenderCache<'static> {
+    pub fn new(capacity: usize) -> Self {
+        RenderCache::with_renderer(katex_renderer(), capacity)
+    }
+}
+
+impl<'r> RenderCache<'r> {
+    pub fn with_renderer(renderer: &'r Renderer, capacity: usize) -> Self {
+        RenderCache { renderer, capacity, entries: Mutex::default() }
+    }
+
+    // 覚えていればその結果を返し、数式で定義されたマクロもmacrosに反映する。KaTeXのエラーは覚えない
+    pub fn render(&self, latex: &str, options: &Options, macros: &mut BTreeMap<String, MacroValue>) -> Result<CachedRender, Error> {
+        let key = key(latex, options, macros)?;
+        if let Some(entry) = self.entries.lock().unwrap().get(key) {
+            for (name, value) in &entry.defined {
+                match value {
+                    Some(value) => macros.insert(name.clone(), value.clone()),
+                    None => macros.remove(name),
+                };
+            }
+            return Ok(entry.rendered.clone());
+        }
+        let before = macros.clone();
+        let html = self.renderer.render_with_opts(latex, options, macros)?;
+        let mut defined: BTreeMap<String, Option<MacroValue>> = macros
+            .iter()
+            .filter(|(name, value)| before.get(*name) != Some(value))
+            .map(|(name, value)| (name.clone(), Some(value.clone())))
+            .collect();
+        defined.extend(before.keys().filter(|name| !macros.contains_key(*name)).map(|name| (name.clone(), None)));
+        let rendered = CachedRender { fonts: font_extract(&html), html };
+        self.entries.lock().unwrap().insert(key, rendered.clone(), defined, self.capacity);
+        Ok(rendered)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        *self.entries.lock().unwrap() = Entries::default();
+    }
+}
+
+// 数式に出てくる制御綴と、そのマクロの本体に出てくる制御綴を辿り、使われうるマクロだけをハッシュに入れる。
+// \で始まらない名前のマクロ(アクティブ文字)はどこで効くか分からないので、常に入れる
+fn key(latex: &str, options: &Options, macros: &BTreeMap<String, MacroValue>) -> Result<u64, Error> {
+    let mut hasher = DefaultHasher::new();
+    latex.hash(&mut hasher);
+    serde_json::to_string(options)?.hash(&mut hasher);
+    options.macro_commit.hash(&mut hasher);
+    options.macro_filter.hash(&mut hasher);
+    let mut relevant: BTreeSet<&str> = macros.keys().map(String::as_str).filter(|name| !name.starts_with('\\')).collect();
+    let mut pending = control_sequences(latex);
+    while let Some(name) = pending.pop() {
+        if let Some((name, value)) = macros.get_key_value(name)
+            && relevant.insert(name)
+        {
+            pending.extend(control_sequences(value.body()));
+        }
+    }
+    for name in relevant {
+        name.hash(&mut hasher);
+        macros[name].hash(&mut hasher);
+    }
+    Ok(hasher.finish())
+}
+
+// \の後に続く英字の列か、一文字
+fn control_sequences(latex: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    for (start, _) in latex.match_indices('\\') {
+        let rest = &latex[start + 1..];
+        let length = match rest.find(|c: char| !c.is_ascii_alphabetic()) {
+            Some(0) => rest.chars().next().map_or(0, char::len_utf8),
+            Some(length) => length,
+            None => rest.len(),
+        };
+        names.push(&latex[start..start + 1 + length]);
+    }
+    names
+}
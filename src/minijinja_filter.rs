@@ -0,0 +1,58 @@
+use std::collections::BTreeMap;
+
+use minijinja::{Environment, ErrorKind, State, Value, value::Kwargs};
+
+use crate::{MacroValue, Options, Renderer, katex_renderer};
+
+// minijinjaのテンプレートで`{{ formula | katex }}`や`{{ formula | katex(display=true) }}`と書けるようにするフィルタ。
+// マクロはテンプレートの描画ごとに持ち越し、描画が終われば捨てる
+pub struct KatexFilter {
+    renderer: &'static Renderer,
+    options: Options,
+    macros: BTreeMap<String, MacroValue>,
+}
+
+// 一回のテンプレートの描画の中で定義されたマクロ
+struct RenderMacros(BTreeMap<String, MacroValue>);
+
+impl KatexFilter {
+    pub fn new() -> Self {
+        KatexFilter::with_renderer(katex_renderer())
+    }
+    pub fn with_renderer(renderer: &'static Renderer) -> Self {
+        KatexFilter { renderer, options: Options::default(), macros: BTreeMap::new() }
+    }
+    pub fn options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+    // 各描画の始めに与えるマクロ
+    pub fn macros(mut self, macros: BTreeMap<String, MacroValue>) -> Self {
+        self.macros = macros;
+        self
+    }
+
+    // "katex"という名前で登録する
+    pub fn register(self, env: &mut Environment<'_>) {
+        self.register_as(env, "katex");
+    }
+    pub fn register_as(self, env: &mut Environment<'_>, name: &'static str) {
+        env.add_filter(name, move |state: &mut State, latex: &str, kwargs: Kwargs| -> Result<Value, minijinja::Error> {
+            let display: Option<bool> = kwargs.get("display")?;
+            kwargs.assert_all_used()?;
+            let options = Options { display_mode: display.unwrap_or(self.options.display_mode), ..self.options.clone() };
+            let RenderMacros(macros) = state.get_or_insert_extension_with(|| RenderMacros(self.macros.clone()));
+            let html = self
+                .renderer
+                .render_with_opts(latex, &options, macros)
+                .map_err(|error| minijinja::Error::new(ErrorKind::InvalidOperation, error.to_string()).with_source(error))?;
+            Ok(Value::from_safe_string(html))
+        });
+    }
+}
+
+impl Default for KatexFilter {
+    fn default() -> Self {
+        KatexFilter::new()
+    }
+}
@@ -0,0 +1,164 @@
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+
+use crate::{Core, EngineOptions, Interrupt};
+
+#[cfg(feature = "boa")]
+use crate::boa;
+#[cfg(feature = "qjs")]
+use crate::qjs;
+#[cfg(feature = "rquickjs")]
+use crate::rqjs;
+#[cfg(feature = "sandbox")]
+use crate::sandbox;
+#[cfg(feature = "v8")]
+use crate::v8;
+
+pub type Error = MultiError;
+
+// どのエンジンで起きたエラーかを残す
+#[derive(Debug, thiserror::Error)]
+pub enum MultiError {
+    #[cfg(feature = "v8")]
+    #[error(transparent)]
+    V8(#[from] v8::Error),
+    #[cfg(feature = "qjs")]
+    #[error(transparent)]
+    QuickJs(#[from] qjs::Error),
+    #[cfg(feature = "rquickjs")]
+    #[error(transparent)]
+    RQuickJs(#[from] rqjs::Error),
+    #[cfg(feature = "boa")]
+    #[error(transparent)]
+    Boa(#[from] boa::Error),
+    #[cfg(feature = "sandbox")]
+    #[error(transparent)]
+    Sandbox(#[from] sandbox::Error),
+    #[error("JSON Error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+// InitOptions::engineで選んだエンジン。作るときにEngineOptions::engineを見て、以降はそれに任せる
+pub(crate) enum Engine {
+    #[cfg(feature = "v8")]
+    V8(v8::Engine),
+    #[cfg(feature = "qjs")]
+    QuickJs(qjs::Engine),
+    #[cfg(feature = "rquickjs")]
+    RQuickJs(rqjs::Engine),
+    #[cfg(feature = "boa")]
+    Boa(boa::Engine),
+    #[cfg(feature = "sandbox")]
+    // wasmtimeのストアや関数を抱えていて他より大きいので、箱に入れる
+    Sandbox(Box<sandbox::Engine>),
+}
+
+impl Core for Engine {
+    type Error = MultiError;
+
+    fn new(code: &str, options: &EngineOptions) -> Result<Self, Self::Error> {
+        Ok(match options.engine {
+            #[cfg(feature = "v8")]
+            crate::Engine::V8 => Engine::V8(Core::new(code, options)?),
+            #[cfg(feature = "qjs")]
+            crate::Engine::QuickJs => Engine::QuickJs(Core::new(code, options)?),
+            #[cfg(feature = "rquickjs")]
+            crate::Engine::RQuickJs => Engine::RQuickJs(Core::new(code, options)?),
+            #[cfg(feature = "boa")]
+            crate::Engine::Boa => Engine::Boa(Core::new(code, options)?),
+            #[cfg(feature = "sandbox")]
+            crate::Engine::Sandbox => Engine::Sandbox(Box::new(Core::new(code, options)?)),
+        })
+    }
+
+    fn new_embedded(code: &str, options: &EngineOptions) -> Result<Self, Self::Error> {
+        Ok(match options.engine {
+            #[cfg(feature = "v8")]
+            crate::Engine::V8 => Engine::V8(Core::new_embedded(code, options)?),
+            #[cfg(feature = "qjs")]
+            crate::Engine::QuickJs => Engine::QuickJs(Core::new_embedded(code, options)?),
+            #[cfg(feature = "rquickjs")]
+            crate::Engine::RQuickJs => Engine::RQuickJs(Core::new_embedded(code, options)?),
+            #[cfg(feature = "boa")]
+            crate::Engine::Boa => Engine::Boa(Core::new_embedded(code, options)?),
+            #[cfg(feature = "sandbox")]
+            crate::Engine::Sandbox => Engine::Sandbox(Box::new(Core::new_embedded(code, options)?)),
+        })
+    }
+
+    fn new_with_snapshot(code: &str, path: &Path, header: &str, options: &EngineOptions) -> Result<Self, Self::Error> {
+        Ok(match options.engine {
+            #[cfg(feature = "v8")]
+            crate::Engine::V8 => Engine::V8(Core::new_with_snapshot(code, path, header, options)?),
+            #[cfg(feature = "qjs")]
+            crate::Engine::QuickJs => Engine::QuickJs(Core::new_with_snapshot(code, path, header, options)?),
+            #[cfg(feature = "rquickjs")]
+            crate::Engine::RQuickJs => Engine::RQuickJs(Core::new_with_snapshot(code, path, header, options)?),
+            #[cfg(feature = "boa")]
+            crate::Engine::Boa => Engine::Boa(Core::new_with_snapshot(code, path, header, options)?),
+            #[cfg(feature = "sandbox")]
+            crate::Engine::Sandbox => Engine::Sandbox(Box::new(Core::new_with_snapshot(code, path, header, options)?)),
+        })
+    }
+
+    fn engine_id(options: &EngineOptions) -> String {
+        match options.engine {
+            #[cfg(feature = "v8")]
+            crate::Engine::V8 => <v8::Engine as Core>::engine_id(options),
+            #[cfg(feature = "qjs")]
+            crate::Engine::QuickJs => <qjs::Engine as Core>::engine_id(options),
+            #[cfg(feature = "rquickjs")]
+            crate::Engine::RQuickJs => <rqjs::Engine as Core>::engine_id(options),
+            #[cfg(feature = "boa")]
+            crate::Engine::Boa => <boa::Engine as Core>::engine_id(options),
+            #[cfg(feature = "sandbox")]
+            crate::Engine::Sandbox => <sandbox::Engine as Core>::engine_id(options),
+        }
+    }
+
+    fn exec<O: DeserializeOwned>(&mut self, function: &str, input: &str) -> Result<O, Self::Error> {
+        match self {
+            #[cfg(feature = "v8")]
+            Engine::V8(engine) => Ok(engine.exec(function, input)?),
+            #[cfg(feature = "qjs")]
+            Engine::QuickJs(engine) => Ok(engine.exec(function, input)?),
+            #[cfg(feature = "rquickjs")]
+            Engine::RQuickJs(engine) => Ok(engine.exec(function, input)?),
+            #[cfg(feature = "boa")]
+            Engine::Boa(engine) => Ok(engine.exec(function, input)?),
+            #[cfg(feature = "sandbox")]
+            Engine::Sandbox(engine) => Ok(engine.exec(function, input)?),
+        }
+    }
+
+    fn interrupt_handle(&mut self) -> Interrupt {
+        match self {
+            #[cfg(feature = "v8")]
+            Engine::V8(engine) => engine.interrupt_handle(),
+            #[cfg(feature = "qjs")]
+            Engine::QuickJs(engine) => engine.interrupt_handle(),
+            #[cfg(feature = "rquickjs")]
+            Engine::RQuickJs(engine) => engine.interrupt_handle(),
+            #[cfg(feature = "boa")]
+            Engine::Boa(engine) => engine.interrupt_handle(),
+            #[cfg(feature = "sandbox")]
+            Engine::Sandbox(engine) => engine.interrupt_handle(),
+        }
+    }
+
+    fn clear_interrupt(&mut self) {
+        match self {
+            #[cfg(feature = "v8")]
+            Engine::V8(engine) => engine.clear_interrupt(),
+            #[cfg(feature = "qjs")]
+            Engine::QuickJs(engine) => engine.clear_interrupt(),
+            #[cfg(feature = "rquickjs")]
+            Engine::RQuickJs(engine) => engine.clear_interrupt(),
+            #[cfg(feature = "boa")]
+            Engine::Boa(engine) => engine.clear_interrupt(),
+            #[cfg(feature = "sandbox")]
+            Engine::Sandbox(engine) => engine.clear_interrupt(),
+        }
+    }
+}
@@ -0,0 +1,392 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+};
+
+use crate::MacroFilter;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Options {
+    pub display_mode: bool,
+    pub output: KatexOutput,
+    pub leqno: bool,
+    pub fleqn: bool,
+    pub throw_on_error: bool,
+    pub error_color: Cow<'static, str>,
+    pub min_rule_thickness: Option<f64>,
+    pub color_is_text_color: bool,
+    pub max_size: MaxSize,
+    pub max_expand: MaxExpand,
+    pub strict: Strict,
+    pub trust: TrustPolicy,
+    // trustで許したリンクや画像のURLをさらに絞る
+    pub url_policy: Option<UrlPolicy>,
+    pub global_group: bool,
+    // KaTeXのオプションではなく、描画したHTMLを詰めて小さくする
+    pub minify: bool,
+    // KaTeXのオプションではなく、\colorなどで指定した文字色を暗い背景向けの色に書き換える
+    pub dark_colors: Option<DarkColors>,
+    // KaTeXのオプションではなく、\colorなどの色とエラーの色をvar(--katex-...)にして、描画し直さずにCSSで変えられるようにする
+    pub css_variables: bool,
+    // KaTeXのオプションではなく、描画したHTMLをInitOptions::helperで登録したこの名前の関数に順に通す
+    pub helpers: Vec<String>,
+    // 数式で定義されたマクロを、呼び出し側の表に書き戻すかどうか。JSには渡さない
+    #[serde(skip)]
+    pub macro_commit: MacroCommit,
+    // 書き戻すときに、定義してよいマクロを絞る。JSには渡さない
+    #[serde(skip)]
+    pub macro_filter: Option<MacroFilter>,
+}
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            display_mode: false,
+            output: KatexOutput::HtmlAndMathml,
+            leqno: false,
+            fleqn: false,
+            throw_on_error: true,
+            error_color: "#cc0000".into(),
+            min_rule_thickness: None,
+            color_is_text_color: false,
+            max_size: MaxSize::Unlimited,
+            max_expand: MaxExpand::Limit(1000),
+            strict: Strict::Ignore,
+            trust: TrustPolicy::Never,
+            url_policy: None,
+            global_group: false,
+            minify: false,
+            dark_colors: None,
+            css_variables: false,
+            helpers: Vec::new(),
+            macro_commit: MacroCommit::OnSuccess,
+            macro_filter: None,
+        }
+    }
+}
+
+// 暗い背景向けの色は、色相と彩度をそのままで明るさを反転したもの。
+// Replaceはその色に置き換え、LightDarkはcolor-schemeで切り替わるようにlight-dark(元の色, 暗い背景向けの色)にする
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DarkColors {
+    Replace,
+    LightDark,
+}
+
+// Alwaysはエラーになった数式でも、エラーの前までに定義されたマクロを書き戻す。Neverは表を変えない
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum MacroCommit {
+    Always,
+    #[default]
+    OnSuccess,
+    Never,
+}
+impl MacroCommit {
+    pub(crate) fn applies(self, succeeded: bool) -> bool {
+        match self {
+            MacroCommit::Always => true,
+            MacroCommit::OnSuccess => succeeded,
+            MacroCommit::Never => false,
+        }
+    }
+}
+
+// 数式の中の幅や高さの上限(em)。MaxExpandと同じくUnlimitedはnullとして渡す
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(from = "Option<f64>", into = "Option<f64>")]
+pub enum MaxSize {
+    Limit(f64),
+    Unlimited,
+}
+// INFINITYもUnlimitedにする
+impl From<f64> for MaxSize {
+    fn from(limit: f64) -> Self {
+        if limit == f64::INFINITY { MaxSize::Unlimited } else { MaxSize::Limit(limit) }
+    }
+}
+impl From<Option<f64>> for MaxSize {
+    fn from(limit: Option<f64>) -> Self {
+        limit.map_or(MaxSize::Unlimited, MaxSize::from)
+    }
+}
+impl From<MaxSize> for Option<f64> {
+    fn from(max_size: MaxSize) -> Self {
+        match max_size {
+            MaxSize::Limit(limit) => Some(limit),
+            MaxSize::Unlimited => None,
+        }
+    }
+}
+
+// JSONにはInfinityが無いので、Unlimitedはnullとして渡し、JS側でInfinityに戻す
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Option<u32>", into = "Option<u32>")]
+pub enum MaxExpand {
+    Limit(u32),
+    Unlimited,
+}
+impl From<u32> for MaxExpand {
+    fn from(limit: u32) -> Self {
+        MaxExpand::Limit(limit)
+    }
+}
+impl From<Option<u32>> for MaxExpand {
+    fn from(limit: Option<u32>) -> Self {
+        limit.map_or(MaxExpand::Unlimited, MaxExpand::Limit)
+    }
+}
+impl From<MaxExpand> for Option<u32> {
+    fn from(max_expand: MaxExpand) -> Self {
+        match max_expand {
+            MaxExpand::Limit(limit) => Some(limit),
+            MaxExpand::Unlimited => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum KatexOutput {
+    Html,
+    Mathml,
+    HtmlAndMathml,
+    // HTMLに加えて、copy-tex拡張が読めるTeXのannotationだけを含める
+    HtmlAndTex,
+}
+
+// JS側ではtrue/falseか、{ commands: [...] }をtrustコールバックに変換して渡す
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "TrustValue", into = "TrustValue")]
+pub enum TrustPolicy {
+    #[default]
+    Never,
+    Always,
+    Commands(BTreeSet<String>),
+}
+impl TrustPolicy {
+    pub fn commands<S: Into<String>>(commands: impl IntoIterator<Item = S>) -> Self {
+        TrustPolicy::Commands(commands.into_iter().map(Into::into).collect())
+    }
+}
+impl From<bool> for TrustPolicy {
+    fn from(trust: bool) -> Self {
+        if trust { TrustPolicy::Always } else { TrustPolicy::Never }
+    }
+}
+
+// \href、\url、\includegraphicsのURLの許可リスト。trustコールバックの中で調べるので、trustで許したコマンドにだけ効く。
+// domainsが空ならどのホストでもよく、"example.com"はそのサブドメインも含む。mailto:のようにホストの無いURLはスキームだけを、
+// スキームの無い相対URLは何も調べずに通す
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct UrlPolicy {
+    pub schemes: BTreeSet<String>,
+    pub domains: BTreeSet<String>,
+}
+impl UrlPolicy {
+    pub fn new<S: Into<String>>(schemes: impl IntoIterator<Item = S>) -> Self {
+        UrlPolicy { schemes: schemes.into_iter().map(|scheme| scheme.into().to_ascii_lowercase()).collect(), domains: BTreeSet::new() }
+    }
+    // http、https、mailtoだけ
+    pub fn web() -> Self {
+        UrlPolicy::new(["http", "https", "mailto"])
+    }
+    pub fn domains<S: Into<String>>(mut self, domains: impl IntoIterator<Item = S>) -> Self {
+        self.domains = domains.into_iter().map(|domain| domain.into().to_ascii_lowercase()).collect();
+        self
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum TrustValue {
+    Bool(bool),
+    Commands { commands: BTreeSet<String> },
+}
+impl From<TrustValue> for TrustPolicy {
+    fn from(value: TrustValue) -> Self {
+        match value {
+            TrustValue::Bool(trust) => trust.into(),
+            TrustValue::Commands { commands } => TrustPolicy::Commands(commands),
+        }
+    }
+}
+impl From<TrustPolicy> for TrustValue {
+    fn from(policy: TrustPolicy) -> Self {
+        match policy {
+            TrustPolicy::Never => TrustValue::Bool(false),
+            TrustPolicy::Always => TrustValue::Bool(true),
+            TrustPolicy::Commands(commands) => TrustValue::Commands { commands },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StrictMode {
+    #[default]
+    Ignore,
+    Warn,
+    Error,
+}
+
+// JS側では"ignore"/"warn"/"error"か、{ codes: {...} }をerrorCodeで引くコールバックに変換して渡す
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "StrictValue", into = "StrictValue")]
+pub enum Strict {
+    #[default]
+    Ignore,
+    Warn,
+    Error,
+    PerCode(BTreeMap<String, StrictMode>),
+}
+impl From<StrictMode> for Strict {
+    fn from(mode: StrictMode) -> Self {
+        match mode {
+            StrictMode::Ignore => Strict::Ignore,
+            StrictMode::Warn => Strict::Warn,
+            StrictMode::Error => Strict::Error,
+        }
+    }
+}
+impl From<bool> for Strict {
+    fn from(strict: bool) -> Self {
+        if strict { Strict::Error } else { Strict::Ignore }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum StrictValue {
+    Bool(bool),
+    Mode(StrictMode),
+    PerCode { codes: BTreeMap<String, StrictMode> },
+}
+impl From<StrictValue> for Strict {
+    fn from(value: StrictValue) -> Self {
+        match value {
+            StrictValue::Bool(strict) => strict.into(),
+            StrictValue::Mode(mode) => mode.into(),
+            StrictValue::PerCode { codes } => Strict::PerCode(codes),
+        }
+    }
+}
+impl From<Strict> for StrictValue {
+    fn from(strict: Strict) -> Self {
+        match strict {
+            Strict::Ignore => StrictValue::Mode(StrictMode::Ignore),
+            Strict::Warn => StrictValue::Mode(StrictMode::Warn),
+            Strict::Error => StrictValue::Mode(StrictMode::Error),
+            Strict::PerCode(codes) => StrictValue::PerCode { codes },
+        }
+    }
+}
+
+impl Options {
+    pub fn builder() -> OptionsBuilder {
+        OptionsBuilder::default()
+    }
+    pub fn display() -> Self {
+        Options { display_mode: true, ..Default::default() }
+    }
+    // 文中の数式。書き間違いがあっても文書全体を止めず、そこだけerror_colorで原文を出す
+    pub fn inline() -> Self {
+        Options { throw_on_error: false, ..Default::default() }
+    }
+    // 信頼できない入力向け。\hrefなどを許さず、LaTeXと違う書き方はエラーにする
+    pub fn strict_secure() -> Self {
+        Options { trust: TrustPolicy::Never, strict: Strict::Error, ..Default::default() }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct OptionsBuilder {
+    options: Options,
+}
+impl OptionsBuilder {
+    pub fn display(mut self, display_mode: bool) -> Self {
+        self.options.display_mode = display_mode;
+        self
+    }
+    pub fn output(mut self, output: KatexOutput) -> Self {
+        self.options.output = output;
+        self
+    }
+    pub fn leqno(mut self, leqno: bool) -> Self {
+        self.options.leqno = leqno;
+        self
+    }
+    pub fn fleqn(mut self, fleqn: bool) -> Self {
+        self.options.fleqn = fleqn;
+        self
+    }
+    pub fn throw_on_error(mut self, throw_on_error: bool) -> Self {
+        self.options.throw_on_error = throw_on_error;
+        self
+    }
+    pub fn error_color(mut self, error_color: impl Into<Cow<'static, str>>) -> Self {
+        self.options.error_color = error_color.into();
+        self
+    }
+    pub fn min_rule_thickness(mut self, min_rule_thickness: f64) -> Self {
+        self.options.min_rule_thickness = Some(min_rule_thickness);
+        self
+    }
+    pub fn color_is_text_color(mut self, color_is_text_color: bool) -> Self {
+        self.options.color_is_text_color = color_is_text_color;
+        self
+    }
+    pub fn max_size(mut self, max_size: impl Into<MaxSize>) -> Self {
+        self.options.max_size = max_size.into();
+        self
+    }
+    pub fn max_expand(mut self, max_expand: impl Into<MaxExpand>) -> Self {
+        self.options.max_expand = max_expand.into();
+        self
+    }
+    pub fn strict(mut self, strict: impl Into<Strict>) -> Self {
+        self.options.strict = strict.into();
+        self
+    }
+    pub fn trust(mut self, trust: impl Into<TrustPolicy>) -> Self {
+        self.options.trust = trust.into();
+        self
+    }
+    pub fn url_policy(mut self, url_policy: UrlPolicy) -> Self {
+        self.options.url_policy = Some(url_policy);
+        self
+    }
+    pub fn global_group(mut self, global_group: bool) -> Self {
+        self.options.global_group = global_group;
+        self
+    }
+    pub fn minify(mut self, minify: bool) -> Self {
+        self.options.minify = minify;
+        self
+    }
+    pub fn css_variables(mut self, css_variables: bool) -> Self {
+        self.options.css_variables = css_variables;
+        self
+    }
+    pub fn dark_colors(mut self, dark_colors: DarkColors) -> Self {
+        self.options.dark_colors = Some(dark_colors);
+        self
+    }
+    pub fn helpers<S: Into<String>>(mut self, helpers: impl IntoIterator<Item = S>) -> Self {
+        self.options.helpers = helpers.into_iter().map(Into::into).collect();
+        self
+    }
+    pub fn macro_commit(mut self, macro_commit: MacroCommit) -> Self {
+        self.options.macro_commit = macro_commit;
+        self
+    }
+    pub fn macro_filter(mut self, macro_filter: MacroFilter) -> Self {
+        self.options.macro_filter = Some(macro_filter);
+        self
+    }
+    pub fn build(self) -> Options {
+        self.options
+    }
+}
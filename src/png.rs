@@ -0,0 +1,49 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use once_cell::sync::Lazy;
+use resvg::{
+    tiny_skia,
+    usvg::{self, fontdb},
+};
+
+use crate::Error;
+
+// フォントを探して読むのは重いので、システムのフォントは一度だけ、KaTeXのフォントはディレクトリごとに一度だけ読む
+static SYSTEM_FONTS: Lazy<Arc<fontdb::Database>> = Lazy::new(|| {
+    let mut fonts = fontdb::Database::new();
+    fonts.load_system_fonts();
+    Arc::new(fonts)
+});
+static KATEX_FONTS: Lazy<Mutex<HashMap<PathBuf, Arc<fontdb::Database>>>> = Lazy::new(Default::default);
+
+// システムのフォントにKaTeXのフォント(fonts/の*.ttf)を足したもの
+pub(crate) fn katex_fonts(dir: &Path) -> Arc<fontdb::Database> {
+    let mut fonts = KATEX_FONTS.lock().unwrap();
+    fonts
+        .entry(dir.to_path_buf())
+        .or_insert_with(|| {
+            let mut fonts = (**SYSTEM_FONTS).clone();
+            fonts.load_fonts_dir(dir);
+            Arc::new(fonts)
+        })
+        .clone()
+}
+
+// SVGをdpiに合わせた大きさのPNGにする。SVGの1pxは96dpiでの1pxとして扱う
+pub fn svg_to_png(svg: &str, dpi: f32) -> Result<Vec<u8>, Error> {
+    rasterize(svg, dpi, SYSTEM_FONTS.clone())
+}
+
+pub(crate) fn rasterize(svg: &str, dpi: f32, fonts: Arc<fontdb::Database>) -> Result<Vec<u8>, Error> {
+    let options = usvg::Options { dpi, fontdb: fonts, ..Default::default() };
+    let tree = usvg::Tree::from_str(svg, &options).map_err(|error| Error::Png(error.to_string()))?;
+    let scale = dpi / 96.0;
+    let size = tree.size().to_int_size().scale_by(scale).ok_or_else(|| Error::Png("image size is zero".to_string()))?;
+    let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height()).ok_or_else(|| Error::Png("image size is zero".to_string()))?;
+    resvg::render(&tree, tiny_skia::Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+    pixmap.encode_png().map_err(|error| Error::Png(error.to_string()))
+}
@@ -0,0 +1,77 @@
+use std::collections::BTreeMap;
+
+use crate::MacroValue;
+
+// 必要なものだけ取り込んで、自分のマクロの表にextendして使う。
+// KaTeXが既に持っているもの(\bra、\ket、\braket、\argminなど)は基本的に入れない
+
+// physicsパッケージ風の微分、絶対値、ベクトルなど。引数は全て必須
+pub fn physics() -> BTreeMap<String, MacroValue> {
+    preset(&[
+        (r"\dd", 0, r"\mathrm{d}"),
+        (r"\dv", 2, r"\frac{\mathrm{d}#1}{\mathrm{d}#2}"),
+        (r"\pdv", 2, r"\frac{\partial #1}{\partial #2}"),
+        (r"\abs", 1, r"\left\lvert #1 \right\rvert"),
+        (r"\norm", 1, r"\left\lVert #1 \right\rVert"),
+        (r"\expval", 1, r"\left\langle #1 \right\rangle"),
+        (r"\order", 1, r"\mathcal{O}\left(#1\right)"),
+        (r"\comm", 2, r"\left[#1, #2\right]"),
+        (r"\acomm", 2, r"\left\{#1, #2\right\}"),
+        (r"\vb", 1, r"\mathbf{#1}"),
+        (r"\vu", 1, r"\hat{\mathbf{#1}}"),
+        (r"\grad", 0, r"\nabla"),
+        (r"\divergence", 0, r"\nabla\cdot"),
+        (r"\curl", 0, r"\nabla\times"),
+        (r"\laplacian", 0, r"\nabla^2"),
+        (r"\tr", 0, r"\operatorname{tr}"),
+        (r"\Tr", 0, r"\operatorname{Tr}"),
+    ])
+}
+
+// KaTeXの\bra、\ket、\braketに無いもの
+pub fn braket() -> BTreeMap<String, MacroValue> {
+    preset(&[
+        (r"\ketbra", 2, r"\left\lvert #1 \middle\rangle\!\middle\langle #2 \right\rvert"),
+        (r"\proj", 1, r"\left\lvert #1 \middle\rangle\!\middle\langle #1 \right\rvert"),
+        (r"\ip", 2, r"\left\langle #1 \middle\vert #2 \right\rangle"),
+        (r"\mel", 3, r"\left\langle #1 \middle\vert #2 \middle\vert #3 \right\rangle"),
+        (r"\ev", 1, r"\left\langle #1 \right\rangle"),
+    ])
+}
+
+// 定義や証明でよく使う略記
+pub fn theorem() -> BTreeMap<String, MacroValue> {
+    preset(&[
+        (r"\defeq", 0, r"\mathrel{:=}"),
+        (r"\st", 0, r"\mathrel{:}"),
+        (r"\eps", 0, r"\varepsilon"),
+        (r"\floor", 1, r"\left\lfloor #1 \right\rfloor"),
+        (r"\ceil", 1, r"\left\lceil #1 \right\rceil"),
+        (r"\inner", 2, r"\left\langle #1, #2 \right\rangle"),
+        (r"\qed", 0, r"\quad\blacksquare"),
+    ])
+}
+
+// \Rなどの黒板太字。\N、\Z、\RはKaTeXにもあるが揃えて入れておく。\Hや\PはKaTeXの別のコマンドなので含めない
+pub fn blackboard() -> BTreeMap<String, MacroValue> {
+    preset(&[
+        (r"\N", 0, r"\mathbb{N}"),
+        (r"\Z", 0, r"\mathbb{Z}"),
+        (r"\Q", 0, r"\mathbb{Q}"),
+        (r"\R", 0, r"\mathbb{R}"),
+        (r"\C", 0, r"\mathbb{C}"),
+        (r"\F", 0, r"\mathbb{F}"),
+        (r"\K", 0, r"\mathbb{K}"),
+        (r"\E", 0, r"\mathbb{E}"),
+    ])
+}
+
+fn preset(definitions: &[(&str, usize, &str)]) -> BTreeMap<String, MacroValue> {
+    definitions
+        .iter()
+        .map(|&(name, num_args, body)| {
+            let value = if num_args == 0 { body.into() } else { MacroValue::WithArgs { num_args, body: body.to_string() } };
+            (name.to_string(), value)
+        })
+        .collect()
+}
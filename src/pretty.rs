@@ -0,0 +1,83 @@
+// 描画したHTMLを一要素一行にして字下げする。下流のテストで期待するHTMLと比べたときに差分を読めるようにするためのもので、
+// 属性は名前順に並べ直し、要素の間の空白だけのテキストは捨てる。テキストだけを持つ要素はその行に中身を書く
+pub fn format_html(html: &str) -> String {
+    let mut tokenizer = html5gum::Tokenizer::new(html);
+    let mut output = String::new();
+    for node in parse(None, &mut tokenizer) {
+        write_node(&node, 0, &mut output);
+    }
+    output
+}
+
+enum Node {
+    Element { start: String, name: String, children: Option<Vec<Node>> },
+    Text(String),
+}
+
+// childrenがNoneのものは終了タグを持たない(<path/>や<img>)
+fn parse(element: Option<&[u8]>, tokens: &mut html5gum::Tokenizer<html5gum::StringReader>) -> Vec<Node> {
+    const VOID: [&[u8]; 4] = [b"br", b"img", b"hr", b"input"];
+    let mut nodes = Vec::new();
+    while let Some(Ok(token)) = tokens.next() {
+        match token {
+            html5gum::Token::EndTag(tag) if Some(tag.name.to_ascii_lowercase().as_slice()) == element => break,
+            html5gum::Token::String(text) => nodes.push(Node::Text(String::from_utf8_lossy(&text).into_owned())),
+            html5gum::Token::StartTag(tag) => {
+                let name = tag.name.to_ascii_lowercase();
+                let mut start = format!("<{}", String::from_utf8_lossy(&name));
+                for (attribute, value) in &tag.attributes {
+                    start.push_str(&format!(" {}=\"{}\"", String::from_utf8_lossy(attribute), escape(&String::from_utf8_lossy(value))));
+                }
+                let children = if tag.self_closing {
+                    start.push_str("/>");
+                    None
+                } else {
+                    start.push('>');
+                    (!VOID.contains(&name.as_slice())).then(|| parse(Some(&name), tokens))
+                };
+                nodes.push(Node::Element { start, name: String::from_utf8_lossy(&name).into_owned(), children });
+            }
+            _ => (),
+        }
+    }
+    nodes
+}
+
+fn write_node(node: &Node, depth: usize, output: &mut String) {
+    let indent = "  ".repeat(depth);
+    match node {
+        Node::Text(text) if text.trim().is_empty() => {}
+        Node::Text(text) => output.push_str(&format!("{indent}{}\n", escape(text))),
+        Node::Element { start, children: None, .. } => output.push_str(&format!("{indent}{start}\n")),
+        Node::Element { start, name, children: Some(children) } => {
+            if children.iter().all(|child| matches!(child, Node::Text(_))) {
+                let text: String =
+                    children.iter().map(|child| if let Node::Text(text) = child { escape(text) } else { String::new() }).collect();
+                output.push_str(&format!("{indent}{start}{text}</{name}>\n"));
+                return;
+            }
+            output.push_str(&format!("{indent}{start}\n"));
+            for child in children {
+                write_node(child, depth + 1, output);
+            }
+            output.push_str(&format!("{indent}</{name}>\n"));
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_element_per_line() {
+        let html = r#"<span style="top:0" class="a"> <span>x &lt; y</span><br><svg><path d="M0"/></svg></span>"#;
+        let expected =
+            "<span class=\"a\" style=\"top:0\">\n  <span>x &lt; y</span>\n  <br>\n  <svg>\n    <path d=\"M0\"/>\n  </svg>\n</span>\n";
+        assert_eq!(format_html(html), expected);
+    }
+}
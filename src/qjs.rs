@@ -1,11 +1,19 @@
-use std::io::Read as _;
+use std::{
+    ffi::{c_int, c_void},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
 
-use crate::{Core, Input, Output};
+use libquickjs_ng_sys as q;
+use serde::de::DeserializeOwned;
+
+use crate::{Core, EngineOptions, Interrupt, cache};
 
 use quickjs_rusty as qjs;
 pub use quickjs_rusty::Context;
 
-pub(crate) type Engine = qjs::Context;
 pub type Error = QJSError;
 
 #[derive(Debug, thiserror::Error)]
@@ -22,36 +30,68 @@ pub enum QJSError {
     Json(#[from] serde_json::Error),
 }
 
-impl Core for qjs::Context {
+// 割り込みハンドラから参照するフラグを、コンテキストより後に破棄されるよう一緒に持つ
+pub(crate) struct Engine {
+    ctx: Context,
+    interrupted: Arc<AtomicBool>,
+}
+
+impl Engine {
+    fn from_context(ctx: Context) -> Engine {
+        let interrupted = Arc::new(AtomicBool::new(false));
+        unsafe {
+            let runtime = q::JS_GetRuntime(ctx.context_raw());
+            q::JS_SetInterruptHandler(runtime, Some(interrupt_handler), Arc::as_ptr(&interrupted) as *mut c_void);
+        }
+        Engine { ctx, interrupted }
+    }
+}
+
+unsafe extern "C" fn interrupt_handler(_runtime: *mut q::JSRuntime, opaque: *mut c_void) -> c_int {
+    let interrupted = unsafe { &*(opaque as *const AtomicBool) };
+    interrupted.load(Ordering::SeqCst) as c_int
+}
+
+impl Core for Engine {
     type Error = QJSError;
 
-    fn new() -> Result<Self, Self::Error> {
+    fn new(code: &str, _options: &EngineOptions) -> Result<Self, Self::Error> {
         let ctx = Context::new(None)?;
-        ctx.eval(crate::KATEX_CODE, false)?;
-        Ok(ctx)
+        ctx.eval(code, false)?;
+        Ok(Engine::from_context(ctx))
     }
 
-    fn new_with_snapshot(cache: &std::path::Path) -> Result<Self, Self::Error> {
+    fn new_with_snapshot(code: &str, path: &std::path::Path, header: &str, _options: &EngineOptions) -> Result<Self, Self::Error> {
         let ctx = Context::new(None)?;
-        let compiled_katex = if cache.exists() {
-            let mut file = std::fs::File::open(cache)?;
-            let mut bytecode = Vec::new();
-            file.read_to_end(&mut bytecode)?;
-            unsafe { qjs::compile::from_bytecode(ctx.context_raw(), &bytecode)?.try_into_compiled_function()? }
-        } else {
-            unsafe {
-                let compiled_katex =
-                    qjs::compile::compile(ctx.context_raw(), crate::KATEX_CODE, "katex.min.js")?.try_into_compiled_function()?;
-                std::fs::write(cache, qjs::compile::to_bytecode(ctx.context_raw(), &compiled_katex))?;
-                compiled_katex
-            }
-        };
+        let bytecode = cache::read_or_create(path, header, || -> Result<_, QJSError> {
+            let compiled_katex = unsafe { qjs::compile::compile(ctx.context_raw(), code, "katex.min.js")?.try_into_compiled_function()? };
+            Ok(unsafe { qjs::compile::to_bytecode(ctx.context_raw(), &compiled_katex) })
+        })?;
+        let compiled_katex = unsafe { qjs::compile::from_bytecode(ctx.context_raw(), &bytecode)?.try_into_compiled_function()? };
         qjs::compile::run_compiled_function(&compiled_katex)?;
-        Ok(ctx)
+        Ok(Engine::from_context(ctx))
+    }
+
+    fn engine_id(_options: &EngineOptions) -> String {
+        "quickjs-ng".to_string()
     }
 
-    fn exec(&mut self, input: Input) -> Result<Output, Self::Error> {
-        let result = self.eval(&format!("renderToStringAndMacros({})", serde_json::to_string(&input)?), false)?;
+    fn exec<O: DeserializeOwned>(&mut self, function: &str, input: &str) -> Result<O, Self::Error> {
+        let script = format!("{function}({input})");
+        let result = {
+            let _span = span!("katex.js", function);
+            self.ctx.eval(&script, false)?
+        };
+        let _span = span!("katex.deserialize");
         Ok(serde_json::from_str(&result.to_string()?)?)
     }
+
+    fn interrupt_handle(&mut self) -> Interrupt {
+        let interrupted = self.interrupted.clone();
+        Box::new(move || interrupted.store(true, Ordering::SeqCst))
+    }
+
+    fn clear_interrupt(&mut self) {
+        self.interrupted.store(false, Ordering::SeqCst);
+    }
 }
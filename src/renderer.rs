@@ -0,0 +1,1345 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashSet, VecDeque},
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    },
+    time::Duration,
+};
+
+#[cfg(not(feature = "wasm"))]
+use std::thread::{self, JoinHandle};
+
+use serde::{Serialize, de::DeserializeOwned};
+
+#[cfg(feature = "standalone")]
+use crate::css::standalone_html;
+use crate::{
+    BatchInput, BatchItem, CheckOutput, CollectOutput, Core, DiagnoseOutput, Diagnostic, Dimensions, EngineOptions, Error, ExpandOutput,
+    HelperInput, HelperOutput, Input, Interrupt, JSError, JsEngine, KatexOutput, KatexVersion, MacroCommit, MacroConflict, MacroScope,
+    MacroValue, MacrosOutput, MeasureOutput, Options, Output, ParseDiagnostics, PartsOutput, Rendered, RenderedParts, Severity, TreeOutput,
+    UsedOutput,
+    auto_render::{Delimiter, Segment, split_at_delimiters},
+    cache, commit_macros, katex_code,
+    text::mathml_to_text,
+};
+#[cfg(feature = "png")]
+use crate::{SvgOutput, png};
+
+// ワーカースレッドでエンジンを受け取って実行されるジョブ
+struct Job {
+    priority: Priority,
+    run: Box<dyn FnOnce(&mut JsEngine) + Send>,
+}
+
+// ワーカーはInteractiveのジョブを先に実行し、無いときだけBatchのジョブを実行する。
+// render_batchとrender_iterはBatch、それ以外はInteractive
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Priority {
+    #[default]
+    Interactive,
+    Batch,
+}
+
+// ワーカーが受け取ったジョブを優先度ごとに並べておく
+#[derive(Default)]
+struct Lanes {
+    interactive: VecDeque<Job>,
+    batch: VecDeque<Job>,
+}
+impl Lanes {
+    // どちらも空ならジョブが来るのを待つ。送信口が全て閉じたときと、idleの間に何も来なかったときはNone
+    #[cfg(not(feature = "wasm"))]
+    fn next(&mut self, jobs: &Receiver<Job>, idle: Option<Duration>) -> Option<Job> {
+        if self.interactive.is_empty() && self.batch.is_empty() {
+            let job = match idle {
+                Some(idle) => jobs.recv_timeout(idle).ok()?,
+                None => jobs.recv().ok()?,
+            };
+            self.push(job);
+        }
+        self.try_next(jobs)
+    }
+    fn try_next(&mut self, jobs: &Receiver<Job>) -> Option<Job> {
+        while let Ok(job) = jobs.try_recv() {
+            self.push(job);
+        }
+        self.interactive.pop_front().or_else(|| self.batch.pop_front())
+    }
+    fn push(&mut self, job: Job) {
+        match job.priority {
+            Priority::Interactive => self.interactive.push_back(job),
+            Priority::Batch => self.batch.push_back(job),
+        }
+    }
+}
+
+// v8/qjs/rquickjs/boa/sandboxのうち二つ以上を有効にしたビルドで、InitOptions::engineに渡す。ビルドに含まれるものだけがある
+#[cfg(multi_engine)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Engine {
+    #[cfg(feature = "v8")]
+    V8,
+    #[cfg(feature = "qjs")]
+    QuickJs,
+    #[cfg(feature = "rquickjs")]
+    RQuickJs,
+    #[cfg(feature = "boa")]
+    Boa,
+    #[cfg(feature = "sandbox")]
+    Sandbox,
+}
+#[cfg(multi_engine)]
+impl Engine {
+    // ビルドに含まれるもの。先頭が既定
+    pub const ALL: &[Engine] = &[
+        #[cfg(feature = "v8")]
+        Engine::V8,
+        #[cfg(feature = "qjs")]
+        Engine::QuickJs,
+        #[cfg(feature = "rquickjs")]
+        Engine::RQuickJs,
+        #[cfg(feature = "boa")]
+        Engine::Boa,
+        #[cfg(feature = "sandbox")]
+        Engine::Sandbox,
+    ];
+    // KATEX_GDEF_ENGINEやキャッシュのディレクトリで使う名前
+    pub fn name(self) -> &'static str {
+        match self {
+            #[cfg(feature = "v8")]
+            Engine::V8 => "v8",
+            #[cfg(feature = "qjs")]
+            Engine::QuickJs => "qjs",
+            #[cfg(feature = "rquickjs")]
+            Engine::RQuickJs => "rquickjs",
+            #[cfg(feature = "boa")]
+            Engine::Boa => "boa",
+            #[cfg(feature = "sandbox")]
+            Engine::Sandbox => "sandbox",
+        }
+    }
+}
+// V8、QuickJS、rquickjs、Boa、サンドボックスの順で、ビルドに含まれる最初のもの
+#[cfg(multi_engine)]
+impl Default for Engine {
+    fn default() -> Self {
+        Engine::ALL[0]
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct InitOptions {
+    cache: Option<PathBuf>,
+    katex_version: Option<KatexVersion>,
+    katex_source: Option<String>,
+    helpers: BTreeMap<String, String>,
+    extra_js: Vec<String>,
+    queue_capacity: Option<usize>,
+    idle_timeout: Option<Duration>,
+    thread_name: Option<String>,
+    stack_size: Option<usize>,
+    #[cfg(any(feature = "v8", feature = "sandbox"))]
+    #[cfg(not(feature = "wasm"))]
+    max_heap_mb: Option<usize>,
+    #[cfg(feature = "v8")]
+    #[cfg(not(feature = "wasm"))]
+    configure_v8: Option<crate::v8::ConfigureV8>,
+    #[cfg(multi_engine)]
+    engine: Engine,
+}
+impl InitOptions {
+    pub fn cache(mut self, path: impl AsRef<Path>) -> Self {
+        self.cache = Some(path.as_ref().to_path_buf());
+        self
+    }
+    pub fn katex_version(mut self, version: KatexVersion) -> Self {
+        self.katex_version = Some(version);
+        self
+    }
+    // 同梱のkatex.min.jsの代わりに使うKaTeXのソース
+    pub fn katex_source(mut self, js: impl Into<String>) -> Self {
+        self.katex_source = Some(js.into());
+        self
+    }
+    // 名前を付けたJSの関数(式のソース)。シムの後、extra_jsより前に定義され、スナップショットにも含まれる。
+    // Options::helpersで描画したHTMLを通すか、Renderer::eval_helperで直接呼ぶ
+    pub fn helper(mut self, name: impl Into<String>, function: impl Into<String>) -> Self {
+        self.helpers.insert(name.into(), function.into());
+        self
+    }
+    // katex.min.jsの後に実行され、スナップショットにも含まれるスクリプト
+    pub fn extra_js(mut self, scripts: Vec<String>) -> Self {
+        self.extra_js = scripts;
+        self
+    }
+    // try_renderが待たせておくジョブの数の上限。これを超えるとError::Busyを返す。
+    // 指定しなければtry_renderも普通に待つ
+    pub fn queue_capacity(mut self, capacity: usize) -> Self {
+        self.queue_capacity = Some(capacity);
+        self
+    }
+    // この間ジョブが来なければワーカーを止めてエンジンのヒープを手放し、次の呼び出しで作り直す。
+    // 作り直すときはキャッシュのスナップショットから起動する。wasm32では効かない
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+    // ワーカースレッドの名前。指定しなければ名前を付けない
+    pub fn thread_name(mut self, name: impl Into<String>) -> Self {
+        self.thread_name = Some(name.into());
+        self
+    }
+    // ワーカースレッドのスタックの大きさ(バイト)。深く入れ子になった数式ではKaTeXの再帰で既定の大きさが足りなくなる
+    pub fn stack_size(mut self, bytes: usize) -> Self {
+        self.stack_size = Some(bytes);
+        self
+    }
+    // V8のヒープ、またはサンドボックスの線形メモリの上限(MiB)。上限に近づいたら実行中の描画を打ち切ってError::OutOfMemoryを返し、
+    // エンジンはそのまま使い続ける。V8かサンドボックスを使うビルドにだけある
+    #[cfg(any(feature = "v8", feature = "sandbox"))]
+    #[cfg(not(feature = "wasm"))]
+    pub fn max_heap_mb(mut self, megabytes: usize) -> Self {
+        self.max_heap_mb = Some(megabytes);
+        self
+    }
+    // JsRuntimeを作るたびに、RuntimeOptionsを渡して呼ぶ。ワーカーを作り直したときやスナップショットを作るときにも呼ぶので、
+    // 何度呼ばれても同じ設定になるようにする。スナップショットの中身を変える拡張を足すときは、別のキャッシュのパスを使う
+    #[cfg(feature = "v8")]
+    #[cfg(not(feature = "wasm"))]
+    pub fn configure_v8(mut self, configure: impl Fn(&mut deno_core::RuntimeOptions) + Send + Sync + 'static) -> Self {
+        self.configure_v8 = Some(crate::v8::ConfigureV8(Arc::new(configure)));
+        self
+    }
+    // このレンダラーで使うエンジン。max_heap_mbはV8とサンドボックス、configure_v8はV8のときだけ効く
+    #[cfg(multi_engine)]
+    pub fn engine(mut self, engine: Engine) -> Self {
+        self.engine = engine;
+        self
+    }
+    fn engine_options(&self) -> EngineOptions {
+        EngineOptions {
+            #[cfg(any(feature = "v8", feature = "sandbox"))]
+            #[cfg(not(feature = "wasm"))]
+            max_heap_mb: self.max_heap_mb,
+            #[cfg(feature = "v8")]
+            #[cfg(not(feature = "wasm"))]
+            configure_v8: self.configure_v8.clone(),
+            #[cfg(multi_engine)]
+            engine: self.engine,
+        }
+    }
+    fn bundled_version(&self) -> Option<KatexVersion> {
+        if self.katex_source.is_some() { None } else { self.katex_version.or_else(KatexVersion::latest) }
+    }
+    fn code(&self) -> String {
+        let source = self.katex_source.as_deref().or(self.bundled_version().map(KatexVersion::source));
+        katex_code(source, &self.helpers, &self.extra_js)
+    }
+    // 埋め込みスナップショットは既定の構成(同梱の最新版、ヘルパーも追加スクリプトもなし)のときだけ使える。
+    // ビルド時にはconfigure_v8もmax_heap_mbも通していないので、どちらかがあれば実行時に作る
+    fn embedded(&self) -> bool {
+        #[cfg(feature = "v8")]
+        #[cfg(not(feature = "wasm"))]
+        if self.configure_v8.is_some() || self.max_heap_mb.is_some() {
+            return false;
+        }
+        cfg!(feature = "embedded-snapshot")
+            && self.katex_source.is_none()
+            && self.helpers.is_empty()
+            && self.extra_js.is_empty()
+            && self.bundled_version() == KatexVersion::latest()
+    }
+    fn katex_label(&self) -> &'static str {
+        self.bundled_version().map_or("custom", KatexVersion::version)
+    }
+    // 同梱版を使うときはバージョンごとに別のキャッシュファイルにする
+    fn cache_path(&self) -> Option<PathBuf> {
+        let cache = self.cache.as_ref()?;
+        let Some(version) = self.bundled_version() else { return Some(cache.clone()) };
+        let mut file_name = cache.file_name().unwrap_or_default().to_os_string();
+        file_name.push(format!("-{}", version.version()));
+        Some(cache.with_file_name(file_name))
+    }
+}
+
+// 時間切れになったジョブを打ち切るために、呼び出し側とワーカーで共有する状態
+#[derive(Default)]
+struct Watchdog {
+    state: Mutex<WatchdogState>,
+}
+#[derive(Default)]
+struct WatchdogState {
+    last_id: u64,
+    started: u64,
+    running: bool,
+    cancelled: HashSet<u64>,
+    interrupt: Option<Interrupt>,
+}
+impl Watchdog {
+    // ワーカーを作り直したときは、前のワーカーで実行中だったジョブを忘れる
+    fn attach(&self, interrupt: Interrupt) {
+        let mut state = self.state.lock().unwrap();
+        state.running = false;
+        state.interrupt = Some(interrupt);
+    }
+    // idの採番と送信を同じロックの中で行い、ワーカーがidの順にジョブを受け取るようにする。
+    // 時間切れのあるジョブは全てInteractiveなので、実行もidの順になる
+    fn send(&self, worker: &Sender<Job>, job: impl FnOnce(u64) -> Job) -> Result<u64, Error> {
+        let mut state = self.state.lock().unwrap();
+        let id = state.last_id + 1;
+        worker.send(job(id)).map_err(|_| Error::SendError)?;
+        state.last_id = id;
+        Ok(id)
+    }
+    fn start(&self, id: u64) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.started = id;
+        state.running = !state.cancelled.remove(&id);
+        state.running
+    }
+    fn finish(&self, engine: &mut JsEngine) {
+        let mut state = self.state.lock().unwrap();
+        state.running = false;
+        engine.clear_interrupt();
+    }
+    fn cancel(&self, id: u64) {
+        let mut state = self.state.lock().unwrap();
+        if id > state.started {
+            state.cancelled.insert(id);
+        } else if id == state.started
+            && state.running
+            && let Some(interrupt) = &state.interrupt
+        {
+            interrupt();
+        }
+    }
+}
+
+// generationは作り直すたびに増え、同じワーカーの死を何度も処理しないために使う
+struct Worker {
+    sender: Sender<Job>,
+    generation: u64,
+    // スレッドを作れなかったときはそのエラー。次に使うときに作り直してみる
+    #[cfg(not(feature = "wasm"))]
+    thread: io::Result<JoinHandle<()>>,
+    // wasm32ではスレッドを作れないので、ジョブは呼び出し側がpumpでその場で実行する
+    #[cfg(feature = "wasm")]
+    local: Arc<Mutex<LocalWorker>>,
+    // エンジンの構築結果。warm_upで一度だけ受け取る
+    ready: Option<Receiver<Result<(), JSError>>>,
+}
+impl Worker {
+    // 送信口を閉じて、キューに残ったジョブを処理し終えるのを待つ
+    fn stop(self) {
+        drop(self.sender);
+        #[cfg(not(feature = "wasm"))]
+        if let Ok(thread) = self.thread {
+            let _ = thread.join();
+        }
+        #[cfg(feature = "wasm")]
+        self.local.lock().unwrap().run_pending();
+    }
+}
+
+#[cfg(feature = "wasm")]
+struct LocalWorker {
+    runtime: Option<JsEngine>,
+    jobs: Receiver<Job>,
+    lanes: Lanes,
+}
+#[cfg(feature = "wasm")]
+impl LocalWorker {
+    // エンジンを作れなかったときはジョブを捨て、呼び出し側にはワーカーが死んだように見せる
+    fn run_pending(&mut self) {
+        while let Some(job) = self.lanes.try_next(&self.jobs) {
+            if let Some(runtime) = &mut self.runtime {
+                (job.run)(runtime);
+            }
+        }
+    }
+}
+
+fn start_engine(init: &InitOptions, watchdog: &Watchdog, ready: Sender<Result<(), JSError>>) -> Option<JsEngine> {
+    let code = init.code();
+    let options = init.engine_options();
+    let runtime = if init.embedded() {
+        <JsEngine as Core>::new_embedded(&code, &options)
+    } else if let Some(cache) = init.cache_path() {
+        let header = cache::header(init.katex_label(), &<JsEngine as Core>::engine_id(&options), &code);
+        <JsEngine as Core>::new_with_snapshot(&code, &cache, &header, &options)
+    } else {
+        <JsEngine as Core>::new(&code, &options)
+    };
+    let mut runtime = match runtime {
+        Ok(runtime) => runtime,
+        Err(error) => {
+            let _ = ready.send(Err(error));
+            return None;
+        }
+    };
+    let _ = ready.send(Ok(()));
+    watchdog.attach(runtime.interrupt_handle());
+    Some(runtime)
+}
+
+#[cfg(not(feature = "wasm"))]
+fn spawn_worker(init: InitOptions, watchdog: Arc<Watchdog>, generation: u64) -> Worker {
+    let (tx, rx): (Sender<Job>, Receiver<Job>) = mpsc::channel();
+    let (ready_tx, ready_rx) = mpsc::channel();
+    let mut builder = thread::Builder::new();
+    if let Some(name) = &init.thread_name {
+        builder = builder.name(name.clone());
+    }
+    if let Some(stack_size) = init.stack_size {
+        builder = builder.stack_size(stack_size);
+    }
+    let thread = builder.spawn(move || {
+        let Some(mut runtime) = start_engine(&init, &watchdog, ready_tx) else { return };
+        let mut lanes = Lanes::default();
+        while let Some(job) = lanes.next(&rx, init.idle_timeout) {
+            (job.run)(&mut runtime);
+        }
+    });
+    Worker { sender: tx, generation, thread, ready: Some(ready_rx) }
+}
+
+#[cfg(feature = "wasm")]
+fn spawn_worker(init: InitOptions, watchdog: Arc<Watchdog>, generation: u64) -> Worker {
+    let (tx, rx): (Sender<Job>, Receiver<Job>) = mpsc::channel();
+    let (ready_tx, ready_rx) = mpsc::channel();
+    let local = LocalWorker { runtime: start_engine(&init, &watchdog, ready_tx), jobs: rx, lanes: Lanes::default() };
+    Worker { sender: tx, generation, local: Arc::new(Mutex::new(local)), ready: Some(ready_rx) }
+}
+
+// ワーカーで数える統計。スナップショットの数はプロセス全体のもの(cache.rs)
+#[derive(Default)]
+struct Metrics {
+    renders: AtomicU64,
+    failures: AtomicU64,
+    jobs: AtomicU64,
+    js_nanos: AtomicU64,
+    queued: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    pub renders: u64,
+    // KaTeXが受け付けなかった数式と、エンジンのエラーや時間切れ
+    pub failures: u64,
+    pub jobs: u64,
+    // wasm32では測らないので0
+    pub average_js_time: Duration,
+    pub queue_depth: u64,
+    pub snapshot_hits: u64,
+    pub snapshot_misses: u64,
+}
+
+// 複数のRendererの数を足し合わせる。スナップショットの数はプロセス全体で一つ
+pub(crate) fn pool_stats(renderers: &[Renderer]) -> RenderStats {
+    let sum = |field: fn(&Metrics) -> &AtomicU64| renderers.iter().map(|renderer| field(&renderer.metrics).load(Ordering::Relaxed)).sum();
+    let jobs: u64 = sum(|metrics| &metrics.jobs);
+    let js_nanos: u64 = sum(|metrics| &metrics.js_nanos);
+    let (snapshot_hits, snapshot_misses) = cache::snapshot_stats();
+    RenderStats {
+        renders: sum(|metrics| &metrics.renders),
+        failures: sum(|metrics| &metrics.failures),
+        jobs,
+        average_js_time: Duration::from_nanos(js_nanos.checked_div(jobs).unwrap_or(0)),
+        queue_depth: sum(|metrics| &metrics.queued),
+        snapshot_hits,
+        snapshot_misses,
+    }
+}
+
+// キューにある間だけ生きる。実行が始まるか、送れずに捨てられたときに数を戻す
+struct Queued {
+    metrics: Arc<Metrics>,
+    #[cfg(feature = "tracing")]
+    _span: tracing::Span,
+}
+impl Queued {
+    fn new(metrics: Arc<Metrics>) -> Queued {
+        metrics.queued.fetch_add(1, Ordering::Relaxed);
+        Queued::counted(metrics)
+    }
+    // 待っているジョブがcapacity未満のときだけ積む
+    fn reserve(metrics: Arc<Metrics>, capacity: usize) -> Result<Queued, Error> {
+        metrics
+            .queued
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |queued| (queued < capacity as u64).then_some(queued + 1))
+            .map_err(|_| Error::Busy)?;
+        Ok(Queued::counted(metrics))
+    }
+    fn counted(metrics: Arc<Metrics>) -> Queued {
+        Queued {
+            metrics,
+            #[cfg(feature = "tracing")]
+            _span: tracing::debug_span!("katex.queue_wait"),
+        }
+    }
+}
+impl Drop for Queued {
+    fn drop(&mut self) {
+        self.metrics.queued.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// 描画したHTMLに、返す前にかける関数
+pub(crate) type PostProcessor = Arc<dyn Fn(String) -> String + Send + Sync>;
+// KaTeXに渡す前の数式にかける関数
+pub(crate) type PreProcessor = Arc<dyn Fn(&str) -> Cow<'_, str> + Send + Sync>;
+
+pub struct Renderer {
+    init: InitOptions,
+    worker: Mutex<Option<Worker>>,
+    watchdog: Arc<Watchdog>,
+    metrics: Arc<Metrics>,
+    post_processor: RwLock<Option<PostProcessor>>,
+    pre_processor: RwLock<Option<PreProcessor>>,
+}
+
+impl Renderer {
+    pub fn new(init: InitOptions) -> Renderer {
+        let watchdog = Arc::new(Watchdog::default());
+        let worker = spawn_worker(init.clone(), watchdog.clone(), 0);
+        Renderer {
+            init,
+            worker: Mutex::new(Some(worker)),
+            watchdog,
+            metrics: Arc::default(),
+            post_processor: RwLock::default(),
+            pre_processor: RwLock::default(),
+        }
+    }
+
+    // エンジンの構築まで待ち、失敗したらそのエラーを返す
+    pub fn try_new(init: InitOptions) -> Result<Renderer, Error> {
+        let renderer = Renderer::new(init);
+        renderer.warm_up()?;
+        Ok(renderer)
+    }
+
+    pub fn warm_up(&self) -> Result<(), Error> {
+        // スレッドを作れなかったときは、ここでError::Spawnになる
+        self.worker()?;
+        let ready = self.worker.lock().unwrap().as_mut().ok_or(Error::ShutDown)?.ready.take();
+        match ready {
+            Some(ready) => Ok(ready.recv()??),
+            None => self.call(|_| Ok(())),
+        }
+    }
+
+    // 以降に描画したHTMLを、返す前にこの関数に通す。classを書き換えたり全体を包んだりするのに使う。
+    // Optionsとは別に効き、マクロを書き戻すかどうかは通す前のHTMLで決める。render_partsではHTMLの方にだけかける
+    pub fn set_post_processor(&self, post_processor: impl Fn(String) -> String + Send + Sync + 'static) {
+        self.set_shared_post_processor(Some(Arc::new(post_processor)));
+    }
+    pub fn clear_post_processor(&self) {
+        self.set_shared_post_processor(None);
+    }
+    pub(crate) fn set_shared_post_processor(&self, post_processor: Option<PostProcessor>) {
+        *self.post_processor.write().unwrap() = post_processor;
+    }
+    fn post_process(&self, html: String) -> String {
+        match &*self.post_processor.read().unwrap() {
+            Some(post_processor) => post_processor(html),
+            None => html,
+        }
+    }
+
+    // 以降に渡した数式を、KaTeXに渡す前にこの関数に通す。コメントを消したり古いコマンドを書き換えたりするのに使う。
+    // プリアンブル以外の数式を受け取る関数の全てに効き、エラーや診断の位置は通した後の数式のもの
+    pub fn set_pre_processor(&self, pre_processor: impl Fn(&str) -> Cow<'_, str> + Send + Sync + 'static) {
+        self.set_shared_pre_processor(Some(Arc::new(pre_processor)));
+    }
+    pub fn clear_pre_processor(&self) {
+        self.set_shared_pre_processor(None);
+    }
+    pub(crate) fn set_shared_pre_processor(&self, pre_processor: Option<PreProcessor>) {
+        *self.pre_processor.write().unwrap() = pre_processor;
+    }
+    fn pre_process<'a>(&self, latex: &'a str) -> Cow<'a, str> {
+        match &*self.pre_processor.read().unwrap() {
+            Some(pre_processor) => pre_processor(latex),
+            None => Cow::Borrowed(latex),
+        }
+    }
+
+    pub fn with_katex_source(js: &str) -> Renderer {
+        Renderer::new(InitOptions::default().katex_source(js))
+    }
+
+    pub fn shutdown(&self) {
+        let worker = self.worker.lock().unwrap().take();
+        if let Some(worker) = worker {
+            worker.stop();
+        }
+    }
+
+    fn worker(&self) -> Result<(Sender<Job>, u64), Error> {
+        let mut worker = self.worker.lock().unwrap();
+        let worker = worker.as_mut().ok_or(Error::ShutDown)?;
+        // 暇で止まったワーカーは次に使うときに作り直す。止まった直後に送ったジョブは、死んだワーカーとしてやり直される
+        #[cfg(not(feature = "wasm"))]
+        if self.init.idle_timeout.is_some() && worker.thread.as_ref().is_ok_and(JoinHandle::is_finished) {
+            let dead = std::mem::replace(worker, spawn_worker(self.init.clone(), self.watchdog.clone(), worker.generation + 1));
+            dead.stop();
+        }
+        #[cfg(not(feature = "wasm"))]
+        if worker.thread.is_err() {
+            *worker = spawn_worker(self.init.clone(), self.watchdog.clone(), worker.generation + 1);
+            if let Err(error) = &worker.thread {
+                return Err(Error::Spawn(io::Error::new(error.kind(), error.to_string())));
+            }
+        }
+        Ok((worker.sender.clone(), worker.generation))
+    }
+    // ワーカーが落ちていたら(スナップショットを再利用して)作り直す。
+    // エンジンを作れずに落ちていたときはそのエラーを返し、呼び出し側はやり直さずにそれを返す
+    fn respawn(&self, generation: u64) -> Option<Error> {
+        let mut worker = self.worker.lock().unwrap();
+        let worker = worker.as_mut().filter(|worker| worker.generation == generation)?;
+        let mut dead = std::mem::replace(worker, spawn_worker(self.init.clone(), self.watchdog.clone(), generation + 1));
+        let ready = dead.ready.take();
+        dead.stop();
+        ready?.try_recv().ok()?.err().map(Error::from)
+    }
+    // キューでの待ち時間とワーカーでの実行時間を数える
+    fn job(&self, job: impl FnOnce(&mut JsEngine) + Send + 'static) -> Job {
+        self.job_in(Priority::Interactive, job)
+    }
+    fn job_in(&self, priority: Priority, job: impl FnOnce(&mut JsEngine) + Send + 'static) -> Job {
+        Self::queued_job(Queued::new(self.metrics.clone()), priority, job)
+    }
+    fn queued_job(queued: Queued, priority: Priority, job: impl FnOnce(&mut JsEngine) + Send + 'static) -> Job {
+        let run = Box::new(move |engine: &mut JsEngine| {
+            let metrics = queued.metrics.clone();
+            drop(queued);
+            #[cfg(feature = "tracing")]
+            let _span = tracing::debug_span!("katex.exec").entered();
+            #[cfg(not(feature = "wasm"))]
+            let started = std::time::Instant::now();
+            job(engine);
+            #[cfg(not(feature = "wasm"))]
+            metrics.js_nanos.fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+            metrics.jobs.fetch_add(1, Ordering::Relaxed);
+        });
+        Job { priority, run }
+    }
+    // 描画の結果を数え、マクロを書き戻してから後処理をかける
+    fn finish_html(
+        &self,
+        output: Result<Output, Error>,
+        latex: &str,
+        macros: &mut BTreeMap<String, MacroValue>,
+        options: &Options,
+    ) -> Result<String, Error> {
+        self.record(output.and_then(|output| output.into_html(latex, macros, options)).map(|html| self.post_process(html)))
+    }
+    fn record<T>(&self, result: Result<T, Error>) -> Result<T, Error> {
+        self.metrics.renders.fetch_add(1, Ordering::Relaxed);
+        if result.is_err() {
+            self.metrics.failures.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+
+    pub fn stats(&self) -> RenderStats {
+        pool_stats(std::slice::from_ref(self))
+    }
+
+    pub(crate) fn queue_depth(&self) -> u64 {
+        self.metrics.queued.load(Ordering::Relaxed)
+    }
+
+    // wasm32ではワーカースレッドが無いので、送ったジョブをここで実行する
+    fn pump(&self) {
+        #[cfg(feature = "wasm")]
+        if let Some(local) = self.worker.lock().unwrap().as_ref().map(|worker| worker.local.clone()) {
+            local.lock().unwrap().run_pending();
+        }
+    }
+    fn is_dead_worker(error: &Error) -> bool {
+        matches!(error, Error::SendError | Error::RecvError(_))
+    }
+
+    // ワーカーが死んでいたときは作り直して一度だけやり直す
+    fn call<T: Send + 'static>(&self, f: impl FnOnce(&mut JsEngine) -> Result<T, JSError> + Clone + Send + 'static) -> Result<T, Error> {
+        self.call_in(Priority::Interactive, f)
+    }
+    fn call_in<T: Send + 'static>(
+        &self,
+        priority: Priority,
+        f: impl FnOnce(&mut JsEngine) -> Result<T, JSError> + Clone + Send + 'static,
+    ) -> Result<T, Error> {
+        match self.try_call_queued(Queued::new(self.metrics.clone()), priority, f.clone()) {
+            Err(error) if Self::is_dead_worker(&error) => self.try_call_queued(Queued::new(self.metrics.clone()), priority, f),
+            result => result,
+        }
+    }
+    fn try_call_queued<T: Send + 'static>(
+        &self,
+        queued: Queued,
+        priority: Priority,
+        f: impl FnOnce(&mut JsEngine) -> Result<T, JSError> + Send + 'static,
+    ) -> Result<T, Error> {
+        let (sender, generation) = self.worker()?;
+        let (tx, rx) = mpsc::channel();
+        let result = match sender.send(Self::queued_job(queued, priority, move |engine: &mut JsEngine| {
+            let _ = tx.send(f(engine));
+        })) {
+            Ok(()) => {
+                self.pump();
+                rx.recv().map_err(Error::from).and_then(|result| Ok(result?))
+            }
+            Err(_) => Err(Error::SendError),
+        };
+        if let Err(error) = &result
+            && Self::is_dead_worker(error)
+            && let Some(error) = self.respawn(generation)
+        {
+            return Err(error);
+        }
+        result
+    }
+
+    fn call_timeout<T: Send + 'static>(
+        &self,
+        timeout: Duration,
+        f: impl FnOnce(&mut JsEngine) -> Result<T, JSError> + Clone + Send + 'static,
+    ) -> Result<T, Error> {
+        match self.try_call_timeout(timeout, f.clone()) {
+            Err(error) if Self::is_dead_worker(&error) => self.try_call_timeout(timeout, f),
+            result => result,
+        }
+    }
+    // 時間内に終わらなければ実行中のスクリプトを打ち切り、まだ始まっていなければ実行させない
+    fn try_call_timeout<T: Send + 'static>(
+        &self,
+        timeout: Duration,
+        f: impl FnOnce(&mut JsEngine) -> Result<T, JSError> + Send + 'static,
+    ) -> Result<T, Error> {
+        let (sender, generation) = self.worker()?;
+        let (tx, rx) = mpsc::channel();
+        let watchdog = self.watchdog.clone();
+        let result = self
+            .watchdog
+            .send(&sender, move |id| {
+                self.job(move |engine: &mut JsEngine| {
+                    if watchdog.start(id) {
+                        let result = f(engine);
+                        watchdog.finish(engine);
+                        let _ = tx.send(result);
+                    }
+                })
+            })
+            .inspect(|_| self.pump())
+            .and_then(|id| match rx.recv_timeout(timeout) {
+                Ok(result) => Ok(result?),
+                Err(RecvTimeoutError::Timeout) => {
+                    self.watchdog.cancel(id);
+                    Err(Error::Timeout(timeout))
+                }
+                Err(RecvTimeoutError::Disconnected) => Err(mpsc::RecvError.into()),
+            });
+        if let Err(error) = &result
+            && Self::is_dead_worker(error)
+            && let Some(error) = self.respawn(generation)
+        {
+            return Err(error);
+        }
+        result
+    }
+
+    #[cfg(feature = "tokio")]
+    async fn call_async<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut JsEngine) -> Result<T, JSError> + Clone + Send + 'static,
+    ) -> Result<T, Error> {
+        match self.try_call_async(f.clone()).await {
+            Err(error) if Self::is_dead_worker(&error) => self.try_call_async(f).await,
+            result => result,
+        }
+    }
+    #[cfg(feature = "tokio")]
+    async fn try_call_async<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut JsEngine) -> Result<T, JSError> + Send + 'static,
+    ) -> Result<T, Error> {
+        let (sender, generation) = self.worker()?;
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let result = match sender.send(self.job(move |engine: &mut JsEngine| {
+            let _ = tx.send(f(engine));
+        })) {
+            Ok(()) => {
+                self.pump();
+                rx.await.map_err(|_| Error::from(mpsc::RecvError)).and_then(|result| Ok(result?))
+            }
+            Err(_) => Err(Error::SendError),
+        };
+        if let Err(error) = &result
+            && Self::is_dead_worker(error)
+            && let Some(error) = self.respawn(generation)
+        {
+            return Err(error);
+        }
+        result
+    }
+
+    pub fn render(&self, latex: &str) -> Result<String, Error> {
+        self.render_with_opts(latex, &Default::default(), &mut BTreeMap::new())
+    }
+
+    pub fn render_with_opts(&self, latex: &str, options: &Options, macros: &mut BTreeMap<String, MacroValue>) -> Result<String, Error> {
+        let latex: &str = &self.pre_process(latex);
+        let exec = exec("renderToStringAndMacros", &Input { latex, options, macros })?;
+        let output: Result<Output, Error> = self.call(exec);
+        self.finish_html(output, latex, macros, options)
+    }
+
+    // macrosから描画し、数式で定義したマクロはどこにも書き戻さない。render_batch_parallelが数式ごとに表を複製せずに済むよう、借りたまま渡す
+    #[cfg(feature = "rayon")]
+    pub(crate) fn render_independent(
+        &self,
+        latex: &str,
+        options: &Options,
+        macros: &BTreeMap<String, MacroValue>,
+    ) -> Result<String, Error> {
+        let latex: &str = &self.pre_process(latex);
+        let exec = exec("renderToStringAndMacros", &Input { latex, options, macros })?;
+        let output: Result<Output, Error> = self.call(exec);
+        self.record(output.and_then(|output| output.into_result(latex)).map(|rendered| self.post_process(rendered.html)))
+    }
+
+    // scopeの親の層も含めたマクロで描画し、数式で定義されたマクロはscope自身の層にだけ入れる
+    pub fn render_scoped(&self, latex: &str, options: &Options, scope: &mut MacroScope) -> Result<String, Error> {
+        let before = scope.flatten();
+        let mut macros = before.clone();
+        let html = self.render_with_opts(latex, options, &mut macros);
+        scope.absorb(&before, macros);
+        html
+    }
+
+    // 既にあったマクロを別の本体で定義し直したときは、それをconflictsに足す。
+    // MacroCommit::Alwaysでは失敗した描画も定義を書き戻すので、エラーを返す前に調べる
+    pub fn render_with_conflicts(
+        &self,
+        latex: &str,
+        options: &Options,
+        macros: &mut BTreeMap<String, MacroValue>,
+        conflicts: &mut Vec<MacroConflict>,
+    ) -> Result<String, Error> {
+        let before = macros.clone();
+        let html = self.render_with_opts(latex, options, macros);
+        conflicts.extend(MacroConflict::detect(&before, macros, latex));
+        html
+    }
+
+    // 描画したHTMLをそのまま書き出す。書き込みに失敗したときはError::Io
+    pub fn render_to_writer(
+        &self,
+        latex: &str,
+        options: &Options,
+        macros: &mut BTreeMap<String, MacroValue>,
+        writer: &mut impl io::Write,
+    ) -> Result<(), Error> {
+        let html = self.render_with_opts(latex, options, macros)?;
+        Ok(writer.write_all(html.as_bytes())?)
+    }
+
+    // ワーカーが混んでいれば待たずにError::Busyを返す。上限はInitOptions::queue_capacity
+    pub fn try_render(&self, latex: &str, options: &Options, macros: &mut BTreeMap<String, MacroValue>) -> Result<String, Error> {
+        let Some(capacity) = self.init.queue_capacity else {
+            return self.render_with_opts(latex, options, macros);
+        };
+        let latex: &str = &self.pre_process(latex);
+        let exec = exec("renderToStringAndMacros", &Input { latex, options, macros })?;
+        let output: Result<Output, Error> =
+            match self.try_call_queued(Queued::reserve(self.metrics.clone(), capacity)?, Priority::Interactive, exec.clone()) {
+                Err(error) if Self::is_dead_worker(&error) => {
+                    self.try_call_queued(Queued::reserve(self.metrics.clone(), capacity)?, Priority::Interactive, exec)
+                }
+                result => result,
+            };
+        self.finish_html(output, latex, macros, options)
+    }
+
+    pub fn render_with_warnings(
+        &self,
+        latex: &str,
+        options: &Options,
+        macros: &mut BTreeMap<String, MacroValue>,
+    ) -> Result<Rendered, Error> {
+        let latex: &str = &self.pre_process(latex);
+        let exec = exec("renderToStringAndMacros", &Input { latex, options, macros })?;
+        let output: Result<Output, Error> = self.call(exec);
+        self.record(
+            output
+                .and_then(|output| output.into_rendered(latex, macros, options))
+                .map(|rendered| Rendered { html: self.post_process(rendered.html), ..rendered }),
+        )
+    }
+
+    pub fn render_timeout(
+        &self,
+        latex: &str,
+        options: &Options,
+        macros: &mut BTreeMap<String, MacroValue>,
+        timeout: Duration,
+    ) -> Result<String, Error> {
+        let latex: &str = &self.pre_process(latex);
+        let exec = exec("renderToStringAndMacros", &Input { latex, options, macros })?;
+        let output: Result<Output, Error> = self.call_timeout(timeout, exec);
+        self.finish_html(output, latex, macros, options)
+    }
+
+    #[cfg(feature = "tokio")]
+    pub async fn render_async(&self, latex: &str) -> Result<String, Error> {
+        self.render_with_opts_async(latex, &Default::default(), &mut BTreeMap::new()).await
+    }
+
+    #[cfg(feature = "tokio")]
+    pub async fn render_with_opts_async(
+        &self,
+        latex: &str,
+        options: &Options,
+        macros: &mut BTreeMap<String, MacroValue>,
+    ) -> Result<String, Error> {
+        let latex: &str = &self.pre_process(latex);
+        let exec = exec("renderToStringAndMacros", &Input { latex, options, macros })?;
+        let output: Result<Output, Error> = self.call_async(exec).await;
+        self.finish_html(output, latex, macros, options)
+    }
+
+    // 一つのジョブで数式を順に描画し、数式ごとにmacro_commitとmacro_filterで書き戻した表を次の数式に渡す。
+    // 書き戻さなかった定義は後の数式にも残らない。ジョブ自体が失敗したときは外側のErrを返し、macrosには何も書き戻さない
+    pub fn render_batch(
+        &self,
+        items: &[(&str, Options)],
+        macros: &mut BTreeMap<String, MacroValue>,
+    ) -> Result<Vec<Result<String, Error>>, Error> {
+        let items: Arc<Vec<(String, Options)>> =
+            Arc::new(items.iter().map(|(latex, options)| (self.pre_process(latex).into_owned(), options.clone())).collect());
+        let batch = items.clone();
+        let initial = Arc::new(macros.clone());
+        let render = move |engine: &mut JsEngine| -> Result<Vec<Output>, JSError> {
+            let mut macros = (*initial).clone();
+            batch
+                .iter()
+                .map(|(latex, options)| {
+                    let input = serde_json::to_string(&Input { latex, options, macros: &macros })?;
+                    let output: Output = engine.exec("renderToStringAndMacros", &input)?;
+                    output.commit(&mut macros, options);
+                    Ok(output)
+                })
+                .collect()
+        };
+        let outputs = match self.call_in(Priority::Batch, render) {
+            Ok(outputs) => outputs,
+            Err(error) => {
+                self.metrics.renders.fetch_add(items.len() as u64, Ordering::Relaxed);
+                self.metrics.failures.fetch_add(items.len() as u64, Ordering::Relaxed);
+                return Err(error);
+            }
+        };
+        Ok(outputs
+            .into_iter()
+            .zip(items.iter())
+            .map(|(output, (latex, options))| self.finish_html(Ok(output), latex, macros, options))
+            .collect())
+    }
+
+    // 次の数式をワーカーに渡しておき、呼び出し側が前の結果を使っている間に描画させる。
+    // マクロは数式の順に持ち越す
+    pub fn render_iter<I: IntoIterator<Item = (String, Options)>>(&self, items: I) -> RenderIter<'_, I::IntoIter> {
+        RenderIter {
+            renderer: self,
+            items: items.into_iter(),
+            macros: Arc::default(),
+            pending: VecDeque::new(),
+            depth: 4,
+            priority: Priority::Batch,
+        }
+    }
+
+    // 数式をワーカーに渡してすぐに戻る。結果はRenderHandle::waitかtry_getで受け取る
+    pub fn render_spawn(&self, latex: &str, options: &Options, macros: BTreeMap<String, MacroValue>) -> RenderHandle<'_> {
+        let latex: &str = &self.pre_process(latex);
+        let pending = exec("renderToStringAndMacros", &Input { latex, options, macros: &macros }).and_then(|exec| {
+            let (sender, generation) = self.worker()?;
+            let (tx, rx) = mpsc::channel();
+            sender
+                .send(self.job(move |engine: &mut JsEngine| {
+                    let _ = tx.send(exec(engine));
+                }))
+                .map_err(|_| Error::SendError)?;
+            self.pump();
+            Ok((rx, generation))
+        });
+        RenderHandle { renderer: self, latex: latex.to_string(), macros, options: options.clone(), pending: Some(pending) }
+    }
+
+    // 文書中の数式をまとめて一度のバッチで描画し、元の位置に差し戻す。地の文は書き換えない
+    pub fn render_document(
+        &self,
+        text: &str,
+        delimiters: &[Delimiter],
+        options: &Options,
+        macros: &mut BTreeMap<String, MacroValue>,
+    ) -> Result<String, Error> {
+        let segments = split_at_delimiters(text, delimiters);
+        let items: Vec<(&str, Options)> = segments
+            .iter()
+            .filter_map(|segment| match *segment {
+                Segment::Math { latex, display, .. } => Some((latex, Options { display_mode: display, ..options.clone() })),
+                Segment::Text(_) => None,
+            })
+            .collect();
+        let mut rendered = self.render_batch(&items, macros)?.into_iter();
+        let mut document = String::with_capacity(text.len());
+        for segment in segments {
+            match segment {
+                Segment::Text(text) => document.push_str(text),
+                Segment::Math { .. } => document.push_str(&rendered.next().unwrap()?),
+            }
+        }
+        Ok(document)
+    }
+
+    // HTMLとMathMLを両方作ってから、別々の文字列として取り出す
+    pub fn render_parts(&self, latex: &str, options: &Options, macros: &mut BTreeMap<String, MacroValue>) -> Result<RenderedParts, Error> {
+        let latex: &str = &self.pre_process(latex);
+        let options = Options { output: KatexOutput::HtmlAndMathml, ..options.clone() };
+        let input = Input { latex, options: &options, macros };
+        self.record(exec("renderPartsAndMacros", &input).and_then(|exec| self.call(exec)).and_then(|output| match output {
+            PartsOutput::Success { parts, failed, macros: macros_value } => {
+                commit_macros(macros, &macros_value, !failed, &options);
+                Ok(RenderedParts { html: self.post_process(parts.html), ..parts })
+            }
+            PartsOutput::Error { error, macros: macros_value } => {
+                commit_macros(macros, &macros_value, false, &options);
+                Err(Error::KaTeXError { message: error, latex: latex.to_string(), macros: macros_value })
+            }
+        }))
+    }
+
+    // 描画と同じマクロで構文木を作り、そこから読み上げ文を作る
+    #[cfg(feature = "speech")]
+    pub fn render_with_speech(
+        &self,
+        latex: &str,
+        options: &Options,
+        macros: &mut BTreeMap<String, MacroValue>,
+    ) -> Result<crate::SpokenRendered, Error> {
+        let latex: &str = &self.pre_process(latex);
+        let input = Input { latex, options, macros };
+        self.record(exec("renderToStringAndTree", &input).and_then(|exec| self.call(exec)).and_then(|output| match output {
+            crate::SpeechOutput::Success { html, tree, failed, macros: macros_value } => {
+                commit_macros(macros, &macros_value, !failed, options);
+                Ok(crate::SpokenRendered { html: self.post_process(html), speech: crate::speech_text(&tree) })
+            }
+            crate::SpeechOutput::Error { error, macros: macros_value } => {
+                commit_macros(macros, &macros_value, false, options);
+                Err(Error::KaTeXError { message: error, latex: latex.to_string(), macros: macros_value })
+            }
+        }))
+    }
+
+    // MathMLだけを描画して一行の文字列に崩す。HTMLに向けたminify・色の書き換え・helpersとpost_processorは通さない
+    pub fn to_text(&self, latex: &str, options: &Options, macros: &mut BTreeMap<String, MacroValue>) -> Result<String, Error> {
+        let latex: &str = &self.pre_process(latex);
+        let options = Options {
+            output: KatexOutput::Mathml,
+            minify: false,
+            dark_colors: None,
+            css_variables: false,
+            helpers: Vec::new(),
+            ..options.clone()
+        };
+        let exec = exec("renderToStringAndMacros", &Input { latex, options: &options, macros })?;
+        let output: Result<Output, Error> = self.call(exec);
+        Ok(mathml_to_text(&self.record(output.and_then(|output| output.into_html(latex, macros, &options)))?))
+    }
+
+    // katex_distはKaTeXの配布物のディレクトリ(katex.min.cssとfonts/を含む)
+    #[cfg(feature = "standalone")]
+    pub fn render_standalone(&self, latex: &str, options: &Options, katex_dist: impl AsRef<Path>) -> Result<String, Error> {
+        let html = self.render_with_opts(latex, options, &mut BTreeMap::new())?;
+        let katex_dist = katex_dist.as_ref();
+        let katex_css = std::fs::read_to_string(katex_dist.join("katex.min.css"))?;
+        Ok(standalone_html(&html, &katex_css, &katex_dist.join("fonts")))
+    }
+
+    // PDFなどに数式を置くための大きさ。幅はKaTeXが持たないので、グリフの幅と空白から見積もる
+    pub fn measure(&self, latex: &str, options: &Options) -> Result<Dimensions, Error> {
+        let latex: &str = &self.pre_process(latex);
+        let input = Input { latex, options, macros: &BTreeMap::new() };
+        self.record(exec("measureAndMacros", &input).and_then(|exec| self.call(exec)).and_then(|output| match output {
+            MeasureOutput::Success { dimensions } => Ok(dimensions),
+            MeasureOutput::Error { error, macros } => Err(Error::KaTeXError { message: error, latex: latex.to_string(), macros }),
+        }))
+    }
+
+    // KaTeXのCSSの代わりにHTMLの木を並べて描いたSVG。文字はKaTeXのフォントの名前で書くので、表示する側にKaTeXのフォントが要る
+    #[cfg(feature = "png")]
+    pub fn render_svg(&self, latex: &str, options: &Options) -> Result<String, Error> {
+        let latex: &str = &self.pre_process(latex);
+        let input = Input { latex, options, macros: &BTreeMap::new() };
+        self.record(exec("renderToSvgAndMacros", &input).and_then(|exec| self.call(exec)).and_then(|output| match output {
+            SvgOutput::Success { svg } => Ok(svg),
+            SvgOutput::Error { error, macros } => Err(Error::KaTeXError { message: error, latex: latex.to_string(), macros }),
+        }))
+    }
+
+    // render_svgのSVGを、katex_distのfonts/にあるKaTeXのフォントでdpiに合わせたPNGにする
+    #[cfg(feature = "png")]
+    pub fn render_png(&self, latex: &str, options: &Options, katex_dist: impl AsRef<Path>, dpi: f32) -> Result<Vec<u8>, Error> {
+        let svg = self.render_svg(latex, options)?;
+        png::rasterize(&svg, dpi, png::katex_fonts(&katex_dist.as_ref().join("fonts")))
+    }
+
+    // KaTeXの構文木(ノードの配列)。KaTeXの内部の形なので、KaTeXのバージョンによって変わりうる
+    pub fn parse_tree(&self, latex: &str, options: &Options) -> Result<serde_json::Value, Error> {
+        let latex: &str = &self.pre_process(latex);
+        let input = Input { latex, options, macros: &BTreeMap::new() };
+        match self.call(exec("parseTree", &input)?)? {
+            TreeOutput::Success { tree } => Ok(tree),
+            TreeOutput::Error { error, macros } => Err(Error::KaTeXError { message: error, latex: latex.to_string(), macros }),
+        }
+    }
+
+    // リンターやCI向けに、HTMLを作らずにパースできるかだけを調べる
+    pub fn check(&self, latex: &str, options: &Options, macros: &BTreeMap<String, MacroValue>) -> Result<(), ParseDiagnostics> {
+        let latex: &str = &self.pre_process(latex);
+        let input = Input { latex, options, macros };
+        let output: CheckOutput = self.call(exec("checkParse", &input)?)?;
+        output.diagnostics.map_or(Ok(()), |diagnostics| Err(diagnostics.locate(latex)))
+    }
+
+    // エディタで下線を引くための診断。パースのエラーに加え、strictでignoreにしていない非標準の書き方も位置付きで返す。
+    // エンジンが動かなかったときは、数式全体に掛かるエラーを一つ返す
+    pub fn diagnostics(&self, latex: &str, options: &Options, macros: &BTreeMap<String, MacroValue>) -> Vec<Diagnostic> {
+        let latex: &str = &self.pre_process(latex);
+        let input = Input { latex, options, macros };
+        match exec("diagnose", &input).and_then(|exec| self.call::<DiagnoseOutput>(exec)) {
+            Ok(output) => output.diagnostics.into_iter().map(|diagnostic| diagnostic.locate(latex)).collect(),
+            Err(error) => vec![Diagnostic { severity: Severity::Error, range: 0..latex.len(), message: error.to_string(), code: None }],
+        }
+    }
+
+    // InitOptions::helperで登録した関数をargsで呼び、戻り値を返す。戻り値がundefinedならnull
+    pub fn eval_helper(&self, name: &str, args: &serde_json::Value) -> Result<serde_json::Value, Error> {
+        let output: HelperOutput = self.call(exec("evalHelper", &HelperInput { name, args })?)?;
+        if output.found { Ok(output.result) } else { Err(Error::UnknownHelper(name.to_string())) }
+    }
+
+    // マクロを展開しただけのTeX。描画はしない
+    pub fn expand(&self, latex: &str, macros: &BTreeMap<String, MacroValue>) -> Result<String, Error> {
+        let latex: &str = &self.pre_process(latex);
+        let input = Input { latex, options: &Options::default(), macros };
+        match self.call(exec("expandMacros", &input)?)? {
+            ExpandOutput::Success { expanded } => Ok(expanded),
+            ExpandOutput::Error { error, macros } => Err(Error::KaTeXError { message: error, latex: latex.to_string(), macros }),
+        }
+    }
+
+    // 文書の数式を一度のバッチでパースだけして、\gdefなどで定義されたマクロを集める。エラーになった数式は飛ばす。
+    // 集めたマクロを固定して、二度目は数式を並べて(別々のワーカーでも)描画できる
+    pub fn collect_macros<'a>(
+        &self,
+        fragments: impl IntoIterator<Item = &'a str>,
+        options: &Options,
+    ) -> Result<BTreeMap<String, MacroValue>, Error> {
+        let latexes: Vec<Cow<str>> = fragments.into_iter().map(|latex| self.pre_process(latex)).collect();
+        let items = latexes.iter().map(|latex| BatchItem { latex, options }).collect();
+        let output: CollectOutput =
+            self.call_in(Priority::Batch, exec("collectMacros", &BatchInput { items, macros: &BTreeMap::new() })?)?;
+        Ok(output.macros)
+    }
+
+    // macrosのうち、数式をパースしたときに展開されたものの名前。マクロの本体から使われたものも入る。
+    // どのページでどの定義が要るかを調べたり、どこからも使われないマクロを見つけたりするのに使う
+    pub fn macros_used(&self, latex: &str, macros: &BTreeMap<String, MacroValue>) -> Result<BTreeSet<String>, Error> {
+        let latex: &str = &self.pre_process(latex);
+        let input = Input { latex, options: &Options::default(), macros };
+        match self.call(exec("macrosUsed", &input)?)? {
+            UsedOutput::Success { used } => Ok(used),
+            UsedOutput::Error { error, macros } => Err(Error::KaTeXError { message: error, latex: latex.to_string(), macros }),
+        }
+    }
+
+    // プリアンブルはHTMLを作らずにパースだけして、定義されたマクロを拾う
+    pub fn macros_from_preamble(&self, preamble: &str, macros: &mut BTreeMap<String, MacroValue>) -> Result<(), Error> {
+        let options = Options { global_group: true, ..Default::default() };
+        let input = Input { latex: preamble, options: &options, macros };
+        match self.call(exec("parseMacros", &input)?)? {
+            MacrosOutput::Success { macros: macros_value } => {
+                commit_macros(macros, &macros_value, true, &options);
+                Ok(())
+            }
+            MacrosOutput::Error { error, macros: macros_value } => {
+                commit_macros(macros, &macros_value, false, &options);
+                Err(Error::KaTeXError { message: error, latex: preamble.to_string(), macros: macros_value })
+            }
+        }
+    }
+}
+
+impl Drop for Renderer {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+// オプションやマクロを複製してワーカーに送る代わりに、呼び出し側でJSONにして文字列だけを渡す。
+// 再試行で複製されても文字列は共有する
+fn exec<O: DeserializeOwned + Send + 'static>(
+    function: &'static str,
+    input: &impl Serialize,
+) -> Result<impl FnOnce(&mut JsEngine) -> Result<O, JSError> + Clone + Send + 'static, Error> {
+    let input = {
+        let _span = span!("katex.serialize");
+        Arc::new(serde_json::to_string(input)?)
+    };
+    Ok(move |engine: &mut JsEngine| engine.exec(function, &input))
+}
+
+// 結果の受け口と、送った先のワーカーの世代
+type Pending = (Receiver<Result<Output, JSError>>, u64);
+
+pub struct RenderHandle<'r> {
+    renderer: &'r Renderer,
+    latex: String,
+    // 描画が成功したら、その数式で定義されたマクロに置き換える
+    macros: BTreeMap<String, MacroValue>,
+    options: Options,
+    // 結果を受け取ったらNone
+    pending: Option<Result<Pending, Error>>,
+}
+
+impl RenderHandle<'_> {
+    // 描画が終わるまで待つ。try_getで受け取った後はError::RecvError
+    pub fn wait(mut self) -> Result<String, Error> {
+        match self.pending.take() {
+            Some(Ok((rx, generation))) => self.finish(rx.recv().map_err(Error::from).and_then(|output| Ok(output?)), generation),
+            Some(Err(error)) => self.renderer.record(Err(error)),
+            None => Err(mpsc::RecvError.into()),
+        }
+    }
+    // 終わっていなければNone。結果は一度だけ返し、その後もNoneを返す
+    pub fn try_get(&mut self) -> Option<Result<String, Error>> {
+        let (output, generation) = match self.pending.take()? {
+            Ok((rx, generation)) => match rx.try_recv() {
+                Err(mpsc::TryRecvError::Empty) => {
+                    self.pending = Some(Ok((rx, generation)));
+                    return None;
+                }
+                Err(mpsc::TryRecvError::Disconnected) => (Err(mpsc::RecvError.into()), generation),
+                Ok(output) => (output.map_err(Error::from), generation),
+            },
+            Err(error) => return Some(self.renderer.record(Err(error))),
+        };
+        Some(self.finish(output, generation))
+    }
+    // 描画が終わっていれば、それまでのマクロにその数式の定義を足したもの。終わるまでは渡したマクロのまま
+    pub fn defined_macros(&self) -> &BTreeMap<String, MacroValue> {
+        &self.macros
+    }
+    fn finish(&mut self, output: Result<Output, Error>, generation: u64) -> Result<String, Error> {
+        if let Err(error) = &output
+            && Renderer::is_dead_worker(error)
+            && let Some(error) = self.renderer.respawn(generation)
+        {
+            return self.renderer.record(Err(error));
+        }
+        self.renderer.finish_html(output, &self.latex, &mut self.macros, &self.options)
+    }
+}
+
+pub struct RenderIter<'r, I> {
+    renderer: &'r Renderer,
+    items: I,
+    // ワーカーで順に更新する
+    macros: Arc<Mutex<BTreeMap<String, MacroValue>>>,
+    pending: VecDeque<(String, Result<Pending, Error>)>,
+    depth: usize,
+    priority: Priority,
+}
+
+impl<I: Iterator<Item = (String, Options)>> RenderIter<'_, I> {
+    // 始める前に与えるマクロ
+    pub fn macros(self, macros: BTreeMap<String, MacroValue>) -> Self {
+        *self.macros.lock().unwrap() = macros;
+        self
+    }
+    // 先にワーカーに渡しておく数式の数。1なら一つずつ描画する
+    pub fn depth(mut self, depth: usize) -> Self {
+        self.depth = depth.max(1);
+        self
+    }
+    // プレビューなど、待たせたくない数式の列ならInteractiveにする
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+    // ここまでに描画した数式で定義されたマクロ
+    pub fn defined_macros(&self) -> BTreeMap<String, MacroValue> {
+        self.macros.lock().unwrap().clone()
+    }
+
+    fn send(&mut self, latex: &str, options: Options) -> Result<Pending, Error> {
+        let (sender, generation) = self.renderer.worker()?;
+        let (tx, rx) = mpsc::channel();
+        let macros = self.macros.clone();
+        let latex_value = latex.to_string();
+        sender
+            .send(self.renderer.job_in(self.priority, move |engine: &mut JsEngine| {
+                let mut macros = macros.lock().unwrap();
+                let output = serde_json::to_string(&Input { latex: &latex_value, options: &options, macros: &macros })
+                    .map_err(JSError::from)
+                    .and_then(|input| engine.exec::<Output>("renderToStringAndMacros", &input));
+                if let Ok(output) = &output {
+                    output.commit(&mut macros, &options);
+                }
+                let _ = tx.send(output);
+            }))
+            .map_err(|_| Error::SendError)?;
+        self.renderer.pump();
+        Ok((rx, generation))
+    }
+}
+
+impl<I: Iterator<Item = (String, Options)>> Iterator for RenderIter<'_, I> {
+    type Item = Result<String, Error>;
+
+    fn next(&mut self) -> Option<Result<String, Error>> {
+        while self.pending.len() < self.depth {
+            let Some((latex, options)) = self.items.next() else { break };
+            let latex = self.renderer.pre_process(&latex).into_owned();
+            let sent = self.send(&latex, options);
+            self.pending.push_back((latex, sent));
+        }
+        let (latex, sent) = self.pending.pop_front()?;
+        let result = sent.and_then(|(rx, generation)| {
+            let result = rx.recv().map_err(Error::from).and_then(|output| Ok(output?));
+            if let Err(error) = &result
+                && Renderer::is_dead_worker(error)
+                && let Some(error) = self.renderer.respawn(generation)
+            {
+                return Err(error);
+            }
+            result
+        });
+        Some(self.renderer.finish_html(
+            result,
+            &latex,
+            &mut BTreeMap::new(),
+            &Options { macro_commit: MacroCommit::Never, ..Default::default() },
+        ))
+    }
+}
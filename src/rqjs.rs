@@ -0,0 +1,82 @@
+use std::{
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use rquickjs::{CatchResultExt, Context, Runtime};
+use serde::de::DeserializeOwned;
+
+use crate::{Core, EngineOptions, Interrupt};
+
+pub type Error = RQuickJsError;
+
+// 投げられた例外はSendではないので、メッセージにしてから返す
+#[derive(Debug, thiserror::Error)]
+pub enum RQuickJsError {
+    #[error("JavaScript Error: {0}")]
+    Js(String),
+    #[error("QuickJS Error: {0}")]
+    QuickJs(#[from] rquickjs::Error),
+    #[error("IO Error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("JSON Error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+// rquickjs(quickjs-ng)で動かす。ランタイムはコンテキストより後に破棄されるよう一緒に持つ
+pub(crate) struct Engine {
+    ctx: Context,
+    _runtime: Runtime,
+    interrupted: Arc<AtomicBool>,
+}
+
+impl Engine {
+    fn eval<T: for<'js> rquickjs::FromJs<'js>>(&self, script: String) -> Result<T, RQuickJsError> {
+        self.ctx.with(|ctx| ctx.eval(script).catch(&ctx).map_err(|error| RQuickJsError::Js(error.to_string())))
+    }
+}
+
+impl Core for Engine {
+    type Error = RQuickJsError;
+
+    fn new(code: &str, _options: &EngineOptions) -> Result<Self, Self::Error> {
+        let runtime = Runtime::new()?;
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let flag = interrupted.clone();
+        runtime.set_interrupt_handler(Some(Box::new(move || flag.load(Ordering::SeqCst))));
+        let engine = Engine { ctx: Context::full(&runtime)?, _runtime: runtime, interrupted };
+        engine.eval::<()>(code.to_string())?;
+        Ok(engine)
+    }
+
+    // rquickjsはモジュールのバイトコードしか書き出せず、KaTeXはスクリプトとして読むので毎回評価する
+    fn new_with_snapshot(code: &str, _path: &Path, _header: &str, options: &EngineOptions) -> Result<Self, Self::Error> {
+        Engine::new(code, options)
+    }
+
+    fn engine_id(_options: &EngineOptions) -> String {
+        "rquickjs".to_string()
+    }
+
+    fn exec<O: DeserializeOwned>(&mut self, function: &str, input: &str) -> Result<O, Self::Error> {
+        let script = format!("{function}({input})");
+        let result: String = {
+            let _span = span!("katex.js", function);
+            self.eval(script)?
+        };
+        let _span = span!("katex.deserialize");
+        Ok(serde_json::from_str(&result)?)
+    }
+
+    fn interrupt_handle(&mut self) -> Interrupt {
+        let interrupted = self.interrupted.clone();
+        Box::new(move || interrupted.store(true, Ordering::SeqCst))
+    }
+
+    fn clear_interrupt(&mut self) {
+        self.interrupted.store(false, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,276 @@
+use std::path::Path;
+
+use once_cell::sync::OnceCell;
+use serde::de::DeserializeOwned;
+use wasmtime::{Caller, Config, Extern, InstancePre, Linker, Memory, Module, ResourceLimiter, Store, TypedFunc, Val, format_err};
+
+use crate::{Core, EngineOptions, Interrupt, cache};
+
+pub type Error = SandboxError;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SandboxError {
+    #[error("JavaScript Error: {0}")]
+    Js(String),
+    #[error("WebAssembly Error: {0}")]
+    Wasm(String),
+    // InitOptions::max_heap_mbより線形メモリを広げようとした
+    #[error("Sandbox ran out of memory")]
+    OutOfMemory,
+    #[error("IO Error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("JSON Error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+// wasmtime::Errorはstd::error::Errorではないので、メッセージにしてから返す
+impl From<wasmtime::Error> for SandboxError {
+    fn from(error: wasmtime::Error) -> Self {
+        SandboxError::Wasm(format!("{error:#}"))
+    }
+}
+
+// build.rsがsandbox-guestをwasm32-wasip1向けにビルドしたもの(またはKATEX_GDEF_SANDBOX_WASMで渡したもの)
+static GUEST_WASM: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/katex-gdef-sandbox.wasm"));
+// ゲストのコンパイルには時間がかかるので、プロセスで一度だけコンパイルし、直列化したものをワーカーごとのwasmtimeのエンジンで読む
+static COMPILED: OnceCell<Vec<u8>> = OnceCell::new();
+
+const PAGE_SIZE: usize = 65536;
+// WASIのエラー番号(ENOSYS)。ファイルやソケットなど、ゲストに渡さない機能はこれを返す
+const ERRNO_NOSYS: i32 = 52;
+
+// QuickJS(またはBoa)をWebAssemblyにしたゲストをwasmtimeの中で動かす。ゲストからはファイルもネットワークも環境変数も見えず、
+// 触れるのは自分の線形メモリだけ。打ち切りはエポックで、メモリの上限はResourceLimiterで掛ける
+pub(crate) struct Engine {
+    engine: wasmtime::Engine,
+    instance_pre: InstancePre<Limits>,
+    guest: Guest,
+    // KaTeXを評価し終えたときの線形メモリ。トラップしたゲストはここから作り直す
+    image: Vec<u8>,
+    max_bytes: Option<usize>,
+}
+
+impl Engine {
+    // KaTeXを読み込む前のゲストを用意する
+    fn start(options: &EngineOptions) -> Result<Self, SandboxError> {
+        let mut config = Config::new();
+        config.epoch_interruption(true);
+        let engine = wasmtime::Engine::new(&config)?;
+        let compiled = COMPILED.get_or_try_init(|| Module::new(&engine, GUEST_WASM)?.serialize())?;
+        // 同じ設定のエンジンでこのプロセスがコンパイルしたものなので、そのまま読んでよい
+        let module = unsafe { Module::deserialize(&engine, compiled)? };
+        let instance_pre = wasi_stubs(&engine, &module)?.instantiate_pre(&module)?;
+        let max_bytes = options.max_heap_mb.map(|mb| mb * 1024 * 1024);
+        let guest = Guest::new(&engine, &instance_pre, max_bytes)?;
+        Ok(Engine { engine, instance_pre, guest, image: Vec::new(), max_bytes })
+    }
+
+    fn load(&mut self, code: &str) -> Result<(), SandboxError> {
+        self.guest.eval(code)?.map_err(SandboxError::Js)?;
+        self.image = self.guest.memory.data(&self.guest.store).to_vec();
+        Ok(())
+    }
+
+    // 新しいインスタンスの線形メモリをimageで置き換える。呼び出しの合間に採ったimageなので、スタックポインタは初期値のままでよい
+    fn restore(&mut self) -> Result<(), SandboxError> {
+        let mut guest = Guest::new(&self.engine, &self.instance_pre, self.max_bytes)?;
+        let size = guest.memory.data_size(&guest.store);
+        if self.image.len() > size {
+            guest.memory.grow(&mut guest.store, ((self.image.len() - size) / PAGE_SIZE) as u64)?;
+        }
+        guest.memory.data_mut(&mut guest.store)[..self.image.len()].copy_from_slice(&self.image);
+        self.guest = guest;
+        Ok(())
+    }
+}
+
+impl Core for Engine {
+    type Error = SandboxError;
+
+    fn new(code: &str, options: &EngineOptions) -> Result<Self, Self::Error> {
+        let mut engine = Engine::start(options)?;
+        engine.load(code)?;
+        Ok(engine)
+    }
+
+    // KaTeXを評価した後の線形メモリをスナップショットとして保存する。中身はゲストのデータだけで、
+    // 書き換えられたファイルを読んでもサンドボックスの外には出られない
+    fn new_with_snapshot(code: &str, path: &Path, header: &str, options: &EngineOptions) -> Result<Self, Self::Error> {
+        let mut engine = Engine::start(options)?;
+        let mut created = false;
+        let image = cache::read_or_create(path, header, || -> Result<_, SandboxError> {
+            created = true;
+            engine.load(code)?;
+            Ok(engine.image.clone())
+        })?;
+        if !created {
+            engine.image = image;
+            engine.restore()?;
+        }
+        Ok(engine)
+    }
+
+    fn engine_id(_options: &EngineOptions) -> String {
+        format!("sandbox {}", env!("KATEX_GDEF_SANDBOX_GUEST"))
+    }
+
+    fn exec<O: DeserializeOwned>(&mut self, function: &str, input: &str) -> Result<O, Self::Error> {
+        let script = format!("{function}({input})");
+        let result = {
+            let _span = span!("katex.js", function);
+            self.guest.eval(&script)
+        };
+        match result {
+            Ok(Ok(output)) => {
+                let _span = span!("katex.deserialize");
+                Ok(serde_json::from_str(&output)?)
+            }
+            Ok(Err(message)) => Err(SandboxError::Js(message)),
+            // 打ち切られたりメモリが足りなくなったりしたゲストは途中の状態で止まっているので、捨てて作り直す
+            Err(error) => {
+                let out_of_memory = self.guest.store.data().exceeded;
+                self.restore()?;
+                Err(if out_of_memory { SandboxError::OutOfMemory } else { error })
+            }
+        }
+    }
+
+    fn interrupt_handle(&mut self) -> Interrupt {
+        let engine = self.engine.clone();
+        Box::new(move || engine.increment_epoch())
+    }
+
+    fn clear_interrupt(&mut self) {
+        self.guest.store.set_epoch_deadline(1);
+    }
+}
+
+// ゲストのインスタンスと、sandbox-guestが書き出している関数
+struct Guest {
+    store: Store<Limits>,
+    memory: Memory,
+    alloc: TypedFunc<u32, u32>,
+    eval: TypedFunc<(u32, u32), i32>,
+    output_ptr: TypedFunc<(), u32>,
+    output_len: TypedFunc<(), u32>,
+}
+
+impl Guest {
+    fn new(engine: &wasmtime::Engine, instance_pre: &InstancePre<Limits>, max_bytes: Option<usize>) -> Result<Self, SandboxError> {
+        let mut store = Store::new(engine, Limits { max_bytes, exceeded: false });
+        store.limiter(|limits| limits);
+        // エポックが一つ進んだら(interrupt_handleが呼ばれたら)トラップする
+        store.set_epoch_deadline(1);
+        let instance = instance_pre.instantiate(&mut store)?;
+        if let Ok(initialize) = instance.get_typed_func::<(), ()>(&mut store, "_initialize") {
+            initialize.call(&mut store, ())?;
+        }
+        let memory = instance.get_memory(&mut store, "memory").ok_or_else(|| SandboxError::Wasm("guest has no memory".to_string()))?;
+        Ok(Guest {
+            memory,
+            alloc: instance.get_typed_func(&mut store, "alloc")?,
+            eval: instance.get_typed_func(&mut store, "eval")?,
+            output_ptr: instance.get_typed_func(&mut store, "output_ptr")?,
+            output_len: instance.get_typed_func(&mut store, "output_len")?,
+            store,
+        })
+    }
+
+    // 外側のErrはトラップ、内側のErrはJSの例外のメッセージ
+    fn eval(&mut self, script: &str) -> Result<Result<String, String>, SandboxError> {
+        let len = script.len() as u32;
+        let ptr = self.alloc.call(&mut self.store, len)?;
+        self.memory.write(&mut self.store, ptr as usize, script.as_bytes()).map_err(|error| SandboxError::Wasm(error.to_string()))?;
+        let status = self.eval.call(&mut self.store, (ptr, len))?;
+        let ptr = self.output_ptr.call(&mut self.store, ())? as usize;
+        let len = self.output_len.call(&mut self.store, ())? as usize;
+        let output = self
+            .memory
+            .data(&self.store)
+            .get(ptr..ptr + len)
+            .ok_or_else(|| SandboxError::Wasm("guest returned an output outside its memory".to_string()))?;
+        let output = String::from_utf8_lossy(output).into_owned();
+        Ok(if status == 0 { Ok(output) } else { Err(output) })
+    }
+}
+
+// 線形メモリの上限。超えて広げようとしたらトラップにし、exceededで見分ける
+struct Limits {
+    max_bytes: Option<usize>,
+    exceeded: bool,
+}
+
+impl ResourceLimiter for Limits {
+    fn memory_growing(&mut self, _current: usize, desired: usize, _maximum: Option<usize>) -> wasmtime::Result<bool> {
+        if self.max_bytes.is_some_and(|max_bytes| desired > max_bytes) {
+            self.exceeded = true;
+            return Err(format_err!("the sandbox memory limit of {} bytes was exceeded", self.max_bytes.unwrap_or_default()));
+        }
+        Ok(true)
+    }
+
+    fn table_growing(&mut self, _current: usize, _desired: usize, _maximum: Option<usize>) -> wasmtime::Result<bool> {
+        Ok(true)
+    }
+}
+
+// Rustの標準ライブラリが起動時や表示に使うWASIの関数だけを、何も見せない形で用意する。
+// 引数と環境変数は空、時計は0、乱数は0で埋め、標準出力と標準エラーへの書き込みは捨てる。それ以外はENOSYSを返す
+fn wasi_stubs(engine: &wasmtime::Engine, module: &Module) -> Result<Linker<Limits>, SandboxError> {
+    const WASI: &str = "wasi_snapshot_preview1";
+    let mut linker = Linker::new(engine);
+    linker.func_wrap(WASI, "args_sizes_get", |mut caller: Caller<'_, Limits>, count: u32, size: u32| {
+        write(&mut caller, count, &0u32.to_le_bytes())?;
+        write(&mut caller, size, &0u32.to_le_bytes())
+    })?;
+    linker.func_wrap(WASI, "environ_sizes_get", |mut caller: Caller<'_, Limits>, count: u32, size: u32| {
+        write(&mut caller, count, &0u32.to_le_bytes())?;
+        write(&mut caller, size, &0u32.to_le_bytes())
+    })?;
+    linker.func_wrap(WASI, "args_get", |_: u32, _: u32| 0i32)?;
+    linker.func_wrap(WASI, "environ_get", |_: u32, _: u32| 0i32)?;
+    linker.func_wrap(WASI, "clock_time_get", |mut caller: Caller<'_, Limits>, _id: u32, _precision: u64, time: u32| {
+        write(&mut caller, time, &0u64.to_le_bytes())
+    })?;
+    linker.func_wrap(WASI, "random_get", |mut caller: Caller<'_, Limits>, buffer: u32, len: u32| {
+        write(&mut caller, buffer, &vec![0; len as usize])
+    })?;
+    linker.func_wrap(WASI, "fd_write", |mut caller: Caller<'_, Limits>, _fd: u32, iovs: u32, iovs_len: u32, written: u32| {
+        let mut total = 0u32;
+        for index in 0..iovs_len {
+            let mut iov = [0u8; 8];
+            read(&mut caller, iovs + index * 8, &mut iov)?;
+            total = total.wrapping_add(u32::from_le_bytes([iov[4], iov[5], iov[6], iov[7]]));
+        }
+        write(&mut caller, written, &total.to_le_bytes())
+    })?;
+    linker.func_wrap(WASI, "proc_exit", |code: u32| -> wasmtime::Result<()> { Err(format_err!("guest exited with {code}")) })?;
+    let mut store = Store::new(engine, Limits { max_bytes: None, exceeded: false });
+    for import in module.imports().filter(|import| import.module() == WASI) {
+        let Some(ty) = import.ty().func().cloned() else { continue };
+        if linker.get_by_import(&mut store, &import).is_some() {
+            continue;
+        }
+        linker.func_new(WASI, import.name(), ty, |_, _, results| {
+            if let Some(result) = results.first_mut() {
+                *result = Val::I32(ERRNO_NOSYS);
+            }
+            Ok(())
+        })?;
+    }
+    Ok(linker)
+}
+
+fn memory(caller: &mut Caller<'_, Limits>) -> wasmtime::Result<Memory> {
+    caller.get_export("memory").and_then(Extern::into_memory).ok_or_else(|| format_err!("guest has no memory"))
+}
+
+// 成功ならWASIのエラー番号0を返す
+fn write(caller: &mut Caller<'_, Limits>, ptr: u32, bytes: &[u8]) -> wasmtime::Result<i32> {
+    memory(caller)?.write(caller, ptr as usize, bytes)?;
+    Ok(0)
+}
+
+fn read(caller: &mut Caller<'_, Limits>, ptr: u32, bytes: &mut [u8]) -> wasmtime::Result<()> {
+    memory(caller)?.read(caller, ptr as usize, bytes)?;
+    Ok(())
+}
@@ -0,0 +1,123 @@
+use ammonia::Builder;
+use once_cell::sync::Lazy;
+use std::{borrow::Cow, collections::HashSet};
+
+// trust: trueで半ば信用できない入力を描画したときに、KaTeXが出すもの以外をHTMLから落とす。
+// 要素と属性は許可したものだけを残し、href/srcは安全なスキームに、styleは位置やurl()を含まない宣言に限る
+pub fn sanitize_html(html: &str) -> String {
+    SANITIZER.clean(html).to_string()
+}
+
+static SANITIZER: Lazy<Builder<'static>> = Lazy::new(|| {
+    let mut builder = Builder::empty();
+    builder
+        .tags(words(ELEMENTS))
+        .clean_content_tags(words(DROPPED_WITH_CONTENT))
+        .generic_attributes(words(ATTRIBUTES))
+        // \htmlDataの属性
+        .generic_attribute_prefixes(HashSet::from(["data-"]))
+        // href/srcはどの要素でも調べる。相対URLはそのまま通す
+        .url_schemes(HashSet::from(["http", "https", "mailto"]))
+        // KaTeXが出した\hrefのリンクにrelを足さない
+        .link_rel(None)
+        .attribute_filter(|_, attribute, value| allowed_attribute(attribute, value));
+    builder
+});
+
+fn words(list: &str) -> HashSet<&str> {
+    list.split_ascii_whitespace().collect()
+}
+
+const DROPPED_WITH_CONTENT: &str = "script style iframe object embed template noscript textarea title xmp noembed";
+
+// KaTeXのHTML、SVG、MathMLで使われる要素
+const ELEMENTS: &str = "span a img br svg path line rect g math semantics annotation mrow mi mn mo ms mtext mspace msup msub msubsup \
+    munder mover munderover mfrac msqrt mroot mtable mtr mtd mlabeledtr mstyle mpadded mphantom menclose mglyph \
+    merror mmultiscripts mprescripts none";
+
+// SVGの属性はhtml5everがSVGの綴りに直した名前(viewBoxなど)で比べる
+const ATTRIBUTES: &str = "class style aria-hidden xmlns width height viewBox preserveAspectRatio d x y x1 y1 x2 y2 stroke-width fill \
+    display mathvariant encoding stretchy fence separator lspace rspace accent accentunder linethickness \
+    scriptlevel displaystyle columnalign rowalign rowspacing columnspacing columnlines rowlines frame framespacing \
+    equalrows equalcolumns side depth voffset notation minsize maxsize movablelimits symmetric largeop mathcolor \
+    mathbackground mathsize alt id href src";
+
+// 許可した属性の値を書き換える(class、style)か、落とす(名前の崩れたdata-*、制御文字を含むURL)
+fn allowed_attribute<'a>(attribute: &str, value: &'a str) -> Option<Cow<'a, str>> {
+    match attribute {
+        // スキームの途中に制御文字があると、url_schemesで調べる前に相対URLとして扱われてしまう
+        "href" | "src" if value.chars().any(|c| c.is_ascii_control()) => None,
+        "class" => Some(
+            value
+                .split_ascii_whitespace()
+                .filter(|class| class.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+                .collect::<Vec<_>>()
+                .join(" ")
+                .into(),
+        ),
+        "style" => Some(safe_style(value).into()),
+        attribute if attribute.starts_with("data-") => {
+            attribute[5..].chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_').then_some(value.into())
+        }
+        _ => Some(value.into()),
+    }
+}
+
+// 数式の中の大きさ、余白、色だけを残す。positionで画面を覆ったり、url()で外部を読んだりはさせない
+fn safe_style(style: &str) -> String {
+    const PROPERTIES: &str = "height width min-width max-width min-height max-height vertical-align top bottom left right margin margin-left \
+        margin-right margin-top margin-bottom padding padding-left padding-right padding-top padding-bottom border \
+        border-width border-style border-color border-top-width border-bottom-width border-right-width \
+        border-left-width border-right-style border-left-style border-bottom-style border-top-style color \
+        background-color font-size font-weight font-style line-height text-align";
+    let mut safe = String::new();
+    for declaration in style.split(';') {
+        let Some((property, value)) = declaration.split_once(':') else { continue };
+        let (property, value) = (property.trim().to_ascii_lowercase(), value.trim());
+        let lower = value.to_ascii_lowercase();
+        if !PROPERTIES.split_ascii_whitespace().any(|allowed| allowed == property)
+            || ["url(", "expression(", "\\", "@import", "javascript:"].iter().any(|bad| lower.contains(bad))
+        {
+            continue;
+        }
+        safe.push_str(&format!("{property}:{value};"));
+    }
+    safe
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls_keep_only_safe_schemes() {
+        let safe_url = |url: &str| sanitize_html(&format!(r#"<a href="{url}">x</a>"#)).contains("href");
+        assert!(safe_url("https://example.com"));
+        assert!(safe_url("mailto:a@example.com"));
+        assert!(safe_url("../page.html#x"));
+        assert!(safe_url("/a:b"));
+        assert!(!safe_url("javascript:alert(1)"));
+        assert!(!safe_url(" JavaScript:alert(1)"));
+        // ブラウザは途中の制御文字と空白も無視する
+        assert!(!safe_url("java\tscript:alert(1)"));
+        assert!(!safe_url("jav\u{1}ascript:alert(1)"));
+        assert!(!safe_url("data:text/html,x"));
+    }
+
+    #[test]
+    fn styles_drop_unknown_properties_and_urls() {
+        assert_eq!(safe_style("height:1em; COLOR: red"), "height:1em;color:red;");
+        assert_eq!(safe_style("position:fixed;width:100%"), "width:100%;");
+        assert_eq!(safe_style("background-color:url(x);color:blue"), "color:blue;");
+        assert_eq!(safe_style("width:expression(alert(1));color:\\72 ed"), "");
+        assert_eq!(safe_style("color"), "");
+    }
+
+    #[test]
+    fn html_keeps_only_allowed_elements_and_attributes() {
+        let html = r#"<span class="mord x<y" onclick="f()" style="position:absolute;top:0">a<script>alert(1)</script></span><div>b</div>"#;
+        assert_eq!(sanitize_html(html), r#"<span class="mord" style="top:0;">a</span>b"#);
+        assert_eq!(sanitize_html(r#"<a href="javascript:f()" data-x="1">c</a>"#), r#"<a data-x="1">c</a>"#);
+        assert_eq!(sanitize_html("<span><span>d</span>"), "<span><span>d</span></span>");
+    }
+}
@@ -0,0 +1,424 @@
+function macroBody(tokens) {
+    let body = "", previous = "";
+    for (let i = tokens.length - 1; i >= 0; i--) {
+        const text = tokens[i].text;
+        if (/^\\[a-zA-Z@]+$/.test(previous) && /^[a-zA-Z@]/.test(text)) body += " ";
+        body += text;
+        previous = text;
+    }
+    return body;
+}
+function flattenMacros(macros) {
+    const flat = {};
+    for (let key in macros) {
+        const value = macros[key];
+        if (typeof value === "string") {
+            flat[key] = value;
+        } else if (typeof value === "function") {
+            flat[key] = value.macroValue;
+        } else {
+            const body = macroBody(value.tokens);
+            flat[key] = value.numArgs > 0 ? { numArgs: value.numArgs, body: body } : body;
+        }
+    }
+    return flat;
+}
+function lowerMacros(macros) {
+    const lowered = {};
+    for (let key in macros) {
+        const value = macros[key];
+        if (typeof value === "string") {
+            lowered[key] = value;
+            continue;
+        }
+        const definition = context => {
+            const lexer = new context.lexer.constructor(value.body, context.settings);
+            const tokens = [];
+            for (let token = lexer.lex(); token.text !== "EOF"; token = lexer.lex()) tokens.push(token);
+            return { tokens: tokens.reverse(), numArgs: value.numArgs };
+        };
+        definition.macroValue = value;
+        lowered[key] = definition;
+    }
+    return lowered;
+}
+// URLを持たない\htmlClassなどはそのまま通す。protocolはKaTeXが小文字にしたもので、相対URLでは"_relative"になる
+function allowedUrl(policy, context) {
+    if (context.url === undefined || context.protocol === "_relative") return true;
+    if (!policy.schemes.includes(context.protocol)) return false;
+    if (policy.domains.length === 0) return true;
+    const authority = /^[^:]*:\/\/([^/?#]*)/.exec(context.url.replace(/[\s\u0000-\u001f]/g, ""));
+    if (!authority) return true;
+    const host = authority[1].replace(/^.*@/, "").replace(/:\d*$/, "").replace(/\.$/, "").toLowerCase();
+    return policy.domains.some(domain => host === domain || host.endsWith("." + domain));
+}
+// errorCodeごとの"ignore"/"warn"/"error"
+function strictLevel(strict) {
+    return typeof strict === "string" ? () => strict : code => Object.prototype.hasOwnProperty.call(strict.codes, code) ? strict.codes[code] : "ignore";
+}
+// warningsを渡すと、console.warnに出されて失われる"warn"の警告をそこに集め、KaTeXにはignoreとして返す
+function katexOptions(options, macros, warnings) {
+    const trust = options.trust, strict = options.strict;
+    const trusted = typeof options.trust === "boolean" ? () => trust : context => trust.commands.includes(context.command);
+    const level = strictLevel(strict);
+    // JSONにできないInfinity(MaxSize::UnlimitedとMaxExpand::Unlimited)はnullで届く
+    return Object.assign({}, options, {
+        maxSize: options.maxSize === null ? Infinity : options.maxSize,
+        maxExpand: options.maxExpand === null ? Infinity : options.maxExpand,
+        macros: macros,
+        trust: options.urlPolicy ? context => trusted(context) && allowedUrl(options.urlPolicy, context) : typeof trust === "boolean" ? trust : trusted,
+        strict: warnings === undefined && typeof strict === "string" ? strict : (code, message) => {
+            const mode = level(code);
+            if (mode !== "warn" || warnings === undefined) return mode;
+            warnings.push(message + " [" + code + "]");
+            return "ignore";
+        },
+    });
+}
+// 改行を含むタグの間の空白と、SVGのパスの中の要らない空白を詰め、styleの最後の;と空のstyleを落とす。
+// \,の<mtext>のように空白だけの中身もあるので、改行を含まない空白は残す
+function minifyHtml(html) {
+    return html
+        .replace(/>[ \t\r\n]*\n[ \t\r\n]*</g, "><")
+        .replace(/ d="([^"]*)"/g, (_, path) => ' d="' + path.replace(/\s+/g, " ").replace(/ (?=[a-zA-Z-])/g, "").replace(/([a-zA-Z]) /g, "$1").replace(/,(?=-)/g, "") + '"')
+        .replace(/ style="([^"]*);"/g, ' style="$1"')
+        .replace(/ style=""/g, "");
+}
+// \colorに書けるCSSの色の名前
+const NAMED_COLORS = Object.fromEntries("aliceblue:f0f8ff antiquewhite:faebd7 aqua:00ffff aquamarine:7fffd4 azure:f0ffff beige:f5f5dc bisque:ffe4c4 black:000000 blanchedalmond:ffebcd blue:0000ff blueviolet:8a2be2 brown:a52a2a burlywood:deb887 cadetblue:5f9ea0 chartreuse:7fff00 chocolate:d2691e coral:ff7f50 cornflowerblue:6495ed cornsilk:fff8dc crimson:dc143c cyan:00ffff darkblue:00008b darkcyan:008b8b darkgoldenrod:b8860b darkgray:a9a9a9 darkgreen:006400 darkgrey:a9a9a9 darkkhaki:bdb76b darkmagenta:8b008b darkolivegreen:556b2f darkorange:ff8c00 darkorchid:9932cc darkred:8b0000 darksalmon:e9967a darkseagreen:8fbc8f darkslateblue:483d8b darkslategray:2f4f4f darkslategrey:2f4f4f darkturquoise:00ced1 darkviolet:9400d3 deeppink:ff1493 deepskyblue:00bfff dimgray:696969 dimgrey:696969 dodgerblue:1e90ff firebrick:b22222 floralwhite:fffaf0 forestgreen:228b22 fuchsia:ff00ff gainsboro:dcdcdc ghostwhite:f8f8ff gold:ffd700 goldenrod:daa520 gray:808080 green:008000 greenyellow:adff2f grey:808080 honeydew:f0fff0 hotpink:ff69b4 indianred:cd5c5c indigo:4b0082 ivory:fffff0 khaki:f0e68c lavender:e6e6fa lavenderblush:fff0f5 lawngreen:7cfc00 lemonchiffon:fffacd lightblue:add8e6 lightcoral:f08080 lightcyan:e0ffff lightgoldenrodyellow:fafad2 lightgray:d3d3d3 lightgreen:90ee90 lightgrey:d3d3d3 lightpink:ffb6c1 lightsalmon:ffa07a lightseagreen:20b2aa lightskyblue:87cefa lightslategray:778899 lightslategrey:778899 lightsteelblue:b0c4de lightyellow:ffffe0 lime:00ff00 limegreen:32cd32 linen:faf0e6 magenta:ff00ff maroon:800000 mediumaquamarine:66cdaa mediumblue:0000cd mediumorchid:ba55d3 mediumpurple:9370db mediumseagreen:3cb371 mediumslateblue:7b68ee mediumspringgreen:00fa9a mediumturquoise:48d1cc mediumvioletred:c71585 midnightblue:191970 mintcream:f5fffa mistyrose:ffe4e1 moccasin:ffe4b5 navajowhite:ffdead navy:000080 oldlace:fdf5e6 olive:808000 olivedrab:6b8e23 orange:ffa500 orangered:ff4500 orchid:da70d6 palegoldenrod:eee8aa palegreen:98fb98 paleturquoise:afeeee palevioletred:db7093 papayawhip:ffefd5 peachpuff:ffdab9 peru:cd853f pink:ffc0cb plum:dda0dd powderblue:b0e0e6 purple:800080 red:ff0000 rosybrown:bc8f8f royalblue:4169e1 saddlebrown:8b4513 salmon:fa8072 sandybrown:f4a460 seagreen:2e8b57 seashell:fff5ee sienna:a0522d silver:c0c0c0 skyblue:87ceeb slateblue:6a5acd slategray:708090 slategrey:708090 snow:fffafa springgreen:00ff7f steelblue:4682b4 tan:d2b48c teal:008080 thistle:d8bfd8 tomato:ff6347 turquoise:40e0d0 violet:ee82ee wheat:f5deb3 white:ffffff whitesmoke:f5f5f5 yellow:ffff00 yellowgreen:9acd32".split(" ").map(entry => entry.split(":")));
+// 色相と彩度はそのままで明るさを反転した色。名前でも#の付いた16進でもないもの(transparentなど)はnull
+function darkColor(color) {
+    const name = color.trim().toLowerCase();
+    const match = /^#([0-9a-f]{3,4}|[0-9a-f]{6}|[0-9a-f]{8})$/.exec(name);
+    let hex = NAMED_COLORS[name] || (match && match[1]);
+    if (!hex) return null;
+    if (hex.length <= 4) hex = hex.replace(/./g, "$&$&");
+    const [r, g, b] = [0, 2, 4].map(i => parseInt(hex.substr(i, 2), 16) / 255);
+    const max = Math.max(r, g, b), min = Math.min(r, g, b), l = (max + min) / 2, d = max - min;
+    const s = d === 0 ? 0 : d / (1 - Math.abs(2 * l - 1));
+    const h = d === 0 ? 0 : max === r ? ((g - b) / d + 6) % 6 : max === g ? (b - r) / d + 2 : (r - g) / d + 4;
+    // 反転した明るさでHSLから戻す
+    const dark = 1 - l, c = (1 - Math.abs(2 * dark - 1)) * s, x = c * (1 - Math.abs((h % 2) - 1)), m = dark - c / 2;
+    const rgb = [[c, x, 0], [x, c, 0], [0, c, x], [0, x, c], [x, 0, c], [c, 0, x]][Math.floor(h) % 6];
+    return "#" + rgb.map(v => Math.round((v + m) * 255).toString(16).padStart(2, "0")).join("") + hex.substr(6);
+}
+// \colorなどで指定した文字色を暗い背景向けにする。"replace"は置き換え、"lightDark"はlight-dark()で元の色と並べる
+function recolorHtml(html, mode) {
+    const recolor = color => {
+        const dark = darkColor(color);
+        return dark === null ? color : mode === "lightDark" ? "light-dark(" + color + "," + dark + ")" : dark;
+    };
+    html = html.replace(/ style="([^"]*)"/g, (_, style) => ' style="' + style.replace(/(^|;)color:([^;]*)/g, (_, start, color) => start + "color:" + recolor(color)) + '"');
+    return mode === "lightDark" ? html : html.replace(/ mathcolor="([^"]*)"/g, (_, color) => ' mathcolor="' + recolor(color) + '"');
+}
+// \colorなどで指定した色とエラーの色をCSSのカスタムプロパティで書く。--katex-color-{色}は#を除いて小文字にした色で、
+// エラーの色だけは--katex-error-colorにする。MathMLのmathcolorはvar()を書けないのでそのまま
+function cssVariablesHtml(html, errorColor) {
+    const variable = color => {
+        const value = color.trim().toLowerCase();
+        if (value === String(errorColor).toLowerCase()) return "var(--katex-error-color)";
+        const match = /^#?([0-9a-z]+)$/.exec(value);
+        return match ? "var(--katex-color-" + match[1] + ")" : color;
+    };
+    return html.replace(/ style="([^"]*)"/g, (_, style) => ' style="' + style.replace(/(^|;)(color|background-color|border-color):([^;]*)/g, (_, start, property, color) => start + property + ":" + variable(color)) + '"');
+}
+// InitOptions::helperで登録した関数。名前で引く
+const katexHelpers = Object.create(null);
+function callHelper(name, args) {
+    if (!Object.prototype.hasOwnProperty.call(katexHelpers, name)) throw new Error("unknown helper: " + name);
+    return katexHelpers[name](args);
+}
+// 戻り値がundefinedならresultを省き、Rust側ではnullになる
+function evalHelper(input) {
+    if (!Object.prototype.hasOwnProperty.call(katexHelpers, input.name)) return JSON.stringify({ found: false });
+    return JSON.stringify({ found: true, result: katexHelpers[input.name](input.args) });
+}
+// copy-texはMathMLのannotationからTeXを読むので、HTML出力にもannotationだけを埋め込む
+function renderToString(latex, options) {
+    if (options.helpers && options.helpers.length > 0) {
+        return options.helpers.reduce((html, name) => callHelper(name, html), renderToString(latex, Object.assign({}, options, { helpers: [] })));
+    }
+    if (options.minify) return minifyHtml(renderToString(latex, Object.assign({}, options, { minify: false })));
+    if (options.darkColors) return recolorHtml(renderToString(latex, Object.assign({}, options, { darkColors: null })), options.darkColors);
+    if (options.cssVariables) return cssVariablesHtml(renderToString(latex, Object.assign({}, options, { cssVariables: false })), options.errorColor);
+    if (options.output !== "htmlAndTex") return katex.renderToString(latex, options);
+    const html = katex.renderToString(latex, Object.assign({}, options, { output: "html" }));
+    const tex = latex.replace(/&/g, "&amp;").replace(/</g, "&lt;").replace(/>/g, "&gt;").replace(/"/g, "&quot;");
+    return html.replace(
+        '<span class="katex">',
+        '<span class="katex"><span class="katex-mathml"><math xmlns="http://www.w3.org/1998/Math/MathML"><semantics><mrow></mrow>' +
+            '<annotation encoding="application/x-tex">' + tex + '</annotation></semantics></math></span>'
+    );
+}
+// throwOnErrorがfalseのとき、KaTeXがエラーを描画したかどうかをfailedで返す。HTMLの中身からは判断しない。
+// 一度throwOnErrorをtrueにして描画し、失敗したら途中で書き込まれた定義を戻してから元のオプションで描画し直す
+function renderChecked(render, options) {
+    if (options.throwOnError !== false) return { result: render(options), failed: false };
+    const macros = options.macros, initial = Object.assign({}, macros);
+    try {
+        return { result: render(Object.assign({}, options, { throwOnError: true })), failed: false };
+    } catch (e) {
+        if (!(e instanceof katex.ParseError)) throw e;
+        for (const key in macros) delete macros[key];
+        Object.assign(macros, initial);
+        return { result: render(options), failed: true };
+    }
+}
+function renderToStringAndMacros(input) {
+    const macros = lowerMacros(input.macros);
+    const warnings = [];
+    try {
+        // 描画し直すときは、失敗した描画の警告を捨てる
+        const render = options => {
+            warnings.length = 0;
+            return renderToString(input.latex, options);
+        };
+        const { result: html, failed } = renderChecked(render, katexOptions(input.options, macros, warnings));
+        return JSON.stringify({ html: html, warnings: warnings, failed: failed, macros: flattenMacros(macros) });
+    } catch (e) {
+        if (e instanceof katex.ParseError) {
+            return JSON.stringify({ error: e.message, macros: flattenMacros(macros) });
+        } else {
+            throw e;
+        }
+    }
+}
+function renderPartsAndMacros(input) {
+    const macros = lowerMacros(input.macros);
+    try {
+        const { result: tree, failed } = renderChecked(options => katex.__renderToDomTree(input.latex, options), katexOptions(input.options, macros));
+        const root = tree.classes.includes("katex-display") ? tree.children[0] : tree;
+        let mathml = "";
+        root.children = root.children.filter(child => {
+            if (!child.classes || !child.classes.includes("katex-mathml")) return true;
+            mathml = child.children.map(node => node.toMarkup()).join("");
+            return false;
+        });
+        const finish = html => {
+            if (input.options.cssVariables) html = cssVariablesHtml(html, input.options.errorColor);
+            if (input.options.darkColors) html = recolorHtml(html, input.options.darkColors);
+            return input.options.minify ? minifyHtml(html) : html;
+        };
+        // helpersはHTMLを受け取る関数なので、HTMLの側にだけ通す
+        const helpers = input.options.helpers || [];
+        const html = helpers.reduce((html, name) => callHelper(name, html), finish(tree.toMarkup()));
+        return JSON.stringify({ html: html, mathml: finish(mathml), failed: failed, macros: flattenMacros(macros) });
+    } catch (e) {
+        if (e instanceof katex.ParseError) {
+            return JSON.stringify({ error: e.message, macros: flattenMacros(macros) });
+        } else {
+            throw e;
+        }
+    }
+}
+// 読み上げ文を作るために構文木も返す。\gdefが二度効かないよう、パースには別のマクロを渡す
+function renderToStringAndTree(input) {
+    const macros = lowerMacros(input.macros);
+    try {
+        const tree = katex.__parse(input.latex, katexOptions(input.options, lowerMacros(input.macros)));
+        const { result: html, failed } = renderChecked(options => renderToString(input.latex, options), katexOptions(input.options, macros));
+        return JSON.stringify({ html: html, tree: tree, failed: failed, macros: flattenMacros(macros) }, (key, value) => (key === "loc" ? undefined : value));
+    } catch (e) {
+        if (e instanceof katex.ParseError) {
+            return JSON.stringify({ error: e.message, macros: flattenMacros(macros) });
+        } else {
+            throw e;
+        }
+    }
+}
+function parseMacros(input) {
+    const macros = lowerMacros(input.macros);
+    try {
+        katex.__parse(input.latex, katexOptions(input.options, macros));
+        return JSON.stringify({ macros: flattenMacros(macros) });
+    } catch (e) {
+        if (e instanceof katex.ParseError) {
+            return JSON.stringify({ error: e.message, macros: flattenMacros(macros) });
+        } else {
+            throw e;
+        }
+    }
+}
+// 文書中の数式を順にパースして、定義されたマクロだけを集める。KaTeXのエラーになった数式は飛ばす
+function collectMacros(input) {
+    const macros = lowerMacros(input.macros);
+    for (const item of input.items) {
+        try {
+            katex.__parse(item.latex, katexOptions(item.options, macros));
+        } catch (e) {
+            if (!(e instanceof katex.ParseError)) throw e;
+        }
+    }
+    return JSON.stringify({ macros: flattenMacros(macros) });
+}
+// 渡したマクロを、展開されたら名前を覚える関数で包んでパースする。マクロの本体の中で展開されたものも数える
+function macrosUsed(input) {
+    const used = new Set();
+    const lowered = lowerMacros(input.macros);
+    const macros = {};
+    for (const key in lowered) {
+        const value = lowered[key];
+        macros[key] = context => {
+            used.add(key);
+            return typeof value === "function" ? value(context) : value;
+        };
+    }
+    try {
+        katex.__parse(input.latex, katexOptions(input.options, macros));
+        return JSON.stringify({ used: Array.from(used) });
+    } catch (e) {
+        if (e instanceof katex.ParseError) {
+            return JSON.stringify({ error: e.message, macros: input.macros });
+        } else {
+            throw e;
+        }
+    }
+}
+// katex.__parseの構文木。locは字句解析器を指していて循環するので、位置(UTF-16)だけを残す
+function parseTree(input) {
+    try {
+        const tree = katex.__parse(input.latex, katexOptions(input.options, lowerMacros(input.macros)));
+        return JSON.stringify({ tree: tree }, (key, value) => (key === "loc" && value ? { start: value.start, end: value.end } : value));
+    } catch (e) {
+        if (e instanceof katex.ParseError) {
+            return JSON.stringify({ error: e.message, macros: input.macros });
+        } else {
+            throw e;
+        }
+    }
+}
+// HTMLを作らずにパースだけする。positionとlengthはUTF-16での位置
+function checkParse(input) {
+    try {
+        katex.__parse(input.latex, katexOptions(input.options, lowerMacros(input.macros)));
+        return JSON.stringify({ diagnostics: null });
+    } catch (e) {
+        if (e instanceof katex.ParseError) {
+            return JSON.stringify({ diagnostics: { message: e.message, rawMessage: e.rawMessage, position: e.position, length: e.length } });
+        } else {
+            throw e;
+        }
+    }
+}
+// エディタ向けに、パースのエラーとstrictで"ignore"でない非標準の書き方を集める。strictの"error"もパースを止めずに集める。
+// positionとlengthはUTF-16での位置で、KaTeXが位置を示さなかったときはnull
+function diagnose(input) {
+    const level = strictLevel(input.options.strict);
+    const diagnostics = [];
+    const options = Object.assign(katexOptions(input.options, lowerMacros(input.macros)), {
+        strict: (code, message, token) => {
+            const mode = level(code);
+            if (mode === "warn" || mode === "error") {
+                const loc = token && token.loc;
+                diagnostics.push({ severity: mode === "error" ? "error" : "warning", message: message, code: code, position: loc ? loc.start : null, length: loc ? loc.end - loc.start : null });
+            }
+            return "ignore";
+        },
+    });
+    try {
+        katex.__parse(input.latex, options);
+    } catch (e) {
+        if (!(e instanceof katex.ParseError)) throw e;
+        diagnostics.push({ severity: "error", message: e.rawMessage || e.message, code: null, position: e.position ?? null, length: e.length ?? null });
+    }
+    return JSON.stringify({ diagnostics: diagnostics });
+}
+// 引数を全て展開して覚え、自身は何も出さないマクロで包んでパースする。
+// \gdefなどはパーサーが実行するので、同じ数式の中での定義は展開に使われない
+function expandMacros(input) {
+    const macros = lowerMacros(input.macros);
+    let expanded = "";
+    macros["\\katexgdef@expand"] = context => {
+        expanded = macroBody(context.expandTokens(context.consumeArg().tokens).reverse());
+        return "";
+    };
+    try {
+        katex.__parse("\\katexgdef@expand{" + input.latex + "}", katexOptions(input.options, macros));
+        return JSON.stringify({ expanded: expanded });
+    } catch (e) {
+        if (e instanceof katex.ParseError) {
+            return JSON.stringify({ error: e.message, macros: input.macros });
+        } else {
+            throw e;
+        }
+    }
+}
+// 隣り合うグリフをKaTeXが一つにまとめるとき幅は足されないので、測っている間だけtextを差し替えて作られたグリフを覚えておき、まとめられた側の幅を足す
+let measuredSymbols = null;
+const measuringText = {
+    configurable: true,
+    get() {
+        return this.symbolText;
+    },
+    set(text) {
+        const previous = this.symbolText;
+        this.symbolText = text;
+        if (measuredSymbols === null) return;
+        if (previous === undefined) {
+            measuredSymbols.push(this);
+        } else if (typeof previous === "string" && text.startsWith(previous) && text.length > previous.length) {
+            const suffix = text.slice(previous.length);
+            const next = measuredSymbols.slice(measuredSymbols.indexOf(this) + 1).find(node => !node.combined && node.symbolText === suffix);
+            if (next) {
+                next.combined = true;
+                this.width = (this.width || 0) + (next.width || 0);
+            }
+        }
+    },
+};
+// KaTeXは幅を計算しないので、グリフの幅とmargin・widthを足し合わせて見積もる。vlistは縦に積むので一番広い行の幅にする
+const sizeMultipliers = [0.5, 0.6, 0.7, 0.8, 0.9, 1, 1.2, 1.44, 1.728, 2.074, 2.488];
+function emLength(length) {
+    return typeof length === "string" && length.endsWith("em") ? parseFloat(length) || 0 : 0;
+}
+function nodeWidth(node) {
+    const style = node.style || {};
+    const classes = node.classes || [];
+    let width = node.text !== undefined ? (node.width || 0) + (node.italic || 0) : 0;
+    const children = node.children || [];
+    if (classes.includes("vlist")) {
+        width += Math.max(0, ...children.map(nodeWidth));
+    } else if (emLength(style.width) > 0) {
+        width += emLength(style.width);
+    } else {
+        width += children.reduce((sum, child) => sum + nodeWidth(child), 0);
+    }
+    width += emLength(style.marginLeft) + emLength(style.marginRight);
+    const reset = classes.find(c => /^reset-size\d+$/.test(c)), size = classes.find(c => /^size\d+$/.test(c));
+    if (reset && size) width *= sizeMultipliers[parseInt(size.slice(4)) - 1] / sizeMultipliers[parseInt(reset.slice(10)) - 1];
+    return width;
+}
+// 幅はHTMLの木から測るので、MathMLだけの出力やエラーの表示を頼まれていてもHTMLで描き、エラーは投げさせる。
+// textを差し替えている間にkatex-htmlの部分をfに渡す
+function withHtmlTree(input, macros, f) {
+    const prototype = katex.__domTree.SymbolNode.prototype;
+    const original = Object.getOwnPropertyDescriptor(prototype, "text");
+    Object.defineProperty(prototype, "text", measuringText);
+    measuredSymbols = [];
+    try {
+        const options = Object.assign(katexOptions(input.options, macros), { output: "html", throwOnError: true });
+        const tree = katex.__renderToDomTree(input.latex, options);
+        const root = tree.classes.includes("katex-display") ? tree.children[0] : tree;
+        return f(root.children.find(child => child.classes && child.classes.includes("katex-html")));
+    } finally {
+        measuredSymbols = null;
+        if (original) {
+            Object.defineProperty(prototype, "text", original);
+        } else {
+            delete prototype.text;
+        }
+    }
+}
+function measureAndMacros(input) {
+    const macros = lowerMacros(input.macros);
+    try {
+        const dimensions = withHtmlTree(input, macros, html => ({ width_em: nodeWidth(html), height_em: html.height, depth_em: html.depth }));
+        return JSON.stringify({ dimensions: dimensions, macros: flattenMacros(macros) });
+    } catch (e) {
+        if (e instanceof katex.ParseError) {
+            return JSON.stringify({ error: e.message, macros: flattenMacros(macros) });
+        } else {
+            throw e;
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use serde_json::Value;
+
+// KaTeXの構文木(parse_treeの戻り値)を英語の読み上げ文にする。aria-labelなどに入れる想定で、
+// MathSpeakほど厳密ではなく、よく使う構文だけを言葉にして、知らないノードは中身だけを読む
+pub fn speech_text(tree: &Value) -> String {
+    let mut words = Words::default();
+    speak(tree, &mut words);
+    words.finish()
+}
+
+#[derive(Default)]
+struct Words {
+    words: Vec<String>,
+    // 続けて読む数字(1と2で12にする)
+    number: String,
+}
+impl Words {
+    fn push(&mut self, word: impl Into<String>) {
+        self.flush();
+        let word = word.into();
+        if !word.is_empty() {
+            self.words.push(word);
+        }
+    }
+    fn digit(&mut self, digit: &str) {
+        self.number.push_str(digit);
+    }
+    fn flush(&mut self) {
+        if !self.number.is_empty() {
+            let number = std::mem::take(&mut self.number);
+            self.words.push(number);
+        }
+    }
+    fn finish(mut self) -> String {
+        self.flush();
+        self.words.join(" ")
+    }
+}
+
+// 一つの塊として読む部分。上付きや分数の中身など
+fn phrase(node: &Value) -> String {
+    speech_text(node)
+}
+
+fn speak(node: &Value, words: &mut Words) {
+    let node = match node {
+        Value::Array(nodes) => return nodes.iter().for_each(|node| speak(node, words)),
+        Value::Object(node) => node,
+        _ => return,
+    };
+    let field = |name: &str| node.get(name).unwrap_or(&Value::Null);
+    let text = |name: &str| field(name).as_str().unwrap_or("");
+    match text("type") {
+        "mathord" | "textord" | "atom" | "op-token" | "accent-token" => {
+            let text = text("text");
+            if text.len() == 1 && text.as_bytes()[0].is_ascii_digit() || text == "." && !words.number.is_empty() {
+                words.digit(text);
+            } else {
+                words.push(symbol(text));
+            }
+        }
+        "spacing" | "kern" | "glue" | "phantom" | "hphantom" | "vphantom" | "rule" | "cr" | "tag" => {}
+        "text" => words.push(plain_text(field("body")).trim()),
+        "supsub" => {
+            let base = field("base");
+            if matches!(base.get("type").and_then(Value::as_str), Some("op" | "operatorname")) {
+                // \sum_{i=1}^nや\lim_{x\to0}の添字は範囲として読む
+                speak(base, words);
+                if !field("sub").is_null() {
+                    let limit = base.get("name").and_then(Value::as_str) == Some("\\lim");
+                    words.push(if !field("sup").is_null() {
+                        "from"
+                    } else if limit {
+                        "as"
+                    } else {
+                        "over"
+                    });
+                    words.push(phrase(field("sub")));
+                }
+                if !field("sup").is_null() {
+                    words.push("to");
+                    words.push(phrase(field("sup")));
+                }
+                if !field("sub").is_null() || !field("sup").is_null() {
+                    words.push("of");
+                }
+                return;
+            }
+            speak(base, words);
+            if !field("sub").is_null() {
+                words.push("sub");
+                words.push(phrase(field("sub")));
+            }
+            if !field("sup").is_null() {
+                match phrase(field("sup")).as_str() {
+                    "2" => words.push("squared"),
+                    "3" => words.push("cubed"),
+                    "prime" | "double prime" => words.push(phrase(field("sup"))),
+                    sup => {
+                        words.push("to the power of");
+                        words.push(sup);
+                    }
+                }
+            }
+        }
+        "genfrac" => {
+            let (numer, denom) = (phrase(field("numer")), phrase(field("denom")));
+            if field("hasBarLine").as_bool() == Some(false) {
+                words.push(format!("{numer} choose {denom}"));
+            } else {
+                words.push(format!("the fraction {numer} over {denom}"));
+            }
+        }
+        "sqrt" => match field("index") {
+            Value::Null => words.push(format!("the square root of {}", phrase(field("body")))),
+            index => words.push(format!("the {} root of {}", ordinal(&phrase(index)), phrase(field("body")))),
+        },
+        "leftright" => {
+            words.push(delimiter(text("left")));
+            speak(field("body"), words);
+            words.push(delimiter(text("right")));
+        }
+        "middle" => words.push(delimiter(text("delim"))),
+        "delimsizing" => words.push(delimiter(text("delim"))),
+        "op" => match field("name").as_str() {
+            Some(name) => words.push(symbol(name)),
+            None => speak(field("body"), words),
+        },
+        "operatorname" => words.push(plain_text(field("body"))),
+        "accent" => {
+            let base = phrase(field("base"));
+            match text("label") {
+                "\\vec" | "\\overrightarrow" => words.push(format!("vector {base}")),
+                label => words.push(format!("{base} {}", accent(label))),
+            }
+        }
+        "overline" => words.push(format!("{} bar", phrase(field("body")))),
+        "underline" => words.push(format!("{} underlined", phrase(field("body")))),
+        "array" => {
+            let rows = field("body").as_array().map_or(&[][..], Vec::as_slice);
+            words.push(format!("matrix with {} {}", rows.len(), if rows.len() == 1 { "row" } else { "rows" }));
+            for (index, row) in rows.iter().enumerate() {
+                let cells: Vec<String> = row.as_array().map_or(&[][..], Vec::as_slice).iter().map(phrase).collect();
+                words.push(format!("row {}: {};", index + 1, cells.join(", ")));
+            }
+            words.push("end matrix");
+        }
+        // \neqなどはHTML用に組み立てた形を持つので、MathML用の一文字の方を読む
+        "htmlmathml" => speak(field("mathml"), words),
+        _ => speak(field("body"), words),
+    }
+}
+
+// \textの中身。文字をそのまま繋げる
+fn plain_text(node: &Value) -> String {
+    match node {
+        Value::Array(nodes) => nodes.iter().map(plain_text).collect(),
+        Value::Object(object) => match object.get("text").and_then(Value::as_str) {
+            Some(text) if object.get("type").and_then(Value::as_str) == Some("spacing") => {
+                if text.starts_with('\\') {
+                    " ".to_string()
+                } else {
+                    text.to_string()
+                }
+            }
+            Some(text) => text.to_string(),
+            None => object.get("body").map(plain_text).unwrap_or_default(),
+        },
+        _ => String::new(),
+    }
+}
+
+fn ordinal(index: &str) -> String {
+    match index {
+        "2" => "square".to_string(),
+        "3" => "cube".to_string(),
+        index => format!("{index}th"),
+    }
+}
+
+fn accent(label: &str) -> &str {
+    match label {
+        "\\hat" | "\\widehat" => "hat",
+        "\\tilde" | "\\widetilde" => "tilde",
+        "\\bar" => "bar",
+        "\\dot" => "dot",
+        "\\ddot" => "double dot",
+        "\\check" => "check",
+        "\\breve" => "breve",
+        label => label.trim_start_matches('\\'),
+    }
+}
+
+fn delimiter(delimiter: &str) -> &'static str {
+    match delimiter {
+        "(" => "open paren",
+        ")" => "close paren",
+        "[" | "\\lbrack" => "open bracket",
+        "]" | "\\rbrack" => "close bracket",
+        "\\{" | "\\lbrace" => "open brace",
+        "\\}" | "\\rbrace" => "close brace",
+        "\\langle" | "⟨" => "open angle",
+        "\\rangle" | "⟩" => "close angle",
+        "|" | "\\vert" | "\\lvert" | "\\rvert" => "vertical bar",
+        "\\|" | "\\Vert" | "\\lVert" | "\\rVert" => "double vertical bar",
+        "\\lfloor" => "open floor",
+        "\\rfloor" => "close floor",
+        "\\lceil" => "open ceiling",
+        "\\rceil" => "close ceiling",
+        _ => "",
+    }
+}
+
+fn symbol(text: &str) -> String {
+    let word = match text {
+        "+" => "plus",
+        "-" | "−" => "minus",
+        "\\pm" => "plus or minus",
+        "\\mp" => "minus or plus",
+        "\\times" => "times",
+        "\\cdot" | "\\ast" | "*" => "times",
+        "\\div" | "/" => "divided by",
+        "=" => "equals",
+        "\\ne" | "\\neq" | "≠" => "is not equal to",
+        "<" | "\\lt" => "is less than",
+        ">" | "\\gt" => "is greater than",
+        "\\le" | "\\leq" => "is less than or equal to",
+        "\\ge" | "\\geq" => "is greater than or equal to",
+        "\\approx" => "is approximately equal to",
+        "\\equiv" => "is equivalent to",
+        "\\sim" => "is similar to",
+        "\\propto" => "is proportional to",
+        "\\in" => "is in",
+        "\\notin" => "is not in",
+        "\\subset" => "is a subset of",
+        "\\subseteq" => "is a subset of or equal to",
+        "\\cup" => "union",
+        "\\cap" => "intersection",
+        "\\to" | "\\rightarrow" => "goes to",
+        "\\mapsto" => "maps to",
+        "\\Rightarrow" | "\\implies" => "implies",
+        "\\Leftrightarrow" | "\\iff" => "if and only if",
+        "\\infty" => "infinity",
+        "\\partial" => "partial",
+        "\\nabla" => "nabla",
+        "\\forall" => "for all",
+        "\\exists" => "there exists",
+        "\\emptyset" | "\\varnothing" => "the empty set",
+        "\\sum" => "the sum",
+        "\\prod" => "the product",
+        "\\int" => "the integral",
+        "\\iint" => "the double integral",
+        "\\iiint" => "the triple integral",
+        "\\oint" => "the contour integral",
+        "\\lim" => "the limit",
+        "\\prime" | "'" => "prime",
+        "," => "comma",
+        "!" => "factorial",
+        "\\ldots" | "\\cdots" | "\\dots" => "dot dot dot",
+        "(" => "open paren",
+        ")" => "close paren",
+        "[" => "open bracket",
+        "]" => "close bracket",
+        "\\{" | "\\lbrace" => "open brace",
+        "\\}" | "\\rbrace" => "close brace",
+        "|" | "\\vert" | "\\mid" => "vertical bar",
+        // \alphaや\sinなどは名前をそのまま読む
+        text => return text.trim_start_matches('\\').to_string(),
+    };
+    word.to_string()
+}
@@ -0,0 +1,249 @@
+// pngのfeatureで、shim.jsの後に読み込まれる。render_svgとrender_pngのためのレイアウト
+// KaTeXのCSSの代わりにHTMLの木を自前で並べて、SVGの要素にする。位置はkatex-htmlの基線の左端からのem(下が正)で、
+// 幅の決まっていない罫線や伸びる記号は、囲んでいるvlistの幅が分かったところで広げる
+const svgTextAlign = {
+    mfrac: "center",
+    "op-limits": "center",
+    accent: "center",
+    mover: "center",
+    munder: "center",
+    "x-arrow": "center",
+    "col-align-c": "center",
+    "col-align-l": "left",
+    "col-align-r": "right",
+    msupsub: "left",
+    "svg-align": "left",
+};
+// 伸びる矢印や括弧を分けて描く部品の、行の幅に対する位置と幅の割合
+const svgFillParts = {
+    "halfarrow-left": [0, 0.502],
+    "halfarrow-right": [0.498, 0.502],
+    "brace-left": [0, 0.251],
+    "brace-center": [0.25, 0.5],
+    "brace-right": [0.749, 0.251],
+};
+// CSSで決まっている左右の詰め物と幅(em)
+const svgPadding = { "x-arrow-pad": [0.5, 0.5], "cd-arrow-pad": [0.27778, 0.55556], boxpad: [0.3, 0.3], "cancel-pad": [0.2, 0.2], anglpad: [0.03889, 0.03889] };
+const svgWidths = { nulldelimiter: 0.12 };
+// 幅いっぱいに引く罫線と、その既定の太さ(em)
+const svgLines = { "frac-line": 0.04, "overline-line": 0.04, "underline-line": 0.04, hline: 0.04, hdashline: 0.04, sout: 0.08 };
+// KaTeXの文字の大きさ(1.21em)を、本文が16pxのときのpxにしたもの
+const svgEmPixels = 19.36;
+// font.rsのfont_stack_setと同じ対応で、classからKaTeXのフォントを決める
+function svgFont(font, classes) {
+    const delimsizing = classes.includes("delimsizing"), opSymbol = classes.includes("op-symbol");
+    font = Object.assign({}, font, { mult: font.mult || (delimsizing && classes.includes("mult")) });
+    for (const c of classes) {
+        switch (c) {
+            case "textbf": font.bold = true; break;
+            case "textit": font.italic = true; break;
+            case "textrm": font.family = "Main"; break;
+            case "mathsf": case "textsf": font.family = "SansSerif"; break;
+            case "texttt": case "mathtt": font.family = "Typewriter"; break;
+            case "mathnormal": font.family = "Math"; font.italic = true; break;
+            case "mathit": font.family = "Main"; font.italic = true; break;
+            case "mathrm": font.italic = false; break;
+            case "mathbf": font.family = "Main"; font.bold = true; break;
+            case "boldsymbol": font.family = "Math"; font.bold = true; font.italic = true; break;
+            case "amsrm": case "mathbb": case "textbb": font.family = "AMS"; break;
+            case "mathcal": font.family = "Caligraphic"; break;
+            case "mathfrak": case "textfrak": font.family = "Fraktur"; break;
+            case "mathboldfrak": case "textboldfrak": font.family = "Fraktur"; font.bold = true; break;
+            case "mathscr": font.family = "Script"; break;
+            case "mathboldsf": case "textboldsf": font.family = "SansSerif"; font.bold = true; break;
+            case "mathsfit": case "mathitsf": case "textitsf": font.family = "SansSerif"; font.italic = true; break;
+            case "mainrm": font.family = "Main"; font.italic = false; break;
+            case "size1": case "size2": case "size3": case "size4": if (delimsizing) font.family = "Size" + c.slice(4); break;
+            case "delim-size1": case "delim-size4": if (font.mult) font.family = "Size" + c.slice(10); break;
+            case "small-op": if (opSymbol) font.family = "Size1"; break;
+            case "large-op": if (opSymbol) font.family = "Size2"; break;
+        }
+    }
+    return font;
+}
+// 子に引き継ぐ大きさ・色・フォント・行揃え
+function svgState(state, classes, style) {
+    const next = Object.assign({}, state, { font: svgFont(state.font, classes) });
+    const reset = classes.find(c => /^reset-size\d+$/.test(c)), size = classes.find(c => /^size\d+$/.test(c));
+    if (reset && size) next.scale = state.scale * sizeMultipliers[parseInt(size.slice(4)) - 1] / sizeMultipliers[parseInt(reset.slice(10)) - 1];
+    if (style.color) next.color = style.color;
+    for (const c of classes) {
+        if (c in svgTextAlign) next.align = svgTextAlign[c];
+    }
+    return next;
+}
+// 要素を(x, y)から並べてoutに足し、横に進んだ幅を返す
+function svgNode(node, x, y, state, out) {
+    const domTree = katex.__domTree;
+    if (node instanceof domTree.SymbolNode) return svgSymbol(node, x, y, state, out);
+    if (node instanceof domTree.SvgNode) return 0;
+    if (!Array.isArray(node.children)) return emLength((node.style || {}).width) * state.scale;
+    const style = node.style || {}, classes = node.classes || [];
+    state = svgState(state, classes, style);
+    const scale = state.scale;
+    const marginLeft = emLength(style.marginLeft) * scale, marginRight = emLength(style.marginRight) * scale;
+    x += marginLeft;
+    y += (emLength(style.top) - emLength(style.bottom) - emLength(style.verticalAlign)) * scale;
+    const left = x + emLength(style.left) * scale;
+    if (classes.includes("vlist-t")) return marginLeft + svgVList(node, left, y, state, out) + marginRight;
+    if (classes.some(c => c in svgLines)) {
+        const thickness = (emLength(style.borderBottomWidth) || svgLines[classes.find(c => c in svgLines)]) * scale;
+        out.push({ kind: "rect", x: left, y: y - thickness, height: thickness, fill: [0, 1], dashed: classes.includes("hdashline"), color: state.color });
+        return 0;
+    }
+    const boxed = classes.includes("fbox") || classes.includes("fcolorbox");
+    if (boxed || classes.includes("angl") || (style.backgroundColor && style.height)) {
+        const height = emLength(style.height) * scale, border = boxed ? (emLength(style.borderWidth) || 0.04) * scale : 0;
+        const angl = classes.includes("angl") ? [(emLength(style.borderTopWidth) || 0.049) * scale, (emLength(style.borderRightWidth) || 0.049) * scale] : [border, border];
+        const borders = { top: angl[0], right: angl[1], bottom: border, left: border };
+        out.push({ kind: "frame", x: left, y: y - height, height: height, fill: [0, 1], borders: borders, stroke: style.borderColor || state.color, background: style.backgroundColor });
+        return 0;
+    }
+    if (classes.includes("rule") || (node.children.length === 0 && style.borderRightWidth)) {
+        const width = emLength(style.borderRightWidth) * scale, height = emLength(style.borderTopWidth || style.height) * scale;
+        out.push({ kind: "rect", x: left, y: y - height, width: width, height: height, dashed: style.borderRightStyle === "dashed", color: state.color });
+        return marginLeft + width + marginRight;
+    }
+    if (node.children.some(child => child instanceof domTree.SvgNode || (child.classes || []).some(c => c in svgFillParts))) {
+        return marginLeft + svgBox(node, left, y, state, out, [0, 1], 0, emLength(style.minWidth) * scale) + marginRight;
+    }
+    if (classes.includes("llap") || classes.includes("rlap") || classes.includes("clap")) {
+        const inner = [], width = node.children.reduce((sum, child) => sum + svgNode(child, left + sum, y, state, inner), 0);
+        const shift = classes.includes("llap") ? -width : classes.includes("clap") ? -width / 2 : 0;
+        for (const item of inner) out.push(Object.assign(item, { x: item.x + shift }));
+        return marginLeft + marginRight;
+    }
+    const padding = classes.reduce((padding, c) => svgPadding[c] || padding, [0, 0]);
+    const paddingLeft = (padding[0] + emLength(style.paddingLeft)) * scale, paddingRight = (padding[1] + emLength(style.paddingRight)) * scale;
+    let width = paddingLeft;
+    if (classes.includes("tag")) width += 1;
+    for (const child of node.children) width += svgNode(child, left + width, y, state, out);
+    width += paddingRight;
+    if (classes.includes("accent-body") && !classes.includes("accent-full")) width = 0;
+    const cssWidth = classes.find(c => c in svgWidths);
+    if (style.width !== undefined) width = emLength(style.width) * scale;
+    else if (cssWidth) width = svgWidths[cssWidth] * scale;
+    return marginLeft + width + marginRight;
+}
+// KaTeXのCSSと同じく、italicの補正はmargin-rightが指定されていないときだけ足す
+function svgSymbol(node, x, y, state, out) {
+    const style = node.style || {};
+    state = svgState(state, node.classes || [], style);
+    const scale = state.scale;
+    const left = emLength(style.marginLeft) * scale;
+    const right = style.marginRight !== undefined ? emLength(style.marginRight) * scale : Math.max(0, node.italic || 0) * scale;
+    y += (emLength(style.top) - emLength(style.bottom) - emLength(style.verticalAlign)) * scale;
+    if (/[^\s\u200b]/.test(node.text)) out.push({ kind: "text", x: x + left, y: y, text: node.text, size: scale, font: state.font, color: state.color });
+    return left + (node.width || 0) * scale + right;
+}
+// vlistの行はtopとpstrutの高さで基線が決まる。一番広い行の幅に揃え、幅の決まっていないものをその幅に広げる
+function svgVList(node, x, y, state, out) {
+    const vlist = node.children[0].children.find(child => (child.classes || []).includes("vlist"));
+    const rows = vlist.children.map(row => {
+        const rowState = svgState(state, row.classes || [], {});
+        const style = row.style || {}, items = [];
+        const pstrut = row.children[0];
+        const rowY = y + (emLength(style.top) + emLength(pstrut.style.height)) * state.scale;
+        let width = emLength(style.marginLeft) * state.scale;
+        for (const child of row.children.slice(1)) width += svgNode(child, x + width, rowY, rowState, items);
+        return { items: items, width: width + emLength(style.marginRight) * state.scale, align: rowState.align };
+    });
+    const width = Math.max(0, ...rows.map(row => row.width));
+    for (const row of rows) {
+        const shift = row.align === "center" ? (width - row.width) / 2 : row.align === "right" ? width - row.width : 0;
+        for (const item of row.items) out.push(item.fill ? svgFill(item, x, width) : Object.assign(item, { x: item.x + shift }));
+    }
+    return width;
+}
+function svgFill(item, x, width) {
+    const full = Math.max(item.minWidth || 0, width);
+    return Object.assign(item, { x: x + item.fill[0] * full, width: item.fill[1] * full, fill: null });
+}
+// SVGの入った箱は、高さを持ち下端が基線に来る。幅はstyle.widthか、無ければ行の幅に広げる
+function svgBox(node, x, y, state, out, part, height, minWidth) {
+    const style = node.style || {};
+    height = emLength(style.height) * state.scale || height;
+    const width = style.width !== undefined ? emLength(style.width) * state.scale : undefined;
+    for (const child of node.children) {
+        if (child instanceof katex.__domTree.SvgNode) {
+            const attributes = child.attributes, svgHeight = height || emLength(attributes.height) * state.scale;
+            out.push({
+                kind: "svg",
+                x: x,
+                y: y - svgHeight,
+                width: width,
+                height: svgHeight,
+                fill: width === undefined ? part : null,
+                minWidth: minWidth,
+                viewBox: attributes.viewBox,
+                preserveAspectRatio: attributes.preserveAspectRatio,
+                body: child.children.map(path => path.toMarkup()).join(""),
+                color: state.color,
+            });
+        } else {
+            const c = (child.classes || []).find(c => c in svgFillParts);
+            if (c) svgBox(child, x, y, state, out, svgFillParts[c], height, minWidth);
+        }
+    }
+    return width !== undefined ? width : minWidth;
+}
+function svgEscape(text) {
+    return String(text).replace(/&/g, "&amp;").replace(/</g, "&lt;").replace(/>/g, "&gt;").replace(/"/g, "&quot;");
+}
+function svgMarkup(item) {
+    const n = value => +value.toFixed(4);
+    const color = item.color ? svgEscape(item.color) : "currentColor";
+    switch (item.kind) {
+        case "text": {
+            const font = item.font;
+            const weight = font.bold ? ' font-weight="bold"' : "", italic = font.italic ? ' font-style="italic"' : "";
+            return `<text x="${n(item.x)}" y="${n(item.y)}" font-family="KaTeX_${font.family}, serif" font-size="${n(item.size)}"${weight}${italic} fill="${color}">${svgEscape(item.text)}</text>`;
+        }
+        case "rect":
+            if (item.dashed) {
+                const horizontal = item.width > item.height, thickness = horizontal ? item.height : item.width;
+                const x = n(horizontal ? item.x : item.x + thickness / 2), y = n(horizontal ? item.y + thickness / 2 : item.y);
+                const end = horizontal ? `x2="${n(item.x + item.width)}" y2="${y}"` : `x2="${x}" y2="${n(item.y + item.height)}"`;
+                return `<line x1="${x}" y1="${y}" ${end} stroke="${color}" stroke-width="${n(thickness)}" stroke-dasharray="${n(thickness * 2)}"/>`;
+            }
+            return `<rect x="${n(item.x)}" y="${n(item.y)}" width="${n(item.width)}" height="${n(item.height)}" fill="${color}"/>`;
+        case "frame": {
+            const rect = (x, y, width, height, fill) => `<rect x="${n(x)}" y="${n(y)}" width="${n(width)}" height="${n(height)}" fill="${fill}"/>`;
+            const { top, right, bottom, left } = item.borders, stroke = item.stroke ? svgEscape(item.stroke) : "currentColor";
+            return [
+                item.background ? rect(item.x, item.y, item.width, item.height, svgEscape(item.background)) : "",
+                top ? rect(item.x, item.y, item.width, top, stroke) : "",
+                bottom ? rect(item.x, item.y + item.height - bottom, item.width, bottom, stroke) : "",
+                left ? rect(item.x, item.y, left, item.height, stroke) : "",
+                right ? rect(item.x + item.width - right, item.y, right, item.height, stroke) : "",
+            ].join("");
+        }
+        case "svg": {
+            const viewBox = item.viewBox ? ` viewBox="${svgEscape(item.viewBox)}"` : "";
+            const aspect = item.preserveAspectRatio ? ` preserveAspectRatio="${svgEscape(item.preserveAspectRatio)}"` : "";
+            return `<svg x="${n(item.x)}" y="${n(item.y)}" width="${n(item.width)}" height="${n(item.height)}"${viewBox}${aspect} fill="${color}" stroke="${color}" stroke-width="0">${item.body}</svg>`;
+        }
+    }
+}
+// 背景の色を先に描き、その上に文字や線を重ねる
+function svgDocument(html) {
+    const items = [], state = { scale: 1, color: null, font: { family: "Main", bold: false, italic: false, mult: false }, align: "left" };
+    const width = svgNode(html, 0, 0, state, items);
+    const resolved = items.map(item => (item.fill ? svgFill(item, 0, width) : item));
+    const body = resolved.filter(item => item.background).concat(resolved.filter(item => !item.background)).map(svgMarkup).join("");
+    const padding = 0.1, height = html.height + html.depth + 2 * padding;
+    const x = -padding, y = -html.height - padding, w = width + 2 * padding;
+    return `<svg xmlns="http://www.w3.org/2000/svg" width="${+(w * svgEmPixels).toFixed(2)}" height="${+(height * svgEmPixels).toFixed(2)}" viewBox="${+x.toFixed(4)} ${+y.toFixed(4)} ${+w.toFixed(4)} ${+height.toFixed(4)}" font-size="1" xml:space="preserve">${body}</svg>`;
+}
+function renderToSvgAndMacros(input) {
+    const macros = lowerMacros(input.macros);
+    try {
+        return JSON.stringify({ svg: withHtmlTree(input, macros, svgDocument), macros: flattenMacros(macros) });
+    } catch (e) {
+        if (e instanceof katex.ParseError) {
+            return JSON.stringify({ error: e.message, macros: flattenMacros(macros) });
+        } else {
+            throw e;
+        }
+    }
+}
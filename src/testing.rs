@@ -0,0 +1,76 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use crate::{Options, format_html, katex_renderer};
+
+// 下流のクレートのテストから使う。描画したHTMLをnormalizeしてから、テストしているクレートのtests/snapshots/{name}.htmlと比べる。
+// ファイルが無いときは書き出して通し、KATEX_GDEF_UPDATE_SNAPSHOTS=1のときは違っていても書き換えて通す
+#[track_caller]
+pub fn assert_render_snapshot(name: &str, latex: &str, options: &Options) {
+    let html = match katex_renderer().render_with_opts(latex, options, &mut BTreeMap::new()) {
+        Ok(html) => normalize(&html),
+        Err(error) => panic!("snapshot {name}: failed to render {latex:?}: {error}"),
+    };
+    let path = snapshot_path(name);
+    let update = std::env::var("KATEX_GDEF_UPDATE_SNAPSHOTS").is_ok_and(|value| !value.is_empty() && value != "0");
+    match std::fs::read_to_string(&path) {
+        Ok(expected) if expected.replace("\r\n", "\n") == html => {}
+        Ok(expected) if !update => panic!("snapshot {name} does not match {}:\n{}", path.display(), first_difference(&expected, &html)),
+        Err(error) if error.kind() != std::io::ErrorKind::NotFound => panic!("snapshot {name}: failed to read {}: {error}", path.display()),
+        _ => {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir).unwrap_or_else(|error| panic!("snapshot {name}: failed to create {}: {error}", dir.display()));
+            }
+            std::fs::write(&path, &html).unwrap_or_else(|error| panic!("snapshot {name}: failed to write {}: {error}", path.display()));
+        }
+    }
+}
+
+// 比べる前の形。format_htmlで字下げし、emの長さを小数点以下3桁に丸めて、浮動小数点の誤差程度の違いを無視する
+pub fn normalize(html: &str) -> String {
+    let formatted = format_html(html);
+    let mut normalized = String::with_capacity(formatted.len());
+    let mut rest = formatted.as_str();
+    while let Some(start) = rest.find(|c: char| c.is_ascii_digit()) {
+        normalized.push_str(&rest[..start]);
+        let end = rest[start..].find(|c: char| !c.is_ascii_digit() && c != '.').map_or(rest.len(), |end| start + end);
+        let number = &rest[start..end];
+        // 整数や、名前の一部の数字(size3やvlist-t2)、TeXの中の数はそのまま
+        match number.split_once('.') {
+            Some((_, fraction)) if fraction.len() > 3 && !fraction.contains('.') && rest[end..].starts_with("em") => {
+                let rounded = format!("{:.3}", number.parse::<f64>().unwrap_or_default());
+                normalized.push_str(rounded.trim_end_matches('0').trim_end_matches('.'));
+            }
+            _ => normalized.push_str(number),
+        }
+        rest = &rest[end..];
+    }
+    normalized.push_str(rest);
+    normalized
+}
+
+fn snapshot_path(name: &str) -> PathBuf {
+    let root = std::env::var_os("CARGO_MANIFEST_DIR").map_or_else(|| PathBuf::from("."), PathBuf::from);
+    root.join("tests").join("snapshots").join(format!("{name}.html"))
+}
+
+fn first_difference(expected: &str, actual: &str) -> String {
+    let (expected, actual): (Vec<&str>, Vec<&str>) = (expected.lines().collect(), actual.lines().collect());
+    let line = expected.iter().zip(&actual).position(|(expected, actual)| expected != actual).unwrap_or(expected.len().min(actual.len()));
+    format!(
+        "first difference at line {}\n- {}\n+ {}\n(set KATEX_GDEF_UPDATE_SNAPSHOTS=1 to accept the new output)",
+        line + 1,
+        expected.get(line).unwrap_or(&"<end of snapshot>"),
+        actual.get(line).unwrap_or(&"<end of output>")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_rounds_only_em_lengths() {
+        let html = r#"<span class="size3 vlist-t2" style="height:0.43056em;top:-2.5500000000000003em;width:1.2345px">10.12345</span>"#;
+        assert_eq!(normalize(html), "<span class=\"size3 vlist-t2\" style=\"height:0.431em;top:-2.55em;width:1.2345px\">10.12345</span>\n");
+    }
+}
@@ -0,0 +1,213 @@
+// KaTeXのMathMLを一行の文字列("x^2 + 1/2")に崩す。代替テキストや検索の抜粋、端末での表示に使う。
+// 記号はMathMLに入っているUnicodeのまま出し、添字と分数だけを^、_、/で書く
+pub fn mathml_to_text(mathml: &str) -> String {
+    let mut tokenizer = html5gum::Tokenizer::new(mathml);
+    let mut text = String::new();
+    for node in parse_children(None, &mut tokenizer) {
+        text.push_str(&linearize(&node));
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+enum Node {
+    Element { name: String, linethickness: Option<String>, accent: bool, children: Vec<Node> },
+    Text(String),
+}
+
+fn parse_children(element: Option<&[u8]>, tokens: &mut html5gum::Tokenizer<html5gum::StringReader>) -> Vec<Node> {
+    let mut children = Vec::new();
+    while let Some(Ok(token)) = tokens.next() {
+        match token {
+            html5gum::Token::EndTag(tag) if Some(tag.name.to_ascii_lowercase().as_slice()) == element => break,
+            html5gum::Token::String(s) => children.push(Node::Text(String::from_utf8_lossy(&s).into_owned())),
+            html5gum::Token::StartTag(tag) => {
+                let name = tag.name.to_ascii_lowercase();
+                let attribute = |name: &[u8]| tag.attributes.get(name).map(|value| String::from_utf8_lossy(value).into_owned());
+                let linethickness = attribute(b"linethickness");
+                let accent = attribute(b"accent").as_deref() == Some("true");
+                let children_of = if tag.self_closing { Vec::new() } else { parse_children(Some(&name), tokens) };
+                children.push(Node::Element {
+                    name: String::from_utf8_lossy(&name).into_owned(),
+                    linethickness,
+                    accent,
+                    children: children_of,
+                });
+            }
+            _ => (),
+        }
+    }
+    children
+}
+
+fn linearize(node: &Node) -> String {
+    let Node::Element { name, linethickness, accent, children } = node else {
+        let Node::Text(text) = node else { unreachable!() };
+        return text.clone();
+    };
+    let child =
+        |index: usize| children.iter().filter(|child| matches!(child, Node::Element { .. })).nth(index).map(linearize).unwrap_or_default();
+    match name.as_str() {
+        "annotation" | "annotation-xml" | "mphantom" => String::new(),
+        // TeXのannotationより前の、表示される方だけを読む
+        "semantics" => child(0),
+        "mi" | "mn" | "mtext" | "ms" => children.iter().map(linearize).collect::<String>().replace('\u{a0}', " "),
+        "mo" => operator(&children.iter().map(linearize).collect::<String>()),
+        "mspace" => " ".to_string(),
+        "msup" => script(&child(0), None, Some(&child(1))),
+        "msub" => script(&child(0), Some(&child(1)), None),
+        "msubsup" => script(&child(0), Some(&child(1)), Some(&child(2))),
+        "munder" => script(&child(0), Some(&child(1)), None),
+        "mover" if *accent => accented(&child(0), &child(1)),
+        "mover" => script(&child(0), None, Some(&child(1))),
+        "munderover" => script(&child(0), Some(&child(1)), Some(&child(2))),
+        // \binomは線の無い分数になる
+        "mfrac" if linethickness.as_deref().is_some_and(|thickness| thickness.trim_end_matches(char::is_alphabetic).parse() == Ok(0.0)) => {
+            format!("{}, {}", child(0).trim(), child(1).trim())
+        }
+        "mfrac" => format!("{}/{}", group(&child(0)), group(&child(1))),
+        "msqrt" => format!("√{}", group(&row(children))),
+        "mroot" => match child(1).trim() {
+            "3" => format!("∛{}", group(&child(0))),
+            "4" => format!("∜{}", group(&child(0))),
+            index => format!("{index}√{}", group(&child(0))),
+        },
+        "mtable" => {
+            format!("[{}]", children.iter().map(|row| cells(row).join(", ")).filter(|row| !row.is_empty()).collect::<Vec<_>>().join("; "))
+        }
+        _ => row(children),
+    }
+}
+
+// 並んだ要素を繋ぐ。先頭や演算子の直後の符号は詰め、演算子を挟まずに並んだ式は一文字同士(2xなど)でなければ空ける
+fn row(children: &[Node]) -> String {
+    let mut text = String::new();
+    let mut previous = None;
+    for child in children {
+        let Node::Element { name, children: tokens, .. } = child else {
+            text.push_str(&linearize(child));
+            continue;
+        };
+        let linearized = linearize(child);
+        let content = tokens.iter().map(linearize).collect::<String>();
+        let kind = match name.as_str() {
+            "mo" if ["(", "[", "{", "⟨", "⌊", "⌈"].contains(&content.as_str()) => Kind::Open,
+            "mo" if [")", "]", "}", "|", "‖", "⟩", "⌋", "⌉", "!", "′", "″"].contains(&content.as_str()) => Kind::Close,
+            "mo" => Kind::Operator,
+            "mn" => Kind::Glued,
+            "mi" if content.chars().count() == 1 => Kind::Glued,
+            _ => Kind::Other,
+        };
+        match (previous, kind) {
+            // 先頭や演算子の直後の-xなど
+            (None | Some(Kind::Open | Kind::Operator), Kind::Operator) => text.push_str(linearized.trim()),
+            (Some(Kind::Close | Kind::Other), Kind::Glued | Kind::Other) | (Some(Kind::Glued), Kind::Other) => {
+                text.push(' ');
+                text.push_str(&linearized);
+            }
+            _ => text.push_str(&linearized),
+        }
+        previous = Some(kind);
+    }
+    text
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Open,
+    Close,
+    Operator,
+    // 一文字の変数と数
+    Glued,
+    Other,
+}
+
+fn cells(row: &Node) -> Vec<String> {
+    let Node::Element { children, .. } = row else { return Vec::new() };
+    children.iter().filter(|cell| matches!(cell, Node::Element { .. })).map(|cell| linearize(cell).trim().to_string()).collect()
+}
+
+// 関係と二項演算の記号だけ前後を空け、括弧や句読点は詰める
+fn operator(text: &str) -> String {
+    match text {
+        // 関数の適用(\sin xの間)と見えない積
+        "\u{2061}" => " ".to_string(),
+        "\u{2062}" | "\u{2063}" => String::new(),
+        "," | ";" => format!("{text} "),
+        "(" | ")" | "[" | "]" | "{" | "}" | "|" | "‖" | "⟨" | "⟩" | "⌊" | "⌋" | "⌈" | "⌉" | "!" | "." | "′" | "″" | "/" => {
+            text.to_string()
+        }
+        text if text.chars().count() > 1 => format!("{text} "),
+        text => format!(" {text} "),
+    }
+}
+
+fn script(base: &str, sub: Option<&str>, sup: Option<&str>) -> String {
+    let mut text = base.trim().to_string();
+    if let Some(sub) = sub {
+        text.push('_');
+        text.push_str(&group(sub));
+    }
+    if let Some(sup) = sup {
+        // f′はf^′にしない
+        match sup.trim() {
+            sup @ ("′" | "″" | "‴") => text.push_str(sup),
+            sup => {
+                text.push('^');
+                text.push_str(&group(sup));
+            }
+        }
+    }
+    text
+}
+
+// 一文字の底には結合文字を付け、それ以外は^で書く
+fn accented(base: &str, accent: &str) -> String {
+    let base = base.trim();
+    let combining = match accent.trim() {
+        "^" | "ˆ" => '\u{302}',
+        "~" | "˜" => '\u{303}',
+        "ˉ" | "¯" | "‾" => '\u{305}',
+        "˙" => '\u{307}',
+        "¨" => '\u{308}',
+        "→" | "⃗" => '\u{20d7}',
+        _ => return format!("{}^{}", group(base), accent.trim()),
+    };
+    if base.chars().count() == 1 { format!("{base}{combining}") } else { format!("{}^{}", group(base), accent.trim()) }
+}
+
+// 一文字か一つの数でなければ括弧で囲む
+fn group(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let atomic = text.chars().all(|c| c.is_ascii_digit() || c == '.')
+        || text.chars().count() == 1
+        || text.starts_with('(') && text.ends_with(')') && text[1..text.len() - 1].find(['(', ')']).is_none();
+    if atomic { text } else { format!("({text})") }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn math(body: &str) -> String {
+        format!(r#"<math><semantics><mrow>{body}</mrow><annotation encoding="application/x-tex">tex</annotation></semantics></math>"#)
+    }
+
+    #[test]
+    fn scripts_and_fractions() {
+        assert_eq!(mathml_to_text(&math("<msup><mi>x</mi><mn>2</mn></msup><mo>+</mo><mfrac><mn>1</mn><mn>2</mn></mfrac>")), "x^2 + 1/2");
+        assert_eq!(mathml_to_text(&math("<msub><mi>a</mi><mrow><mi>i</mi><mo>+</mo><mn>1</mn></mrow></msub>")), "a_(i + 1)");
+        assert_eq!(mathml_to_text(&math(r#"<mfrac linethickness="0px"><mi>n</mi><mi>k</mi></mfrac>"#)), "n, k");
+    }
+
+    #[test]
+    fn roots_accents_and_tables() {
+        assert_eq!(mathml_to_text(&math("<msqrt><mi>x</mi></msqrt><mroot><mi>y</mi><mn>3</mn></mroot>")), "√x ∛y");
+        assert_eq!(mathml_to_text(&math(r#"<mover accent="true"><mi>x</mi><mo>^</mo></mover>"#)), "x\u{302}");
+        assert_eq!(
+            mathml_to_text(&math(
+                "<mtable><mtr><mtd><mn>1</mn></mtd><mtd><mn>0</mn></mtd></mtr><mtr><mtd><mn>0</mn></mtd><mtd><mn>1</mn></mtd></mtr></mtable>"
+            )),
+            "[1, 0; 0, 1]"
+        );
+    }
+}
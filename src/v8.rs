@@ -1,13 +1,25 @@
 use std::{
-    io::{Read as _, Write as _},
-    path::Path,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
 };
 
-use crate::{Core, Input, Output};
+use once_cell::sync::Lazy;
+use serde::de::DeserializeOwned;
+
+use crate::{Core, EngineOptions, Interrupt, cache};
 
-pub(crate) type Engine = deno_core::JsRuntime;
 pub type Error = V8Error;
 
+#[cfg(feature = "embedded-snapshot")]
+static EMBEDDED_SNAPSHOT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/katex.snapshot"));
+
+// V8はスナップショットを'staticで借りるので、読んだものはキャッシュのパスとヘッダーごとに一度だけリークさせて使い回す
+static SNAPSHOTS: Lazy<Mutex<HashMap<(PathBuf, String), &'static [u8]>>> = Lazy::new(Default::default);
+
 #[derive(Debug, thiserror::Error)]
 pub enum V8Error {
     #[error("Runtime Error: {0}")]
@@ -16,41 +28,126 @@ pub enum V8Error {
     Io(#[from] std::io::Error),
     #[error("JSON Error: {0}")]
     Json(#[from] serde_json::Error),
+    #[error("Out of memory")]
+    OutOfMemory,
+}
+
+// ヒープの上限に近づいたときに立てるフラグを、ランタイムと一緒に持つ
+pub(crate) struct Engine {
+    runtime: deno_core::JsRuntime,
+    heap_limit: Option<usize>,
+    out_of_memory: Arc<AtomicBool>,
+}
+
+impl Engine {
+    fn from_runtime(runtime: deno_core::JsRuntime, options: &EngineOptions) -> Engine {
+        let mut engine =
+            Engine { runtime, heap_limit: options.max_heap_mb.map(|mb| mb * 1024 * 1024), out_of_memory: Arc::new(AtomicBool::new(false)) };
+        engine.watch_heap();
+        engine
+    }
+    // 上限に近づいたら実行を打ち切る。打ち切るまでに使う分だけ上限を広げておき、execで元に戻す
+    fn watch_heap(&mut self) {
+        if self.heap_limit.is_none() {
+            return;
+        }
+        let handle = self.runtime.v8_isolate().thread_safe_handle();
+        let out_of_memory = self.out_of_memory.clone();
+        self.runtime.add_near_heap_limit_callback(move |current, _initial| {
+            out_of_memory.store(true, Ordering::SeqCst);
+            handle.terminate_execution();
+            current * 2
+        });
+    }
+}
+
+// InitOptions::configure_v8の関数。Debugを付けるために包む
+#[derive(Clone)]
+pub(crate) struct ConfigureV8(pub(crate) Arc<dyn Fn(&mut deno_core::RuntimeOptions) + Send + Sync>);
+impl std::fmt::Debug for ConfigureV8 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ConfigureV8")
+    }
+}
+
+// configure_v8は最後に呼ぶので、こちらで決めたものも書き換えられる
+fn runtime_options(options: &EngineOptions, snapshot: Option<&'static [u8]>) -> deno_core::RuntimeOptions {
+    let mut runtime = deno_core::RuntimeOptions::default();
+    if let Some(mb) = options.max_heap_mb {
+        runtime.create_params = Some(deno_core::v8::CreateParams::default().heap_limits(0, mb * 1024 * 1024));
+    }
+    runtime.startup_snapshot = snapshot;
+    if let Some(ConfigureV8(configure)) = &options.configure_v8 {
+        configure(&mut runtime);
+    }
+    runtime
 }
 
-impl Core for deno_core::JsRuntime {
+impl Core for Engine {
     type Error = V8Error;
-    fn new() -> Result<Self, Self::Error> {
-        let mut rtm = deno_core::JsRuntime::new(deno_core::RuntimeOptions::default());
-        rtm.execute_script("katex", crate::KATEX_CODE)?;
-        Ok(rtm)
-    }
-    fn new_with_snapshot(path: &Path) -> Result<Self, Self::Error> {
-        let Ok(snapshot) = get_snapshot(path) else { return Core::new() };
-        let mut options = deno_core::RuntimeOptions::default();
-        options.startup_snapshot = Some(snapshot);
-        return Ok(deno_core::JsRuntime::new(options));
-    }
-    fn exec(&mut self, code: Input) -> Result<Output, Self::Error> {
-        let result = self.execute_script("katex", format!("renderToStringAndMacros({})", serde_json::to_string(&code)?))?;
-        let scope = &mut self.handle_scope();
+    fn new(code: &str, options: &EngineOptions) -> Result<Self, Self::Error> {
+        let mut rtm = deno_core::JsRuntime::new(runtime_options(options, None));
+        rtm.execute_script("katex", code.to_string())?;
+        Ok(Engine::from_runtime(rtm, options))
+    }
+    #[cfg(feature = "embedded-snapshot")]
+    fn new_embedded(_code: &str, options: &EngineOptions) -> Result<Self, Self::Error> {
+        Ok(Engine::from_runtime(deno_core::JsRuntime::new(runtime_options(options, Some(EMBEDDED_SNAPSHOT))), options))
+    }
+    fn new_with_snapshot(code: &str, path: &Path, header: &str, options: &EngineOptions) -> Result<Self, Self::Error> {
+        let Ok(snapshot) = get_snapshot(code, path, header, options) else { return Core::new(code, options) };
+        return Ok(Engine::from_runtime(deno_core::JsRuntime::new(runtime_options(options, Some(snapshot))), options));
+    }
+    fn engine_id(_options: &EngineOptions) -> String {
+        format!("v8 {}", deno_core::v8::V8::get_version())
+    }
+    fn exec<O: DeserializeOwned>(&mut self, function: &str, input: &str) -> Result<O, Self::Error> {
+        let script = format!("{function}({input})");
+        let result = {
+            let _span = span!("katex.js", function);
+            self.runtime.execute_script("katex", script)
+        };
+        // 打ち切った描画のゴミを捨て、広げた上限を戻して次の描画に備える
+        if self.out_of_memory.swap(false, Ordering::SeqCst) {
+            let isolate = self.runtime.v8_isolate();
+            isolate.cancel_terminate_execution();
+            isolate.low_memory_notification();
+            if let Some(heap_limit) = self.heap_limit {
+                self.runtime.remove_near_heap_limit_callback(heap_limit);
+            }
+            self.watch_heap();
+            return Err(V8Error::OutOfMemory);
+        }
+        let result = result?;
+        let scope = &mut self.runtime.handle_scope();
         let local_result = deno_core::v8::Local::new(scope, result);
+        let _span = span!("katex.deserialize");
         Ok(serde_json::from_str(&local_result.to_rust_string_lossy(scope))?)
     }
+    fn interrupt_handle(&mut self) -> Interrupt {
+        let handle = self.runtime.v8_isolate().thread_safe_handle();
+        Box::new(move || {
+            handle.terminate_execution();
+        })
+    }
+    fn clear_interrupt(&mut self) {
+        self.runtime.v8_isolate().cancel_terminate_execution();
+    }
 }
 
-fn get_snapshot(cache: &Path) -> Result<&'static [u8], V8Error> {
-    if cache.exists() {
-        let mut file = std::fs::File::open(cache)?;
-        let mut bytecode = Vec::new();
-        file.read_to_end(&mut bytecode)?;
-        Ok(Box::leak(bytecode.into()))
-    } else {
-        let mut rtm = deno_core::JsRuntimeForSnapshot::new(deno_core::RuntimeOptions::default());
-        rtm.execute_script("katex", crate::KATEX_CODE)?;
-        let snapshot = rtm.snapshot();
-        let mut file = std::fs::File::create(cache)?;
-        file.write_all(&snapshot)?;
-        Ok(Box::leak(snapshot))
+// 拡張を足したときにスナップショットと起動時で揃うよう、スナップショットを作るときにもconfigure_v8を通す
+fn get_snapshot(code: &str, cache: &Path, header: &str, options: &EngineOptions) -> Result<&'static [u8], V8Error> {
+    let mut snapshots = SNAPSHOTS.lock().unwrap();
+    let key = (cache.to_path_buf(), header.to_string());
+    if let Some(&snapshot) = snapshots.get(&key) {
+        return Ok(snapshot);
     }
+    let snapshot = cache::read_or_create(cache, header, || -> Result<_, V8Error> {
+        let mut rtm = deno_core::JsRuntimeForSnapshot::new(runtime_options(options, None));
+        rtm.execute_script("katex", code.to_string())?;
+        Ok(rtm.snapshot().into_vec())
+    })?;
+    let snapshot: &'static [u8] = Box::leak(snapshot.into_boxed_slice());
+    snapshots.insert(key, snapshot);
+    Ok(snapshot)
 }
@@ -0,0 +1,85 @@
+use std::{cell::RefCell, path::Path};
+
+use js_sys::{Function, JSON};
+use serde::de::DeserializeOwned;
+use wasm_bindgen::{JsCast, JsValue};
+
+use crate::{Core, EngineOptions, Interrupt};
+
+pub type Error = WasmError;
+
+#[derive(Debug, thiserror::Error)]
+pub enum WasmError {
+    #[error("JavaScript Error: {0}")]
+    Js(String),
+    #[error("IO Error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("JSON Error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+impl From<JsValue> for WasmError {
+    fn from(value: JsValue) -> Self {
+        let message = value.as_string().or_else(|| value.dyn_ref::<js_sys::Error>().map(|error| String::from(error.message())));
+        WasmError::Js(message.unwrap_or_else(|| format!("{value:?}")))
+    }
+}
+
+// JsValueはSendではないので、ホストのJSで作った関数はスレッドローカルに置き、エンジンは番号だけを持つ
+thread_local! {
+    static DISPATCHERS: RefCell<Vec<Option<Function>>> = const { RefCell::new(Vec::new()) };
+}
+
+// ホストのJS環境(ブラウザやNode.js)でKaTeXを動かす。KaTeXとシムは関数のスコープの中で評価し、
+// 名前を受け取ってシムの関数を呼ぶ関数を返させる。katex自体はグローバルに置かれるので、
+// 別のKaTeXで作ったRendererがあると後から作った方に置き換わる
+pub(crate) struct Engine {
+    id: usize,
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        DISPATCHERS.with_borrow_mut(|dispatchers| dispatchers[self.id] = None);
+    }
+}
+
+impl Core for Engine {
+    type Error = WasmError;
+
+    fn new(code: &str, _options: &EngineOptions) -> Result<Self, Self::Error> {
+        let body = format!("{code}\nreturn function (name, input) {{ return eval(name)(input); }};");
+        let dispatcher: Function = Function::new_no_args(&body).call0(&JsValue::NULL)?.dyn_into()?;
+        let id = DISPATCHERS.with_borrow_mut(|dispatchers| {
+            dispatchers.push(Some(dispatcher));
+            dispatchers.len() - 1
+        });
+        Ok(Engine { id })
+    }
+
+    // ホストのJSにはスナップショットが無いので毎回評価する
+    fn new_with_snapshot(code: &str, _path: &Path, _header: &str, options: &EngineOptions) -> Result<Self, Self::Error> {
+        Engine::new(code, options)
+    }
+
+    fn engine_id(_options: &EngineOptions) -> String {
+        "wasm".to_string()
+    }
+
+    fn exec<O: DeserializeOwned>(&mut self, function: &str, input: &str) -> Result<O, Self::Error> {
+        let input = JSON::parse(input)?;
+        let dispatcher = DISPATCHERS.with_borrow(|dispatchers| dispatchers[self.id].clone()).expect("engine is alive");
+        let result = {
+            let _span = span!("katex.js", function);
+            dispatcher.call2(&JsValue::NULL, &JsValue::from_str(function), &input)?
+        };
+        let result = result.as_string().ok_or_else(|| WasmError::Js(format!("{function} did not return a string")))?;
+        let _span = span!("katex.deserialize");
+        Ok(serde_json::from_str(&result)?)
+    }
+
+    // ホストのJSは同じスレッドで同期的に動くので、打ち切ることはできない
+    fn interrupt_handle(&mut self) -> Interrupt {
+        Box::new(|| ())
+    }
+
+    fn clear_interrupt(&mut self) {}
+}